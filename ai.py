@@ -4,16 +4,25 @@ import time
 import os
 import atexit
 
+# The same variables the terminal app reads, so both ends agree on where the files are
+INPUT_FILE = os.environ.get("STEMMGPT_FILES_INPUT", "input.txt")
+OUTPUT_FILE = os.environ.get("STEMMGPT_FILES_OUTPUT", "output.txt")
+MEMORY_FILE = os.environ.get("STEMMGPT_FILES_MEMORY", "memory.txt")
+READY_FILE = os.environ.get("STEMMGPT_FILES_READY", "ready.txt")
+RESET_FILE = os.environ.get("STEMMGPT_FILES_RESET", "reset.txt")
+
 # Clean up any existing files
-if os.path.exists("input.txt"):
-    os.remove("input.txt")
-if os.path.exists("output.txt"):
-    os.remove("output.txt")
-if os.path.exists("memory.txt"):
-    os.remove("memory.txt")
+if os.path.exists(INPUT_FILE):
+    os.remove(INPUT_FILE)
+if os.path.exists(OUTPUT_FILE):
+    os.remove(OUTPUT_FILE)
+if os.path.exists(MEMORY_FILE):
+    os.remove(MEMORY_FILE)
+if os.path.exists(RESET_FILE):
+    os.remove(RESET_FILE)
 
 # Initialize memory with system prompt
-with open("memory.txt", "w", encoding="utf-8") as f:
+with open(MEMORY_FILE, "w", encoding="utf-8") as f:
     f.write("""System: You are STEMM GPT, an AI assistant specialized in STEM (Science, Technology, Engineering, Mathematics, and Medicine). You excel at:
 - Explaining complex scientific concepts in simple terms
 - Helping with mathematical problem-solving
@@ -37,36 +46,47 @@ pipe = pipeline(
 print("Model loaded!")
 
 # Create ready file to signal we're ready
-with open("ready.txt", "w") as f:
+with open(READY_FILE, "w") as f:
     f.write("ready")
 
 # Clean up ready file when shutting down
 def cleanup():
-    if os.path.exists("ready.txt"):
-        os.remove("ready.txt")
-    if os.path.exists("input.txt"):
-        os.remove("input.txt")
-    if os.path.exists("output.txt"):
-        os.remove("output.txt")
-    if os.path.exists("memory.txt"):
-        os.remove("memory.txt")
+    if os.path.exists(READY_FILE):
+        os.remove(READY_FILE)
+    if os.path.exists(INPUT_FILE):
+        os.remove(INPUT_FILE)
+    if os.path.exists(OUTPUT_FILE):
+        os.remove(OUTPUT_FILE)
+    if os.path.exists(MEMORY_FILE):
+        os.remove(MEMORY_FILE)
+    if os.path.exists(RESET_FILE):
+        os.remove(RESET_FILE)
 atexit.register(cleanup)
 
 messages = [
     {"role": "system", "content": "You are a helpful assistant."}
 ]
 
-def process_input():
+def read_input():
+    try:
+        with open(INPUT_FILE, "r", encoding="utf-8") as f:
+            return f.read()
+    except FileNotFoundError:
+        return None
+
+def process_input(prompt):
+    # The first line names the prompt, and goes back with the reply so the app
+    # can tell it from a late reply to a prompt it gave up on
+    prompt_id, _, user_input = prompt.partition("\n")
+    user_input = user_input.strip()
+    asked = len(messages)
     try:
-        # Read memory and input
+        # Read memory
         memory = ""
-        if os.path.exists("memory.txt"):
-            with open("memory.txt", "r", encoding="utf-8") as f:
+        if os.path.exists(MEMORY_FILE):
+            with open(MEMORY_FILE, "r", encoding="utf-8") as f:
                 memory = f.read().strip()
         
-        with open("input.txt", "r", encoding="utf-8") as f:
-            user_input = f.read().strip()
-        
         # Combine memory and new input
         full_input = memory + "\n" + user_input if memory else user_input
         
@@ -82,23 +102,37 @@ def process_input():
         )
         assistant_reply = outputs[0]["generated_text"][-1]["content"]
         messages.append({"role": "assistant", "content": assistant_reply})
-        
-        # Write response to file
-        with open("output.txt", "w", encoding="utf-8") as f:
-            f.write(assistant_reply)
+        reply = assistant_reply
             
     except Exception as e:
         print(f"Error processing input: {e}")
-        with open("output.txt", "w", encoding="utf-8") as f:
-            f.write(f"Error: {str(e)}")
+        reply = f"Error: {str(e)}"
+
+    # The app removes or replaces the prompt when it gives up waiting, so nobody wants this reply
+    if read_input() != prompt:
+        del messages[asked:]
+        print("Dropped a reply to an abandoned prompt")
+        return
+
+    # Write response to file, all at once so the app never reads half of it
+    with open(OUTPUT_FILE + ".tmp", "w", encoding="utf-8") as f:
+        f.write(prompt_id + "\n" + reply)
+    os.replace(OUTPUT_FILE + ".tmp", OUTPUT_FILE)
 
 def main():
     print("Waiting for input...")
     while True:
-        if os.path.exists("input.txt"):
-            process_input()
-            # Remove input file to signal we're done
-            os.remove("input.txt")
+        # /clear in the app: forget everything but the system message
+        if os.path.exists(RESET_FILE):
+            del messages[1:]
+            os.remove(RESET_FILE)
+            print("Conversation cleared")
+        prompt = read_input()
+        if prompt is not None:
+            process_input(prompt)
+            # Remove input file to signal we're done, unless the app has sent another prompt since
+            if read_input() == prompt:
+                os.remove(INPUT_FILE)
         time.sleep(0.1)  # Small delay to prevent CPU spinning
 
 if __name__ == "__main__":
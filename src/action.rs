@@ -0,0 +1,221 @@
+use std::path::PathBuf;
+
+use crate::layout::Preset;
+
+/// What to put on the clipboard.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CopyTarget {
+    /// A reply, counting back from the latest, which is 1.
+    Reply(usize),
+    /// A code block by the number shown beside it, or the latest one.
+    Code(Option<usize>),
+}
+
+/// Everything the user can ask the app to do, whether from a key, a slash command or the palette.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    // App
+    Edit,
+    StopEditing,
+    Quit,
+    Suspend,
+    OpenPalette,
+    ToggleLog,
+    Submit,
+    Help,
+    Clear,
+    Save(Option<PathBuf>),
+    /// Typewriter speed in characters per second, or `None` to show the current one.
+    Speed(Option<u32>),
+    TypeFaster,
+    TypeSlower,
+    SkipTyping,
+    ScrollUp,
+    ScrollDown,
+    /// Switch to the named theme, or `None` to list them.
+    Theme(Option<String>),
+    NextTheme,
+    /// Switch to a layout preset, or `None` to list them.
+    Layout(Option<Preset>),
+    NextLayout,
+    GrowInput,
+    ShrinkInput,
+    GrowAvatar,
+    ShrinkAvatar,
+    Copy(CopyTarget),
+
+    // Avatar
+    Panic,
+    Revive,
+    CycleVisualization,
+    ToggleAvatarStyle,
+
+    // Input box
+    InsertNewline,
+    Complete,
+    Backspace,
+    Delete,
+    MoveLeft,
+    MoveRight,
+    MoveUp,
+    MoveDown,
+    MoveHome,
+    MoveEnd,
+    MoveWordLeft,
+    MoveWordRight,
+    DeleteWordLeft,
+    DeleteWordRight,
+    KillToEnd,
+    KillToStart,
+    KillWordLeft,
+    Yank,
+    Undo,
+    Redo,
+    SearchHistory,
+    ExpandPastes,
+}
+
+use Action::*;
+
+/// Actions that need nothing more than a key press, in the order the palette lists them.
+pub const ALL: &[Action] = &[
+    Edit, StopEditing, Submit, Quit, Suspend, OpenPalette, ToggleLog, Help, Clear, Save(None), Speed(None), TypeFaster, TypeSlower, SkipTyping, ScrollUp, ScrollDown,
+    Theme(None), NextTheme, Layout(None), NextLayout, GrowInput, ShrinkInput, GrowAvatar, ShrinkAvatar,
+    Copy(CopyTarget::Reply(1)), Copy(CopyTarget::Code(None)),
+    Panic, Revive, CycleVisualization, ToggleAvatarStyle,
+    InsertNewline, Complete, Backspace, Delete,
+    MoveLeft, MoveRight, MoveUp, MoveDown, MoveHome, MoveEnd, MoveWordLeft, MoveWordRight,
+    DeleteWordLeft, DeleteWordRight, KillToEnd, KillToStart, KillWordLeft, Yank,
+    Undo, Redo, SearchHistory, ExpandPastes,
+];
+
+impl Action {
+    /// Whether the action works on the input box, so only makes sense while editing.
+    pub fn edits_input(&self) -> bool {
+        matches!(
+            self,
+            InsertNewline | Complete | Backspace | Delete | MoveLeft | MoveRight | MoveUp | MoveDown | MoveHome | MoveEnd
+                | MoveWordLeft | MoveWordRight | DeleteWordLeft | DeleteWordRight | KillToEnd | KillToStart | KillWordLeft
+                | Yank | Undo | Redo | SearchHistory | ExpandPastes
+        )
+    }
+
+    /// Stable identifier, e.g. `kill-to-end`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Edit => "edit",
+            StopEditing => "stop-editing",
+            Quit => "quit",
+            Suspend => "suspend",
+            OpenPalette => "open-palette",
+            ToggleLog => "toggle-log",
+            Submit => "submit",
+            Help => "help",
+            Clear => "clear",
+            Save(_) => "save",
+            Speed(_) => "speed",
+            TypeFaster => "type-faster",
+            TypeSlower => "type-slower",
+            SkipTyping => "skip-typing",
+            ScrollUp => "scroll-up",
+            ScrollDown => "scroll-down",
+            Theme(_) => "theme",
+            NextTheme => "next-theme",
+            Layout(_) => "layout",
+            NextLayout => "next-layout",
+            GrowInput => "grow-input",
+            ShrinkInput => "shrink-input",
+            GrowAvatar => "grow-avatar",
+            ShrinkAvatar => "shrink-avatar",
+            Copy(CopyTarget::Reply(_)) => "copy-reply",
+            Copy(CopyTarget::Code(_)) => "copy-code",
+            Panic => "panic",
+            Revive => "revive",
+            CycleVisualization => "cycle-visualization",
+            ToggleAvatarStyle => "toggle-avatar-style",
+            InsertNewline => "insert-newline",
+            Complete => "complete",
+            Backspace => "backspace",
+            Delete => "delete",
+            MoveLeft => "move-left",
+            MoveRight => "move-right",
+            MoveUp => "move-up",
+            MoveDown => "move-down",
+            MoveHome => "move-home",
+            MoveEnd => "move-end",
+            MoveWordLeft => "move-word-left",
+            MoveWordRight => "move-word-right",
+            DeleteWordLeft => "delete-word-left",
+            DeleteWordRight => "delete-word-right",
+            KillToEnd => "kill-to-end",
+            KillToStart => "kill-to-start",
+            KillWordLeft => "kill-word-left",
+            Yank => "yank",
+            Undo => "undo",
+            Redo => "redo",
+            SearchHistory => "search-history",
+            ExpandPastes => "expand-pastes",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            Edit => "Start typing in the input box",
+            StopEditing => "Leave the input box",
+            Quit => "Leave STEMM GPT",
+            Suspend => "Go back to the shell until fg brings the app back",
+            OpenPalette => "Search for an action to run",
+            ToggleLog => "Show or hide the latest log records",
+            Submit => "Send the prompt, or run it if it is a /command",
+            Help => "List the slash commands",
+            Clear => "Clear the conversation and start afresh",
+            Save(_) => "Save the conversation as Markdown",
+            Speed(_) => "Show how fast replies are typed out",
+            TypeFaster => "Type replies out faster",
+            TypeSlower => "Type replies out slower",
+            SkipTyping => "Show the rest of the reply at once",
+            ScrollUp => "Scroll the conversation back",
+            ScrollDown => "Scroll the conversation forward",
+            Theme(_) => "List the colour themes",
+            NextTheme => "Switch to the next colour theme",
+            Layout(_) => "List the pane layouts",
+            NextLayout => "Switch to the next pane layout",
+            GrowInput => "Make the input box bigger",
+            ShrinkInput => "Make the input box smaller",
+            GrowAvatar => "Make the avatar bigger",
+            ShrinkAvatar => "Make the avatar smaller",
+            Copy(CopyTarget::Reply(_)) => "Copy the last reply to the clipboard",
+            Copy(CopyTarget::Code(_)) => "Copy the last code block to the clipboard",
+            Panic => "Make the avatar panic",
+            Revive => "Bring the avatar back to life",
+            CycleVisualization => "Switch between waveform, spectrum and bars",
+            ToggleAvatarStyle => "Switch between the drawn face and the frame animation",
+            InsertNewline => "Start a new line in the prompt",
+            Complete => "Complete the slash command being typed",
+            Backspace => "Delete the character before the cursor",
+            Delete => "Delete the character under the cursor",
+            MoveLeft => "Move the cursor left",
+            MoveRight => "Move the cursor right",
+            MoveUp => "Move up a line, or back through history",
+            MoveDown => "Move down a line, or forward through history",
+            MoveHome => "Move to the start of the line",
+            MoveEnd => "Move to the end of the line",
+            MoveWordLeft => "Move to the previous word",
+            MoveWordRight => "Move to the next word",
+            DeleteWordLeft => "Delete the previous word",
+            DeleteWordRight => "Delete the next word",
+            KillToEnd => "Cut to the end of the line",
+            KillToStart => "Cut to the start of the line",
+            KillWordLeft => "Cut back to the previous space",
+            Yank => "Paste the last cut text",
+            Undo => "Undo the last edit",
+            Redo => "Redo the last undone edit",
+            SearchHistory => "Search previous prompts",
+            ExpandPastes => "Show large pastes in full",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        ALL.iter().find(|action| action.name() == name).cloned()
+    }
+}
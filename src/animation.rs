@@ -1,144 +1,296 @@
-use std::{time::Duration, usize::{MAX, MIN}};
+use std::{f32::consts::PI, time::Duration};
 
-use ratatui::{style::Style, text::Span, widgets::{Borders, Paragraph}, Frame};
-use rand::{rngs::ThreadRng, Rng};
-use chrono::Utc;
+use ratatui::{style::Style, widgets::{Borders, Paragraph}, Frame};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
 use include_dir::{include_dir, Dir};
-use ratatui::{layout::{Alignment, Rect}, style::{Color, Stylize}, text::{Line, Text}, widgets::Block};
-use rodio::{source::SineWave, OutputStream, Sink, Source};
+use ratatui::{layout::{Alignment, Rect}, text::{Line, Text}, widgets::Block};
+
+use crate::{
+    audio::{self, Speaker, Visualization, Voice},
+    clock::SharedClock,
+    config::AvatarSettings,
+    face::{self, FaceParams, Mood},
+    state::{State, StateMachine, Transition, Trigger},
+    theme::Theme,
+};
 
 static DYING_FRAMES_DIR: Dir = include_dir!("src/frames/dying");
 static IDLE_FRAMES_DIR: Dir = include_dir!("src/frames/idle");
 static BLINKING_FRAMES_DIR: Dir = include_dir!("src/frames/blinking");
 
+// Length of each chunk of synthesized voice.
+const TALKING_FRAME_INTERVAL: Duration = Duration::from_millis(100);
+const BLINK_FRAME_INTERVAL: Duration = Duration::from_millis(50);
+// How long the flatlining wave plays before the dying face shows up.
+const DYING_WAVE_DURATION: Duration = Duration::from_secs(16);
+const THINKING_DOT_INTERVAL: Duration = Duration::from_millis(400);
+// Time constant for the mouth catching up with the sound being made.
+const MOUTH_EASING: Duration = Duration::from_millis(40);
+// Below this many rows the talking face leaves the waveform out.
+const MIN_FACE_WITH_WAVE_HEIGHT: usize = 8;
+// Bounds for the random pause between two blinks, in milliseconds.
+const BLINK_PAUSE_MS: std::ops::Range<u64> = 800..2400;
+
 fn get_frames(dir: &'static Dir<'static>) -> Vec<&'static str> {
     dir.files()
         .filter_map(|f| f.contents_utf8())
         .collect()
 }
 
-#[derive(Debug)]
-pub enum State {
-    TALKING,
-    IDLE,
-    DYING,
-    BLINKING,
+/// Whether the face comes from the hand-drawn frames or is drawn from `FaceParams`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum AvatarStyle {
+    Frames,
+    Procedural,
 }
 
 pub struct Animation {
-    last_talking_frame: Vec<String>,
+    clock: SharedClock,
+    voice: Voice,
+    speaker: Option<Speaker>,
+    visualization: Visualization,
+    // Samples currently coming out of the speaker and when they started.
+    sound_chunk: Vec<f32>,
+    sound_chunk_at: Duration,
     dying_frames: Vec<&'static str>,
     blinking_frames: Vec<&'static str>,
     idle_frames: Vec<&'static str>,
-    last_time: i64,
-    state: State,
-    talking_frame_num: usize,
-    change_talking_frame: bool,
-    until_talking: i32,
-    blink_frame_num: usize,
-    rng: ThreadRng,
-    timer: u32,
+    last_frame_at: Duration,
+    next_blink_at: Duration,
+    state: StateMachine,
+    style: AvatarStyle,
+    face: FaceParams,
+    mouth_target: f32,
+    mood: Mood,
+    listening_gaze: (f32, f32),
+    rng: StdRng,
 }
 
 impl Animation {
-    pub fn new() -> Self {
-        Self {
-            last_talking_frame: vec!["".to_string()],
+    pub fn new(clock: SharedClock, settings: &AvatarSettings) -> Self {
+        let speaker = if settings.sound { Speaker::open() } else { None };
+        let mut animation = Self::with_speaker(clock, speaker);
+        animation.style = settings.style;
+        animation.visualization = settings.visualization;
+        animation
+    }
+
+    /// An animation that never opens the audio device, for headless use.
+    pub fn silent(clock: SharedClock) -> Self {
+        Self::with_speaker(clock, None)
+    }
+
+    fn with_speaker(clock: SharedClock, speaker: Option<Speaker>) -> Self {
+        let now = clock.now();
+        let mut animation = Self {
+            clock,
+            voice: Voice::new(),
+            speaker,
+            visualization: Visualization::Oscilloscope,
+            sound_chunk: Vec::new(),
+            sound_chunk_at: now,
             dying_frames: get_frames(&DYING_FRAMES_DIR),
             idle_frames: get_frames(&IDLE_FRAMES_DIR),
             blinking_frames: get_frames(&BLINKING_FRAMES_DIR),
-            last_time: Utc::now().timestamp_millis(),
-            state: State::IDLE,
-            talking_frame_num: 0,
-            change_talking_frame: false,
-            until_talking: 0,
-            blink_frame_num: 0,
-            rng: rand::thread_rng(),
-            timer: 1000,
-        }
+            last_frame_at: now,
+            next_blink_at: now,
+            state: StateMachine::new(now),
+            style: AvatarStyle::Procedural,
+            face: FaceParams::default(),
+            mouth_target: 0.0,
+            mood: Mood::Neutral,
+            listening_gaze: (0.0, 0.0),
+            rng: StdRng::from_entropy(),
+        };
+        animation.schedule_blink();
+        animation
     }
 
-    pub fn render_ascii_art_widget(&mut self, animation_area: Rect, frame: &mut Frame) {
-        let box_width = animation_area.width as usize;
-        let box_height = animation_area.height as usize;
+    pub fn render_ascii_art_widget(&mut self, animation_area: Rect, frame: &mut Frame, theme: &Theme) {
         let border_block =
             Block::default()
                 .borders(Borders::ALL)
-                .title("STEMM GPT...").fg(Color::Rgb(0, 255, 0));
+                .title("STEMM GPT...").style(self.style(theme));
 
         frame.render_widget(border_block.clone(), animation_area);
         let inner_animation_area = border_block.inner(animation_area);
+        let box_width = inner_animation_area.width as usize;
+        let box_height = inner_animation_area.height as usize;
+        let padded_frame = self.frame_text(box_width, box_height);
+
+        frame.render_widget(Paragraph::new(padded_frame)
+            .alignment(Alignment::Left),
+            inner_animation_area)
 
+    }
 
-        let padded_frame = match self.state {
-            State::TALKING => {
-                if Utc::now().timestamp_millis() - self.last_time > 100  {
-                    self.last_time = Utc::now().timestamp_millis();
-                    self.talking_frame_num += 1;
-                    self.change_talking_frame = true;
-                }
-                if self.change_talking_frame {
-                    self.last_talking_frame = self.create_sound_wave(box_width, box_height, self.talking_frame_num);
-                    self.change_talking_frame = false;
-                }
+    /// Advances the animation and returns the current frame padded to `box_width` x `box_height`.
+    pub fn frame_text(&mut self, box_width: usize, box_height: usize) -> Text<'static> {
+        let now = self.clock.now();
+        self.advance_face(now);
 
-                let text_lines: Vec<Line> = self.last_talking_frame.clone().into_iter()
-                    .map(|line| Line::from(Span::styled(line, Style::default().fg(Color::Rgb(0, 255, 0)))))
-                    .collect();
-                Text::from(text_lines)
-            },
-            State::IDLE => {
-                if self.rng.gen_range(0..100) == 0  {
-                    self.last_time = Utc::now().timestamp_millis();
-                    self.talking_frame_num += 1;
-                    self.set_state(State::BLINKING);
-                }
+        if self.state.state() == State::IDLE && now >= self.next_blink_at {
+            self.fire(Trigger::Blink);
+        }
+        let blink_frame = (now.saturating_sub(self.state.since()).as_millis() / BLINK_FRAME_INTERVAL.as_millis()) as usize;
+        if self.state.state() == State::BLINKING && blink_frame >= self.blinking_frames.len() {
+            self.fire(Trigger::BlinkDone);
+            self.schedule_blink();
+        }
 
-                let current_frame = self.idle_frames[0];
-                Text::from(Self::pad_ascii_frame(current_frame, box_width, box_height))
-            },
-            State::DYING => {
-                if self.timer > 0 {
-                    self.timer -= 1;
-                    if Utc::now().timestamp_millis() - self.last_time > 100  {
-                        self.last_time = Utc::now().timestamp_millis();
-                        self.talking_frame_num += 1;
-                        self.change_talking_frame = true;
-                    }
-                    if self.change_talking_frame {
-                        self.last_talking_frame = self.create_sound_wave(box_width, box_height, self.talking_frame_num);
-                        self.change_talking_frame = false;
-                    }
-
-                    let text_lines: Vec<Line> = self.last_talking_frame.clone().into_iter()
-                        .map(|line| Line::from(Span::styled(line, Style::default().fg(Color::Rgb(0, 255, 0)))))
-                        .collect();
-                    Text::from(text_lines)
+        match (self.style, self.state.state()) {
+            (_, State::TALKING) => self.talking_text(now, box_width, box_height),
+            (_, State::DYING) => {
+                if now.saturating_sub(self.state.since()) < DYING_WAVE_DURATION {
+                    self.talking_text(now, box_width, box_height)
                 } else {
                     let current_frame = self.dying_frames[0];
                     Text::from(Self::pad_ascii_frame(current_frame, box_width, box_height))
                 }
             },
+            (_, State::THINKING) => {
+                let dots = (now.saturating_sub(self.state.since()).as_millis() / THINKING_DOT_INTERVAL.as_millis()) % 4;
+                let caption = format!("thinking{:<3}", ".".repeat(dots as usize));
+                self.captioned_face(&caption, box_width, box_height)
+            },
+            (_, State::ERROR) => self.captioned_face("something went wrong", box_width, box_height),
+            (AvatarStyle::Procedural, _) => {
+                Text::from(face::draw(&self.face, box_width, box_height).join("\n"))
+            },
+            (AvatarStyle::Frames, State::BLINKING) => {
+                let current_frame = self.blinking_frames[blink_frame.min(self.blinking_frames.len() - 1)];
+                Text::from(Self::pad_ascii_frame(current_frame, box_width, box_height))
+            },
+            (AvatarStyle::Frames, _) => {
+                let current_frame = self.idle_frames[0];
+                Text::from(Self::pad_ascii_frame(current_frame, box_width, box_height))
+            },
+        }
+    }
+
+    // Works out where the procedural face should be looking, how open its eyes
+    // and mouth are, and eases the mouth towards its target.
+    fn advance_face(&mut self, now: Duration) {
+        let elapsed = now.saturating_sub(self.last_frame_at).as_secs_f32();
+        self.last_frame_at = now;
+        let in_state = now.saturating_sub(self.state.since());
+        let seconds = now.as_secs_f32();
+
+        let (gaze, eyelids, mouth_target, mood) = match self.state.state() {
+            State::IDLE => ((0.3 * (seconds * 0.5).sin(), 0.15 * (seconds * 0.37).sin()), 0.0, 0.0, self.mood),
             State::BLINKING => {
-                if Utc::now().timestamp_millis() - self.last_time > 50  {
-                    self.last_time = Utc::now().timestamp_millis();
-                    self.blink_frame_num += 1;
-                }
+                let blink_length = BLINK_FRAME_INTERVAL * self.blinking_frames.len() as u32;
+                let progress = (in_state.as_secs_f32() / blink_length.as_secs_f32()).min(1.0);
+                (self.face.gaze, (PI * progress).sin(), 0.0, self.mood)
+            },
+            State::LISTENING => (self.listening_gaze, 0.0, 0.0, Mood::Neutral),
+            State::THINKING => ((-0.6, -0.8), 0.2, 0.0, Mood::Neutral),
+            State::TALKING => ((0.0, 0.0), 0.0, self.mouth_target, self.mood),
+            State::ERROR => ((0.0, 0.2), 0.0, 0.0, Mood::Surprised),
+            State::DYING => ((0.0, 0.0), 1.0, 0.0, Mood::Sad),
+        };
 
-                if self.blink_frame_num >= self.blinking_frames.len() {
-                    self.blink_frame_num = 0;
-                    self.state = State::IDLE;
-                }
+        let ease = 1.0 - (-elapsed / MOUTH_EASING.as_secs_f32()).exp();
+        self.face.mouth_open += (mouth_target - self.face.mouth_open) * ease;
+        self.face.gaze = gaze;
+        self.face.eyelids = eyelids;
+        self.face.mood = mood;
+    }
 
-                let current_frame = self.blinking_frames[self.blink_frame_num];
-                Text::from(Self::pad_ascii_frame(current_frame, box_width, box_height))
+    /// The theme's error style while something has gone wrong, its avatar style otherwise.
+    pub fn style(&self, theme: &Theme) -> Style {
+        match self.state.state() {
+            State::ERROR => theme.error,
+            _ => theme.avatar,
+        }
+    }
+
+    // Idle face with one line of text centred underneath it.
+    fn captioned_face(&self, caption: &str, box_width: usize, box_height: usize) -> Text<'static> {
+        let face = match self.style {
+            AvatarStyle::Frames => self.idle_frames[0].to_string(),
+            AvatarStyle::Procedural => face::draw(&self.face, box_width, box_height.saturating_sub(2)).join("\n"),
+        };
+        let frame = format!("{}\n\n{}", face, caption);
+        let widest = frame.lines().map(|line| line.chars().count()).max().unwrap_or(0);
+        let centred: Vec<String> = frame.lines()
+            .map(|line| format!("{:^widest$}", line))
+            .collect();
+        Text::from(Self::pad_ascii_frame(&centred.join("\n"), box_width, box_height))
+    }
+
+    pub fn cycle_visualization(&mut self) {
+        self.visualization = self.visualization.next();
+    }
+
+    pub fn toggle_style(&mut self) {
+        self.style = match self.style {
+            AvatarStyle::Frames => AvatarStyle::Procedural,
+            AvatarStyle::Procedural => AvatarStyle::Frames,
+        };
+    }
+
+    pub fn set_style(&mut self, style: AvatarStyle) {
+        self.style = style;
+    }
+
+    /// Shapes the mouth for the character currently being spoken.
+    pub fn lip_sync(&mut self, c: char) {
+        self.mouth_target = face::mouth_openness(c);
+    }
+
+    pub fn set_mood(&mut self, mood: Mood) {
+        self.mood = mood;
+    }
+
+    /// Direction the face looks in while the user is typing, -1..1 on each axis.
+    pub fn look_towards(&mut self, gaze: (f32, f32)) {
+        self.listening_gaze = gaze;
+    }
+
+    // Synthesizes and plays the voice one chunk at a time, drawing whichever
+    // part of the current chunk should be audible right now.
+    fn talking_text(&mut self, now: Duration, box_width: usize, box_height: usize) -> Text<'static> {
+        let elapsed = now.saturating_sub(self.sound_chunk_at);
+        if self.sound_chunk.is_empty() || elapsed >= TALKING_FRAME_INTERVAL {
+            self.sound_chunk = self.voice.next_chunk(TALKING_FRAME_INTERVAL);
+            self.sound_chunk_at = now;
+            if let Some(speaker) = &self.speaker {
+                speaker.play(&self.sound_chunk);
+            }
+        }
+
+        let progress = now.saturating_sub(self.sound_chunk_at).as_secs_f32() / TALKING_FRAME_INTERVAL.as_secs_f32();
+        let window = audio::window_at(&self.sound_chunk, progress);
+        let lines = match (self.style, self.state.state()) {
+            // Lip-synced face with the waveform in a strip underneath.
+            (AvatarStyle::Procedural, State::TALKING) if box_height >= MIN_FACE_WITH_WAVE_HEIGHT => {
+                let wave_height = box_height / 4;
+                let mut lines = face::draw(&self.face, box_width, box_height - wave_height);
+                lines.extend(audio::visualize(self.visualization, window, box_width, wave_height));
+                lines
             },
+            _ => audio::visualize(self.visualization, window, box_width, box_height),
         };
 
-        frame.render_widget(Paragraph::new(padded_frame)
-            .alignment(Alignment::Left),
-            inner_animation_area)
+        let text_lines: Vec<Line> = lines.into_iter()
+            .map(Line::from)
+            .collect();
+        Text::from(text_lines)
+    }
 
+    /// Makes blinking and babbling repeat exactly from one run to the next.
+    pub fn seed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+        self.voice.seed(seed);
+        self.schedule_blink();
+    }
+
+    fn schedule_blink(&mut self) {
+        let pause = Duration::from_millis(self.rng.gen_range(BLINK_PAUSE_MS));
+        self.next_blink_at = self.clock.now() + pause;
     }
 
     fn pad_ascii_frame(frame: &str, target_width: usize, target_height: usize) -> String {
@@ -164,7 +316,7 @@ impl Animation {
         let mut padded_lines = Vec::new();
 
         // Add top padding
-        padded_lines.extend(std::iter::repeat(" ".repeat(target_width)).take(top_pad));
+        padded_lines.extend(std::iter::repeat_n(" ".repeat(target_width), top_pad));
 
         // Pad each line horizontally
         for line in &lines {
@@ -178,107 +330,27 @@ impl Animation {
         }
 
         // Add bottom padding
-        padded_lines.extend(std::iter::repeat(" ".repeat(target_width)).take(bottom_pad));
+        padded_lines.extend(std::iter::repeat_n(" ".repeat(target_width), bottom_pad));
 
         // Join everything into the final string
         padded_lines.join("\n")
     }
 
 
-    pub fn set_state(&mut self, state: State) {
-        match self.state {
-            State::DYING => {},
-            State::BLINKING => {},
-            _ => self.state = state,
-        }
+    /// Feeds `trigger` to the avatar's state machine; unknown transitions are ignored.
+    pub fn fire(&mut self, trigger: Trigger) -> bool {
+        let now = self.clock.now();
+        self.state.fire(trigger, now)
     }
 
-    fn create_sound_wave(&mut self, width: usize, height: usize, frame: usize) -> Vec<String> {
-        let center = height / 2;
-        let mut lines = Vec::new();
-
-        if self.until_talking > 0 {
-            for y in 0..height {
-                let mut line = String::new();
-                for _ in 0..width {
-                    let target_y = center as i32;
-                    let distance = (y as i32 - target_y).abs();
-                    let char = match distance {
-                        0 => '█',
-                        _ => ' ',
-                    };
-                    line.push(char);
-                }
-                lines.push(line);
-            }
-            self.until_talking -= 1;
-            if self.until_talking == 0 {
-                self.talking_frame_num = self.rng.gen_range(MIN/10000..MAX/10000);
-            }
-
-            return lines;
-        }
-
-        let center = height / 2;
-        let mut amplitudes = Vec::new();
-
-        // Control speech-like pauses and bursts
-        let is_silent: bool = self.rng.gen_bool(0.2);
-        let burst_strength: f32 = if is_silent {
-            self.until_talking = 3;
-            0.2
-        } else {
-            self.rng.gen_range(0.5..1.5)
-        };
-
-        for x in 0..width {
-            let t = (x as f32 + frame as f32 * 0.5) * 0.3;
-
-            let wave = (t.sin() * 0.5 + (t * 3.0).sin() * 0.3) * burst_strength;
-            let jitter = self.rng.gen_range(-0.2..0.2);
-            let base_amplitude = wave + jitter;
-
-            // Push amplitude (clamped to avoid overflow)
-            let amplitude = (base_amplitude * center as f32).clamp(-(center as f32), center as f32);
-            amplitudes.push(amplitude as i32);
-        }
-
-        let avg_amplitude: f32 = amplitudes.iter().map(|&x| x as f32).sum::<f32>() / amplitudes.len() as f32;
-        if avg_amplitude.abs() > 0.1 {
-            Self::play_sound(avg_amplitude);
-        }
-
-        // Render lines
-        for y in 0..height {
-            let mut line = String::new();
-            for x in 0..width {
-                let target_y = center as i32 + amplitudes[x];
-                let distance = (y as i32 - target_y).abs();
-                let char = match distance {
-                    0 => '█',
-                    1 => '▒',
-                    _ => ' ',
-                };
-                line.push(char);
-            }
-            lines.push(line);
-        }
-        lines
+    /// Jumps straight to `state` without going through the transition table.
+    pub fn show_state(&mut self, state: State) {
+        let now = self.clock.now();
+        self.state.reset_to(state, now);
     }
 
-    fn play_sound(amplitude: f32) {
-        std::thread::spawn(move || {
-            let (_stream, stream_handle) = OutputStream::try_default().unwrap();
-            let sink = Sink::try_new(&stream_handle).unwrap();
-
-            let freq = 220.0 + amplitude * 300.0;
-            let source = SineWave::new(freq)
-                .take_duration(Duration::from_millis(200))
-                .amplify(amplitude.clamp(0.1, 0.3));
-
-            sink.append(source);
-            sink.sleep_until_end();
-        });
+    pub fn drain_transitions(&mut self) -> impl Iterator<Item = Transition> + '_ {
+        self.state.drain_transitions()
     }
 
 }
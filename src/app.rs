@@ -1,278 +1,1101 @@
 use std::{
-    sync::LazyLock, time::{Duration, Instant}
+    fs, path::PathBuf, time::{Duration, Instant}
 };
-use std::io;
-use ratatui::{layout::Alignment, style::Style, widgets::{Borders, Paragraph, Wrap}, Frame};
+use ratatui::{layout::{Alignment, Constraint, Position, Rect}, style::Style, text::Line, widgets::{Borders, Paragraph}, Frame};
 use color_eyre::{eyre::Ok, Result};
 use crossterm::event::{
-    self, Event, KeyCode, KeyEventKind,
+    Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, MouseButton, MouseEvent, MouseEventKind,
 };
-use ratatui::layout::{Constraint, Layout};
-use ratatui::style::{Color, Stylize};
-use ratatui::text::Text;
-use ratatui::widgets::{Block, Widget};
+use ratatui::style::Stylize;
+use ratatui::text::{Span, Text};
+use ratatui::widgets::{Block, Clear};
 use ratatui::Terminal;
-use ratatui::backend::CrosstermBackend;
+use unicode_width::UnicodeWidthStr;
 
-use crate::{animation::{Animation, State}, typewriter::Typewriter};
+use crate::{
+    animation::Animation,
+    backend::{Backend, FileBackend},
+    clock::SharedClock,
+    action::{Action, CopyTarget},
+    clipboard,
+    commands::{self, Command},
+    config::{self, Config, LayoutSetting, UiSettings},
+    crash,
+    editor::Editor,
+    events::{EventSource, Interrupt},
+    face,
+    history::{self, History, HistorySearch},
+    keymap::{InputMode, Key, Keymap, Resolution},
+    layout::{self, Panes, Split},
+    log_view::LogView,
+    logging::Recent,
+    palette::Palette,
+    paste::{self, Pastes},
+    replay::{Recorder, Step},
+    state::{State, Trigger},
+    status::StatusBar,
+    theme::{self, Theme},
+    typewriter::{self, Role, Typewriter},
+    vim::{KeyMode, Vim},
+};
 
-static HEADER_TEXT: LazyLock<Text<'static>> = LazyLock::new(|| {
+fn header_text(theme: &Theme) -> Text<'static> {
     Text::from_iter([
-        "".fg(Color::Cyan),
-        "         🤖 STEMM GPT 🧬 ☪️    ".fg(Color::Green).bold(),
-        "     AI Assistant for STEM   ".fg(Color::Blue),
-        "".fg(Color::Cyan),
-        "".into(),
-        "Press 'e' to edit | 'q' to quit".fg(Color::Yellow).italic(),
+        Line::default(),
+        Line::styled("         🤖 STEMM GPT 🧬 ☪️    ", theme.title),
+        Line::styled("     AI Assistant for STEM   ", theme.subtitle),
+        Line::default(),
+        Line::default(),
+        Line::styled("Press 'e' to edit | 'q' to quit | /help for commands", theme.hint),
     ])
-});
-
-enum InputMode {
-    Normal,
-    Editing,
 }
 
 pub struct App {
     exit: bool,
-    /// Current value of the input box
-    input: String,
-    /// Position of cursor in the editor area.
-    character_index: usize,
+    /// Set by Ctrl+Z until the run loop hands the terminal back to the shell
+    suspend: bool,
+    /// Current value of the input box, including its cursor
+    editor: Editor,
+    /// Prompts submitted in this and earlier sessions
+    history: History,
+    /// Reverse incremental search, while one is running
+    search: Option<HistorySearch>,
+    /// Modal key handling for the input box, if turned on
+    vim: Option<Vim>,
+    /// Large pastes collapsed into placeholders in the input box
+    pastes: Pastes,
+    /// Highlighted entry in the slash command popup
+    completion: usize,
+    keymap: Keymap,
+    /// Keys pressed so far of a chord such as Ctrl+X Ctrl+S
+    chord: Vec<Key>,
+    /// The Ctrl+P command palette, while it is open
+    palette: Option<Palette>,
+    /// Records logged since the app started
+    log: Recent,
+    /// The log overlay, while it is open
+    log_view: Option<LogView>,
     /// Current input mode
     input_mode: InputMode,
     /// History of recorded messages
     typewriter: Typewriter,
     animation: Animation,
+    backend: Box<dyn Backend>,
+    /// Avatar state as last reported by the animation
+    avatar_state: State,
+    ui: UiSettings,
+    /// Where the panes were last drawn
+    panes: Panes,
+    /// The split whose border is being dragged with the mouse
+    dragging: Option<Split>,
+    /// Text being selected in the conversation with the mouse
+    selection: Option<Selection>,
+    /// Selected text waiting to go to the clipboard once drawing is done
+    copied: Option<String>,
+    /// Rows of the input box scrolled out of sight at the top
+    input_scroll: u16,
+    /// Whether to copy with local clipboard tools as well as OSC 52
+    clipboard_fallback: bool,
+    /// Where layout changes are saved
+    config_path: Option<PathBuf>,
+    /// Whether copying and `/save` reach outside the app, which a replay shouldn't
+    writes_out: bool,
+    theme: Theme,
+    status: StatusBar,
+    recorder: Option<Recorder>,
 }
 
 impl App {
-    pub fn new() -> Self {
+    pub fn with_clock(config: &Config, clock: SharedClock) -> Self {
         Self {
             exit: false,
-            input: String::new(),
+            suspend: false,
+            editor: Editor::new(),
+            history: History::load(history::default_path()),
+            search: None,
+            vim: (config.ui.keymode == KeyMode::Vim).then(Vim::new),
+            pastes: Pastes::new(),
+            completion: 0,
+            keymap: Keymap::default(),
+            chord: Vec::new(),
+            palette: None,
+            log: Recent::default(),
+            log_view: None,
             input_mode: InputMode::Editing,
-            character_index: 0,
-            typewriter: Typewriter::new(),
-            animation: Animation::new(),
+            recorder: None,
+            status: StatusBar::new(clock.clone(), config.session.clone().unwrap_or_default()),
+            typewriter: Typewriter::new(clock.clone(), &config.typewriter),
+            animation: Animation::new(clock, &config.avatar),
+            backend: Box::new(FileBackend::new(&config.files)),
+            avatar_state: State::IDLE,
+            ui: config.ui.clone(),
+            panes: Panes::default(),
+            dragging: None,
+            selection: None,
+            copied: None,
+            input_scroll: 0,
+            clipboard_fallback: config.clipboard.local_fallback,
+            writes_out: true,
+            config_path: config.path.clone(),
+            theme: Theme::default(),
         }
     }
 
-    pub fn run(mut self, mut terminal: Terminal<CrosstermBackend<io::Stdout>>) -> Result<()> {
-        let tick_rate = Duration::from_millis(16);
+    pub fn with_keymap(mut self, keymap: Keymap) -> Self {
+        self.keymap = keymap;
+        self
+    }
+
+    pub fn with_theme(mut self, theme: Theme) -> Self {
+        self.theme = theme;
+        self
+    }
+
+    /// Where the log viewer reads records from.
+    pub fn with_log(mut self, log: Recent) -> Self {
+        self.log = log;
+        self
+    }
+
+    pub fn with_backend(mut self, backend: Box<dyn Backend>) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    pub fn with_vim(mut self, enabled: bool) -> Self {
+        self.vim = enabled.then(Vim::new);
+        self
+    }
+
+    /// Whether the input box has vim keys.
+    pub fn vim(&self) -> bool {
+        self.vim.is_some()
+    }
+
+    /// Logs everything that happens from here on, to be replayed later.
+    pub fn with_recorder(mut self, recorder: Recorder) -> Self {
+        self.recorder = Some(recorder);
+        self
+    }
+
+    pub fn with_history(mut self, history: History) -> Self {
+        self.history = history;
+        self
+    }
+
+    /// Leaves the clipboard and the files `/save` writes alone, for replays.
+    pub fn without_writing_out(mut self) -> Self {
+        self.writes_out = false;
+        self
+    }
+
+    /// Seeds the avatar's randomness, so the same events always draw the same screens.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.animation.seed(seed);
+        self
+    }
+
+    pub fn run<B: ratatui::backend::Backend>(mut self, terminal: &mut Terminal<B>, events: &mut impl EventSource) -> Result<()> {
+        let tick_rate = Duration::from_millis(self.ui.tick_ms);
         let mut last_tick = Instant::now();
         while !self.exit {
-            match self.typewriter.update_typewriter()  {
-                Some(state) => self.animation.set_state(state),
-                None => {},
-            };
-
-            terminal.draw(|frame| self.render(frame))?;
+            self.draw(terminal)?;
             let timeout = tick_rate.saturating_sub(last_tick.elapsed());
-            if !event::poll(timeout)? {
-                last_tick = Instant::now();
-                continue;
+            match events.next(timeout)? {
+                Some(event) => self.handle_event(event),
+                None => last_tick = Instant::now(),
             }
-            match event::read()? {
-                Event::Mouse(_) => {},
-                Event::Key(key) => {
-                    match self.input_mode {
-                        InputMode::Normal => match key.code {
-                            KeyCode::Char('e') => {
-                                self.input_mode = InputMode::Editing;
-                            }
-                            KeyCode::Char('q') => {
-                                return Ok(());
-                            }
-                            _ => {}
-                        },
-                        InputMode::Editing if key.kind == KeyEventKind::Press => match key.code {
-                            KeyCode::PageUp => {
-                                self.typewriter.add_message(String::from("
-                                PANIC! PANIC!
-                                "));
-                                self.animation.set_state(State::DYING)
-                            },
-                            KeyCode::Enter => self.submit_message(),
-                            KeyCode::Char(to_insert) => self.enter_char(to_insert),
-                            KeyCode::Backspace => self.delete_char(),
-                            KeyCode::Left => self.move_cursor_left(),
-                            KeyCode::Right => self.move_cursor_right(),
-                            KeyCode::Esc => self.input_mode = InputMode::Normal,
-                            _ => {}
-                        },
-                        InputMode::Editing => {}
-                    }
+            match events.interrupt() {
+                Some(Interrupt::Quit) => {
+                    crash::note("quit on a signal");
+                    self.exit = true;
+                },
+                Some(Interrupt::Suspend) => self.suspend = true,
+                None => {},
+            }
+            if std::mem::take(&mut self.suspend) {
+                crash::note("suspended");
+                events.suspend()?;
+                terminal.clear()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Catches up with the backend and the clock, then draws the screen.
+    pub fn draw<B: ratatui::backend::Backend>(&mut self, terminal: &mut Terminal<B>) -> Result<()> {
+        self.record(Step::Draw);
+        self.poll_backend();
+        if let Some(trigger) = self.typewriter.update_typewriter() {
+            if trigger == Trigger::ReplyStarted {
+                if let Some(c) = self.typewriter.current_char() {
+                    self.animation.lip_sync(c);
                 }
+            }
+            self.animation.fire(trigger);
+        }
 
-                _ => {}
+        terminal.draw(|frame| self.render(frame))?;
+        if let Some(text) = self.copied.take().filter(|_| self.writes_out) {
+            if let std::result::Result::Err(err) = clipboard::copy(&text, self.clipboard_fallback) {
+                self.status.warn(format!("Could not copy the selection: {err}"));
             }
         }
+        for transition in self.animation.drain_transitions() {
+            self.avatar_state = transition.to;
+        }
         Ok(())
     }
 
-    fn render(&mut self, frame: &mut Frame) {
-        let base_area = frame.size();
+    pub fn handle_event(&mut self, event: Event) {
+        crash::note_event(&event);
+        if self.recorder.is_some() {
+            self.record(Step::Event(event.clone()));
+        }
+        match event {
+            Event::Mouse(mouse) => self.handle_mouse(mouse),
+            Event::Paste(text) => {
+                if let InputMode::Editing = self.input_mode {
+                    let text = self.pastes.add(paste::normalize(&text));
+                    // Pasting ends a search the way Esc does, back at what was being written
+                    if let Some(search) = self.search.take() {
+                        self.editor.set_text(&search.original);
+                    }
+                    self.edit(|editor| editor.insert_str(&text));
+                }
+            },
+            Event::Key(key) if key.kind == KeyEventKind::Press => self.handle_key(key),
+            _ => {}
+        }
+        self.pastes.follow(&self.editor.text());
+    }
 
-        // Create main layout with header and content
-        let main_layout = Layout::vertical([
-            Constraint::Length(HEADER_TEXT.height() as u16), // Header
-            Constraint::Min(0), // Content area
-        ]).split(base_area);
+    fn record(&mut self, step: Step) {
+        let Some(recorder) = self.recorder.as_mut() else {
+            return;
+        };
+        if let std::result::Result::Err(err) = recorder.record(&step) {
+            self.recorder = None;
+            self.status.warn(format!("Stopped recording: {err}"));
+        }
+    }
 
-        let [heading, content_area] = [main_layout[0], main_layout[1]];
+    /// Whether the user has asked to quit.
+    #[cfg(test)]
+    pub fn exited(&self) -> bool {
+        self.exit
+    }
 
-        // Split content area horizontally: left side for output, right side for input/animation
-        let horizontal_layout = Layout::horizontal([
-            Constraint::Percentage(50), // Left side - output canvas
-            Constraint::Percentage(50), // Right side - input and animation
-        ]).split(content_area);
+    /// Where the panes were last drawn.
+    #[cfg(test)]
+    pub fn panes(&self) -> &Panes {
+        &self.panes
+    }
 
-        let [animation_area, right_side] = [horizontal_layout[0], horizontal_layout[1]];
+    fn render(&mut self, frame: &mut Frame) {
+        let header_height = header_text(&self.theme).height() as u16;
+        let [screen, status] = ratatui::layout::Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).areas(frame.size());
+        self.panes = layout::split(screen, self.ui.layout, self.ui.avatar_size, self.ui.input_size, header_height);
+        let panes = self.panes.clone();
 
-        // Split right side vertically: top for input, bottom for animation
-        let right_vertical = Layout::vertical([
-            Constraint::Percentage(50), // Input canvas
-            Constraint::Percentage(50), // Animation
-        ]).split(right_side);
+        if let Some(header) = panes.header {
+            frame.render_widget(self.header(), header);
+        }
+        self.typewriter.render_output(panes.output, frame, &self.theme, self.input_mode == InputMode::Normal);
+        self.render_selection(frame, Block::bordered().inner(panes.output));
+        self.render_input(frame, panes.input);
+        if let Some(avatar) = panes.avatar {
+            // Have the avatar look at the input box while the user types
+            let towards_input = (
+                (panes.input.x + panes.input.width / 2) as f32 - (avatar.x + avatar.width / 2) as f32,
+                // Terminal cells are about twice as tall as they are wide
+                2.0 * ((panes.input.y + panes.input.height / 2) as f32 - (avatar.y + avatar.height / 2) as f32),
+            );
+            let reach = towards_input.0.abs().max(towards_input.1.abs()).max(1.0);
+            self.animation.look_towards((towards_input.0 / reach, towards_input.1 / reach));
+            self.animation.render_ascii_art_widget(avatar, frame, &self.theme);
+        }
+        self.render_completions(frame, panes.input);
+        if let Some(log_view) = self.log_view.as_mut() {
+            log_view.render(frame, screen, &self.log, &self.theme);
+        }
+        self.render_palette(frame);
+        let mode = match (&self.vim, self.input_mode) {
+            (Some(vim), InputMode::Editing) => format!("EDIT {}", vim.mode().label()),
+            (None, InputMode::Editing) => "EDIT".to_string(),
+            (_, InputMode::Normal) => "NORMAL".to_string(),
+        };
+        self.status.render(frame, status, &mode, self.backend.connection(), &self.theme);
+    }
 
-        let [input_area, left_side] = [right_vertical[0], right_vertical[1]];
+    // Commands matching what has been typed so far, while it looks like a slash command.
+    fn completions(&self) -> Vec<&'static Command> {
+        match self.input_mode {
+            InputMode::Editing => commands::completions(&self.editor.text()),
+            InputMode::Normal => Vec::new(),
+        }
+    }
 
-        // Render all widgets
-        frame.render_widget(self.header(), heading);
-        frame.render_widget(self.output_canvas(), left_side);
-        frame.render_widget(self.input_canvas(), input_area);
-        self.animation.render_ascii_art_widget(animation_area, frame);
+    // Whether a command name is half typed, in which case Enter completes it rather than running it.
+    fn completing_name(&self) -> bool {
+        let text = self.editor.text();
+        let completions = self.completions();
+        !text.contains(' ')
+            && !completions.is_empty()
+            && !completions.iter().any(|command| text == format!("/{}", command.name))
+    }
+
+    // Fills in the name of the highlighted command.
+    fn complete(&mut self) {
+        let completions = self.completions();
+        if let Some(command) = completions.get(self.completion.min(completions.len() - 1)) {
+            let text = format!("/{} ", command.name);
+            if !self.editor.text().starts_with(&text) {
+                self.edit(|editor| editor.set_text(&text));
+            }
+        }
+        self.completion = 0;
     }
 
-    fn output_canvas(&mut self) -> impl Widget + '_ {
-        self.typewriter.output_canvas()
+    // Lists matching commands just below the input box, over the output.
+    fn render_completions(&mut self, frame: &mut Frame, input_area: Rect) {
+        let completions = self.completions();
+        if completions.is_empty() {
+            self.completion = 0;
+            return;
+        }
+        self.completion = self.completion.min(completions.len() - 1);
+
+        // Below the input box, or above it when it is at the bottom of the screen
+        let below = frame.size().height.saturating_sub(input_area.bottom());
+        let wanted = completions.len() as u16 + 2;
+        let area = if below >= 3 {
+            Rect::new(input_area.x, input_area.bottom(), input_area.width, wanted.min(below))
+        } else {
+            let height = wanted.min(input_area.y);
+            Rect::new(input_area.x, input_area.y - height, input_area.width, height)
+        };
+        if area.height < 3 {
+            return;
+        }
+        let usage_width = completions.iter().map(|command| command.usage().len()).max().unwrap_or(0);
+        let lines: Vec<Line> = completions.iter().enumerate()
+            .map(|(index, command)| {
+                let line = Line::from(vec![
+                    format!(" {:<usage_width$}  ", command.usage()).bold(),
+                    command.help.into(),
+                ]);
+                if index == self.completion {
+                    line.patch_style(self.theme.selection)
+                } else {
+                    line
+                }
+            })
+            .collect();
+        let block = Block::bordered()
+            .title(" Commands (Tab to complete) ")
+            .style(self.theme.popup);
+        frame.render_widget(Clear, area);
+        frame.render_widget(Paragraph::new(lines).block(block), area);
     }
 
-    fn input_canvas(&mut self) -> impl Widget + '_ {
+    fn render_input(&mut self, frame: &mut Frame, input_area: Rect) {
+        let title = match (&self.search, self.avatar_state) {
+            (Some(search), _) if search.found.is_none() && !search.query.is_empty() => {
+                format!(" (failed reverse-i-search)`{}' ", search.query)
+            },
+            (Some(search), _) => format!(" (reverse-i-search)`{}' ", search.query),
+            (None, State::THINKING) => " Input (waiting for reply...) ".to_string(),
+            (None, State::ERROR) => " Input (last request failed) ".to_string(),
+            _ if !self.pastes.is_empty() && self.pastes.expand(&self.editor.text()) != self.editor.text() => {
+                " Input (Ctrl+O expands pastes) ".to_string()
+            },
+            _ => " Input ".to_string(),
+        };
+        let title = match (&self.vim, &self.input_mode) {
+            (Some(vim), InputMode::Editing) => format!("{title}-- {} -- ", vim.mode().label()),
+            _ => title,
+        };
+        let title = match self.chord.as_slice() {
+            [] => title,
+            chord => {
+                let keys: Vec<String> = chord.iter().map(Key::to_string).collect();
+                format!("{title}{} - ", keys.join(" "))
+            },
+        };
         let block = Block::bordered()
-            .title(" Input ")
+            .title(title)
             .title_alignment(Alignment::Left)
             .style(match self.input_mode {
-                InputMode::Normal => Style::default(),
-                InputMode::Editing => Style::default().fg(Color::Yellow),
+                InputMode::Normal => self.theme.border,
+                InputMode::Editing => self.theme.accent,
             });
-        Paragraph::new(self.input.as_str())
-            .block(block)
-            .wrap(Wrap { trim: false })
+        let inner = block.inner(input_area);
+
+        // While searching, preview the match in place of the input
+        let mut preview = None;
+        if let Some(entry) = self.search.as_ref().and_then(|search| search.found).and_then(|index| self.history.get(index)) {
+            let mut editor = Editor::new();
+            editor.set_text(entry);
+            preview = Some(editor);
+        }
+
+        // Scroll just far enough to keep the cursor inside the box
+        let layout = preview.as_ref().unwrap_or(&self.editor).layout(inner.width);
+        let (cursor_x, cursor_y) = layout.cursor;
+        let scroll = cursor_y.saturating_sub(inner.height.saturating_sub(1));
+        self.input_scroll = scroll;
+        let selection = self.vim.as_ref().and_then(|vim| vim.selection(&self.editor)).filter(|_| preview.is_none());
+        let text: Vec<Line> = layout.lines.into_iter()
+            .zip(layout.starts)
+            .map(|(line, start)| match selection {
+                Some(selection) => highlight(line, start, selection, self.theme.selection),
+                None => Line::from(line),
+            })
+            .collect();
+
+        frame.render_widget(Paragraph::new(text).block(block).scroll((scroll, 0)), input_area);
+        if let InputMode::Editing = self.input_mode {
+            if inner.width > 0 && inner.height > 0 {
+                frame.set_cursor(inner.x + cursor_x.min(inner.width - 1), inner.y + cursor_y - scroll);
+            }
+        }
     }
 
-    fn move_cursor_left(&mut self) {
-        let cursor_moved_left = self.character_index.saturating_sub(1);
-        self.character_index = self.clamp_cursor(cursor_moved_left);
+    fn handle_key(&mut self, key: KeyEvent) {
+        if self.palette.is_some() {
+            return self.handle_palette_key(key);
+        }
+        let editing = self.input_mode == InputMode::Editing;
+        if !editing && self.handle_log_key(key) {
+            return;
+        }
+        if editing && self.handle_search_key(key) {
+            return;
+        }
+        if let Some(vim) = self.vim.as_mut().filter(|_| editing) {
+            if vim.handle_key(key, &mut self.editor) {
+                return self.update_listening();
+            }
+        }
+
+        self.chord.push(Key::from(key));
+        match self.keymap.resolve(self.input_mode, &self.chord) {
+            Resolution::Action(action) => {
+                let action = action.clone();
+                self.chord.clear();
+                self.perform(action);
+            },
+            Resolution::Prefix => {},
+            // An unfinished chord is dropped; a single unbound key may still be typed
+            Resolution::Unbound if self.chord.len() > 1 => self.chord.clear(),
+            Resolution::Unbound => {
+                self.chord.clear();
+                if let Some(c) = typed_char(&key).filter(|_| editing) {
+                    self.edit(|editor| editor.insert_char(c));
+                }
+            },
+        }
     }
 
-    fn move_cursor_right(&mut self) {
-        let cursor_moved_right = self.character_index.saturating_add(1);
-        self.character_index = self.clamp_cursor(cursor_moved_right);
+    // Dragging a border between panes resizes them, dragging over the conversation
+    // selects text to copy, and clicking a pane moves the focus there.
+    fn handle_mouse(&mut self, mouse: MouseEvent) {
+        if self.palette.is_some() {
+            return;
+        }
+        let (column, row) = (mouse.column, mouse.row);
+        let position = Position { x: column, y: row };
+        let output = Block::bordered().inner(self.panes.output);
+        match mouse.kind {
+            MouseEventKind::Down(MouseButton::Left) => {
+                self.selection = None;
+                self.dragging = self.panes.dividers.iter()
+                    .find(|divider| divider.contains(column, row))
+                    .map(|divider| divider.split);
+                if self.dragging.is_some() {
+                    return;
+                }
+                if self.panes.input.contains(position) {
+                    if self.input_mode == InputMode::Normal {
+                        self.perform(Action::Edit);
+                    }
+                    self.place_cursor(column, row);
+                } else if self.panes.output.contains(position) || self.panes.avatar.is_some_and(|avatar| avatar.contains(position)) {
+                    if self.input_mode == InputMode::Editing {
+                        self.perform(Action::StopEditing);
+                    }
+                    if output.contains(position) {
+                        self.selection = Some(Selection { anchor: (column, row), cursor: (column, row), copy: false, shown: None });
+                    }
+                }
+            },
+            MouseEventKind::Drag(MouseButton::Left) => {
+                if let Some(split) = self.dragging {
+                    if let Some(divider) = self.panes.dividers.iter().find(|divider| divider.split == split) {
+                        let size = divider.size_at(column, row);
+                        match split {
+                            Split::Avatar => self.ui.avatar_size = size,
+                            Split::Input => self.ui.input_size = size,
+                        }
+                    }
+                } else if let Some(selection) = self.selection.as_mut() {
+                    selection.cursor = (
+                        column.clamp(output.x, output.right().saturating_sub(1)),
+                        row.clamp(output.y, output.bottom().saturating_sub(1)),
+                    );
+                }
+            },
+            MouseEventKind::Up(MouseButton::Left) => {
+                if let Some(split) = self.dragging.take() {
+                    self.save_layout(LayoutSetting::Size(split));
+                }
+                match self.selection.as_mut() {
+                    // A click without a drag selects nothing
+                    Some(selection) if selection.anchor == selection.cursor => self.selection = None,
+                    Some(selection) => selection.copy = true,
+                    None => {},
+                }
+            },
+            MouseEventKind::ScrollUp => {
+                self.selection = None;
+                self.typewriter.scroll_by(3);
+            },
+            MouseEventKind::ScrollDown => {
+                self.selection = None;
+                self.typewriter.scroll_by(-3);
+            },
+            _ => {},
+        }
     }
 
-    fn enter_char(&mut self, new_char: char) {
-        let index = self.byte_index();
-        self.input.insert(index, new_char);
-        self.move_cursor_right();
+    // Moves the cursor to the character under a click in the input box.
+    fn place_cursor(&mut self, column: u16, row: u16) {
+        let inner = Block::bordered().inner(self.panes.input);
+        if self.search.is_some() || !inner.contains(Position { x: column, y: row }) {
+            return;
+        }
+        let layout = self.editor.layout(inner.width);
+        let offset = layout.offset_at(column - inner.x, row - inner.y + self.input_scroll);
+        self.editor.set_cursor_offset(offset);
     }
 
-    fn byte_index(&self) -> usize {
-        self.input
-            .char_indices()
-            .map(|(i, _)| i)
-            .nth(self.character_index)
-            .unwrap_or(self.input.len())
+    // Highlights the selected part of the conversation, and picks up its text to
+    // copy once the mouse button has been let go. The selection is dropped as
+    // soon as the conversation shown changes under it.
+    fn render_selection(&mut self, frame: &mut Frame, area: Rect) {
+        let Some(selection) = self.selection.as_mut() else {
+            return;
+        };
+        if area.is_empty() {
+            return;
+        }
+        let buffer = frame.buffer_mut();
+        let shown: Vec<String> = (area.top()..area.bottom())
+            .map(|y| (area.left()..area.right()).map(|x| buffer.get(x, y).symbol()).collect())
+            .collect();
+        if *selection.shown.get_or_insert_with(|| shown.clone()) != shown {
+            self.selection = None;
+            return;
+        }
+        // In reading order, whichever way the mouse was dragged
+        let (start, end) = match (selection.anchor.1, selection.anchor.0) <= (selection.cursor.1, selection.cursor.0) {
+            true => (selection.anchor, selection.cursor),
+            false => (selection.cursor, selection.anchor),
+        };
+        let mut lines = Vec::new();
+        for y in start.1.max(area.y)..=end.1.min(area.bottom() - 1) {
+            let from = if y == start.1 { start.0 } else { area.x };
+            let to = if y == end.1 { end.0 } else { area.right() - 1 };
+            let mut line = String::new();
+            let mut covered = 0;
+            for x in from.max(area.x)..=to.min(area.right() - 1) {
+                let cell = buffer.get_mut(x, y);
+                cell.set_style(self.theme.selection);
+                // Cells behind a wide character hold nothing of their own
+                if covered > 0 {
+                    covered -= 1;
+                    continue;
+                }
+                line.push_str(cell.symbol());
+                covered = cell.symbol().width().saturating_sub(1);
+            }
+            lines.push(typewriter::without_code_label(line.trim_end()).to_string());
+        }
+        if std::mem::take(&mut selection.copy) {
+            self.copied = Some(lines.join("\n"));
+        }
     }
 
-    fn clamp_cursor(&self, new_cursor_pos: usize) -> usize {
-        new_cursor_pos.clamp(0, self.input.chars().count())
+    // Resizes a split by `step` percent and remembers the new size.
+    fn resize(&mut self, split: Split, step: i16) {
+        let size = match split {
+            Split::Avatar => &mut self.ui.avatar_size,
+            Split::Input => &mut self.ui.input_size,
+        };
+        *size = size.saturating_add_signed(step).clamp(layout::MIN_SIZE, layout::MAX_SIZE);
+        self.save_layout(LayoutSetting::Size(split));
     }
 
-    fn delete_char(&mut self) {
-        let is_not_cursor_leftmost = self.character_index != 0;
-        if is_not_cursor_leftmost {
-            // Method "remove" is not used on the saved text for deleting the selected char.
-            // Reason: Using remove on String works on bytes instead of the chars.
-            // Using remove would require special care because of char boundaries.
+    // Copies a reply or a code block from the conversation, saying where it went.
+    fn copy(&mut self, target: CopyTarget) {
+        let replies: Vec<&str> = self.typewriter.messages().iter()
+            .filter(|message| message.role == Role::Assistant)
+            .map(|message| message.text.as_str())
+            .collect();
+        let picked = match target {
+            CopyTarget::Reply(back) => match replies.len().checked_sub(back) {
+                Some(index) => std::result::Result::Ok((replies[index].to_string(), "the reply".to_string())),
+                None if replies.is_empty() => std::result::Result::Err("There is no reply to copy yet".to_string()),
+                None => std::result::Result::Err(format!("There are only {} replies", replies.len())),
+            },
+            CopyTarget::Code(number) => {
+                let blocks: Vec<String> = replies.iter()
+                    .flat_map(|reply| clipboard::code_blocks(reply))
+                    .map(|block| block.code)
+                    .collect();
+                let number = number.unwrap_or(blocks.len());
+                match blocks.get(number.wrapping_sub(1)) {
+                    Some(code) => std::result::Result::Ok((code.clone(), format!("code block {number}"))),
+                    None if blocks.is_empty() => std::result::Result::Err("There is no code block to copy yet".to_string()),
+                    None => std::result::Result::Err(format!("There is no code block {number}, the last is {}", blocks.len())),
+                }
+            },
+        };
+        match picked {
+            std::result::Result::Ok((_, what)) if !self.writes_out => self.status.notify(format!("Not copying {what} while replaying")),
+            std::result::Result::Ok((text, what)) => match clipboard::copy(&text, self.clipboard_fallback) {
+                std::result::Result::Ok(place) => self.status.notify(format!("Copied {what} to {place}")),
+                std::result::Result::Err(err) => self.status.warn(format!("Could not copy {what}: {err}")),
+            },
+            std::result::Result::Err(err) => self.status.warn(err),
+        }
+    }
 
-            let current_index = self.character_index;
-            let from_left_to_current_index = current_index - 1;
+    fn save_layout(&mut self, changed: LayoutSetting) {
+        let Some(path) = &self.config_path else {
+            return;
+        };
+        if let std::result::Result::Err(err) = config::save_layout(path, &self.ui, changed) {
+            self.status.warn(format!("Could not save the layout: {err}"));
+        }
+    }
 
-            // Getting all characters before the selected character.
-            let before_char_to_delete = self.input.chars().take(from_left_to_current_index);
-            // Getting all characters after selected character.
-            let after_char_to_delete = self.input.chars().skip(current_index);
+    fn handle_palette_key(&mut self, key: KeyEvent) {
+        let Some(palette) = self.palette.as_mut() else {
+            return;
+        };
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        if let Some(c) = typed_char(&key) {
+            return palette.type_char(c);
+        }
+        match key.code {
+            KeyCode::Esc => self.palette = None,
+            KeyCode::Enter => {
+                let action = palette.selected_action();
+                self.palette = None;
+                if let Some(action) = action {
+                    self.perform(action);
+                }
+            },
+            KeyCode::Up => palette.select_previous(),
+            KeyCode::Char('p') if ctrl => palette.select_previous(),
+            KeyCode::Down => palette.select_next(),
+            KeyCode::Char('n') if ctrl => palette.select_next(),
+            KeyCode::Backspace => palette.backspace(),
+            _ => {},
+        }
+    }
 
-            // Put all characters together except the selected one.
-            // By leaving the selected one out, it is forgotten and therefore deleted.
-            self.input = before_char_to_delete.chain(after_char_to_delete).collect();
-            self.move_cursor_left();
+    // Keys for the log overlay while it is open in Normal mode, so typing isn't
+    // taken for them. Returns false for keys it leaves alone.
+    fn handle_log_key(&mut self, key: KeyEvent) -> bool {
+        let Some(log_view) = self.log_view.as_mut() else {
+            return false;
+        };
+        match key.code {
+            KeyCode::Esc | KeyCode::Char('L') => self.log_view = None,
+            KeyCode::Char('v') => log_view.next_level(),
+            KeyCode::Char('m') => log_view.next_module(&self.log),
+            KeyCode::Up => log_view.scroll_up(1),
+            KeyCode::Down => log_view.scroll_down(1),
+            KeyCode::PageUp => log_view.scroll_up(10),
+            KeyCode::PageDown => log_view.scroll_down(10),
+            _ => return false,
         }
+        true
     }
 
-    fn submit_message(&mut self) {
-        use std::fs;
-        use std::io::Write;
-        use std::time::Duration;
-        use std::thread;
+    fn render_palette(&self, frame: &mut Frame) {
+        let Some(palette) = &self.palette else {
+            return;
+        };
+        let screen = frame.size();
+        let width = (screen.width * 3 / 4).clamp(20.min(screen.width), 90.min(screen.width));
+        let height = (screen.height * 2 / 3).max(5.min(screen.height));
+        let area = Rect::new(screen.x + (screen.width - width) / 2, screen.y + (screen.height - height) / 4, width, height);
 
-        // 1. Write input to file and append to memory
-        if let std::result::Result::Ok(mut file) = fs::File::create("input.txt") {
-            let _ = file.write_all(self.input.as_bytes());
+        let matches = palette.matches();
+        let visible = (height as usize).saturating_sub(3);
+        let first = palette.selected().saturating_sub(visible.saturating_sub(1));
+        let mut lines = vec![Line::from(vec!["> ".bold(), palette.query.clone().into()])];
+        for (index, action) in matches.iter().enumerate().skip(first).take(visible) {
+            let key = self.keymap.key_for(action, self.input_mode).unwrap_or_default();
+            let line = Line::from(vec![
+                format!(" {:<20} ", action.name()).bold(),
+                Span::styled(format!("{:<12} ", key), self.theme.key),
+                action.description().into(),
+            ]);
+            lines.push(if index == palette.selected() { line.patch_style(self.theme.selection) } else { line });
         }
-        // Append user input to memory
-        if let std::result::Result::Ok(mut file) = fs::OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open("memory.txt")
-        {
-            let _ = writeln!(file, "User: {}", self.input);
+        if matches.is_empty() {
+            lines.push(Line::from(" No matching actions".italic()));
         }
 
-        // 2. Wait for output file to be created
-        while fs::metadata("output.txt").is_err() {
-            thread::sleep(Duration::from_millis(100));
-        }
+        let block = Block::bordered()
+            .title(" Command palette (Enter to run, Esc to close) ")
+            .style(self.theme.popup);
+        frame.render_widget(Clear, area);
+        frame.render_widget(Paragraph::new(lines).block(block), area);
+        let end = (area.x as usize + 3 + palette.query.width()).min(area.right().saturating_sub(2) as usize);
+        frame.set_cursor(end as u16, area.y + 1);
+    }
 
-        // 3. Read output from file
-        let ai_reply = match fs::read_to_string("output.txt") {
-            std::result::Result::Ok(contents) => contents,
-            std::result::Result::Err(_) => "Error reading AI response".to_string(),
+    // Keys typed while a Ctrl+R search is running edit the query rather than the input.
+    // Returns false for keys that should still be handled as ordinary editing keys.
+    fn handle_search_key(&mut self, key: KeyEvent) -> bool {
+        let Some(search) = self.search.as_mut() else {
+            return false;
         };
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Char('r') if ctrl => search.older(&self.history),
+            KeyCode::Char('g') if ctrl => {
+                let original = std::mem::take(&mut search.original);
+                self.editor.set_text(&original);
+                self.search = None;
+            },
+            KeyCode::Esc => {
+                let original = std::mem::take(&mut search.original);
+                self.editor.set_text(&original);
+                self.search = None;
+            },
+            KeyCode::Char(c) if !ctrl => {
+                search.query.push(c);
+                search.refresh(&self.history);
+            },
+            KeyCode::Backspace => {
+                search.query.pop();
+                search.refresh(&self.history);
+            },
+            // Anything else accepts the match and is then handled as usual, except
+            // Enter, which only accepts it so the prompt can be looked over before sending
+            _ => {
+                if let Some(entry) = search.found.and_then(|index| self.history.get(index)) {
+                    self.editor.set_text(entry);
+                }
+                self.search = None;
+                self.history.reset_navigation();
+                return key.code == KeyCode::Enter;
+            },
+        }
+        true
+    }
 
-        // 4. Append AI response to memory
-        if let std::result::Result::Ok(mut file) = fs::OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open("memory.txt")
-        {
-            let _ = writeln!(file, "AI: {}", ai_reply);
+    // Applies an edit to the input and lets the avatar know whether the user is typing.
+    fn edit(&mut self, edit: impl FnOnce(&mut Editor)) {
+        edit(&mut self.editor);
+        self.update_listening();
+    }
+
+    // The avatar listens while there is something in the input box.
+    fn update_listening(&mut self) {
+        if self.editor.is_empty() {
+            self.animation.fire(Trigger::StoppedTyping);
+        } else {
+            self.animation.fire(Trigger::Typing);
         }
+    }
 
-        // 5. Clean up output file
-        let _ = fs::remove_file("output.txt");
+    fn submit_message(&mut self) {
+        let input = self.pastes.expand(&self.editor.text());
+        if let Some(command) = commands::parse(&input) {
+            self.history.push(&input);
+            self.pastes.clear();
+            self.edit(Editor::clear);
+            match command {
+                std::result::Result::Ok(action) => self.perform(action),
+                std::result::Result::Err(err) => self.typewriter.show_message(Role::System, err),
+            }
+            return;
+        }
+        if self.backend.is_pending() {
+            return;
+        }
 
-        self.typewriter.add_message(ai_reply);
-        self.input.clear();
-        self.reset_cursor();
+        let prompt = commands::prompt(&input);
+        match self.backend.send(&prompt) {
+            std::result::Result::Ok(()) => {
+                self.pastes.clear();
+                self.history.push(&prompt);
+                self.editor.clear();
+                let context = self.backend.context_len();
+                crash::note(format!("sent a prompt of {} characters", prompt.chars().count()));
+                self.record(Step::Sent(context));
+                self.status.sent(context);
+                self.typewriter.show_message(Role::User, prompt);
+                self.animation.fire(Trigger::RequestSent);
+            },
+            std::result::Result::Err(err) => {
+                self.animation.fire(Trigger::BackendFailed);
+                self.status.failed();
+                crash::note(format!("could not send: {err}"));
+                // Left in the input box to send again
+                self.typewriter.add_message(Role::Error, format!("Could not send message: {err}"));
+            },
+        }
+    }
+
+    fn perform(&mut self, action: Action) {
+        crash::note(format!("action {}", action.name()));
+        match action {
+            Action::Edit => {
+                self.input_mode = InputMode::Editing;
+                if let Some(vim) = self.vim.as_mut() {
+                    vim.reset();
+                }
+            },
+            Action::StopEditing => {
+                self.input_mode = InputMode::Normal;
+                self.animation.fire(Trigger::StoppedTyping);
+            },
+            Action::OpenPalette => self.palette = Some(Palette::new(self.input_mode == InputMode::Editing)),
+            Action::ToggleLog => self.log_view = match self.log_view {
+                Some(_) => None,
+                None => Some(LogView::new()),
+            },
+            Action::Submit if self.completing_name() => self.complete(),
+            Action::Submit => self.submit_message(),
+            Action::Panic => {
+                self.typewriter.add_message(Role::Assistant, String::from("
+                PANIC! PANIC!
+                "));
+                self.animation.fire(Trigger::Panic);
+            },
+            Action::Revive => {
+                self.animation.fire(Trigger::Revive);
+            },
+            Action::ScrollUp => self.typewriter.scroll_up(),
+            Action::ScrollDown => self.typewriter.scroll_down(),
+            Action::CycleVisualization => self.animation.cycle_visualization(),
+            Action::ToggleAvatarStyle => self.animation.toggle_style(),
+            Action::InsertNewline => self.edit(Editor::insert_newline),
+            Action::Complete => {
+                if !self.completions().is_empty() {
+                    self.complete();
+                }
+            },
+            Action::Backspace => self.edit(Editor::backspace),
+            Action::Delete => self.edit(Editor::delete),
+            Action::MoveLeft => self.editor.move_left(),
+            Action::MoveRight => self.editor.move_right(),
+            Action::MoveUp if self.completions().len() > 1 => {
+                self.completion = self.completion.saturating_sub(1);
+            },
+            Action::MoveDown if self.completions().len() > 1 => {
+                self.completion = (self.completion + 1).min(self.completions().len() - 1);
+            },
+            Action::MoveUp if self.editor.on_first_line() => {
+                if let Some(entry) = self.history.previous(&self.editor.text()) {
+                    self.editor.set_text(entry);
+                }
+            },
+            Action::MoveDown if self.editor.on_last_line() => {
+                if let Some(entry) = self.history.next() {
+                    self.editor.set_text(&entry);
+                }
+            },
+            Action::MoveUp => self.editor.move_up(),
+            Action::MoveDown => self.editor.move_down(),
+            Action::MoveHome => self.editor.move_home(),
+            Action::MoveEnd => self.editor.move_end(),
+            Action::MoveWordLeft => self.editor.move_word_left(),
+            Action::MoveWordRight => self.editor.move_word_right(),
+            Action::DeleteWordLeft => self.edit(Editor::delete_word_left),
+            Action::DeleteWordRight => self.edit(Editor::delete_word_right),
+            Action::KillToEnd => self.edit(Editor::kill_to_end),
+            Action::KillToStart => self.edit(Editor::kill_to_start),
+            Action::KillWordLeft => self.edit(Editor::kill_word_left),
+            Action::Yank => self.edit(Editor::yank),
+            Action::Undo => self.edit(|editor| { editor.undo(); }),
+            Action::Redo => self.edit(|editor| { editor.redo(); }),
+            Action::SearchHistory => {
+                let mut search = HistorySearch::new(self.editor.text());
+                search.refresh(&self.history);
+                self.search = Some(search);
+            },
+            Action::ExpandPastes => {
+                let expanded = self.pastes.expand(&self.editor.text());
+                self.edit(|editor| editor.set_text(&expanded));
+            },
+            Action::Help => {
+                let width = commands::COMMANDS.iter().map(|command| command.usage().len()).max().unwrap_or(0);
+                let help = commands::COMMANDS.iter()
+                    .map(|command| format!("{:<width$}  {}", command.usage(), command.help))
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                self.typewriter.show_message(Role::System, help);
+            },
+            // The reply on its way would land in the conversation just forgotten
+            Action::Clear if self.backend.is_pending() => {
+                self.status.notify("Wait for the reply before clearing the conversation");
+            },
+            Action::Clear => {
+                self.typewriter.clear();
+                if let std::result::Result::Err(err) = self.backend.forget_conversation() {
+                    self.status.warn(format!("Could not reset the model's memory: {err}"));
+                }
+            },
+            Action::Save(_) if !self.writes_out => self.status.notify("Not saved while replaying"),
+            Action::Save(path) => {
+                let path = path.unwrap_or_else(|| {
+                    PathBuf::from(chrono::Local::now().format("stemmgpt-%Y%m%d-%H%M%S.md").to_string())
+                });
+                let markdown: String = self.typewriter.messages().iter()
+                    .map(|message| format!("## {}\n\n{}\n\n", message.role.label(), message.text.trim()))
+                    .collect();
+                match fs::write(&path, markdown) {
+                    std::result::Result::Ok(()) => self.status.notify(format!("Saved the conversation to {}", path.display())),
+                    std::result::Result::Err(err) => self.status.warn(format!("Could not save to {}: {err}", path.display())),
+                }
+            },
+            Action::Theme(None) => {
+                let names: Vec<String> = theme::available().into_iter()
+                    .map(|name| if name == self.theme.name { format!("{name} (current)") } else { name })
+                    .collect();
+                self.typewriter.show_message(Role::System, format!("Themes: {}", names.join(", ")));
+            },
+            Action::Theme(Some(name)) => match Theme::named(&name) {
+                std::result::Result::Ok(theme) => {
+                    self.status.notify(format!("Theme {}", theme.name));
+                    self.theme = theme;
+                },
+                std::result::Result::Err(err) => self.typewriter.show_message(Role::System, err),
+            },
+            Action::NextTheme => {
+                let names = theme::available();
+                let current = names.iter().position(|name| *name == self.theme.name);
+                let next = current.map_or(0, |index| (index + 1) % names.len());
+                self.perform(Action::Theme(Some(names[next].clone())));
+            },
+            Action::Layout(None) => {
+                let names: Vec<String> = layout::PRESETS.iter()
+                    .map(|preset| match *preset == self.ui.layout {
+                        true => format!("{} (current)", preset.name()),
+                        false => preset.name().to_string(),
+                    })
+                    .collect();
+                self.typewriter.show_message(Role::System, format!("Layouts: {}", names.join(", ")));
+            },
+            Action::Layout(Some(preset)) => {
+                self.ui.layout = preset;
+                self.status.notify(format!("Layout {}", preset.name()));
+                self.save_layout(LayoutSetting::Preset);
+            },
+            Action::NextLayout => self.perform(Action::Layout(Some(self.ui.layout.next()))),
+            Action::GrowInput => self.resize(Split::Input, 5),
+            Action::ShrinkInput => self.resize(Split::Input, -5),
+            Action::GrowAvatar => self.resize(Split::Avatar, 5),
+            Action::ShrinkAvatar => self.resize(Split::Avatar, -5),
+            Action::Copy(target) => self.copy(target),
+            Action::Quit => self.exit = true,
+            Action::Suspend => self.suspend = true,
+            Action::TypeFaster => {
+                self.typewriter.set_char_delay(self.typewriter.char_delay() * 2 / 3);
+                self.perform(Action::Speed(None));
+            },
+            Action::TypeSlower => {
+                self.typewriter.set_char_delay(self.typewriter.char_delay() * 3 / 2);
+                self.perform(Action::Speed(None));
+            },
+            Action::SkipTyping => self.typewriter.finish_message(),
+            Action::Speed(speed) => {
+                if let Some(speed) = speed {
+                    self.typewriter.set_char_delay(Duration::from_secs(1) / speed);
+                }
+                let per_second = |delay: Duration| 1.0 / delay.as_secs_f64();
+                let (speed, typing) = (per_second(self.typewriter.char_delay()), per_second(self.typewriter.typing_delay()));
+                match typing > speed {
+                    true => self.status.notify(format!("Typing replies at {speed:.0} characters per second, this long one at {typing:.0}")),
+                    false => self.status.notify(format!("Typing replies at {speed:.0} characters per second")),
+                }
+            },
+        }
     }
 
-    const fn reset_cursor(&mut self) {
-        self.character_index = 0;
+    fn poll_backend(&mut self) {
+        let reply = self.backend.poll();
+        if let Some(reply) = &reply {
+            self.record(Step::Reply(reply.clone()));
+        }
+        match reply {
+            Some(std::result::Result::Ok(ai_reply)) => {
+                crash::note(format!("reply of {} characters", ai_reply.chars().count()));
+                self.status.replied(&ai_reply);
+                self.animation.set_mood(face::mood_for(&ai_reply));
+                self.typewriter.add_message(Role::Assistant, ai_reply);
+            },
+            Some(std::result::Result::Err(err)) => {
+                crash::note(format!("request failed: {err}"));
+                self.animation.fire(Trigger::BackendFailed);
+                self.status.failed();
+                self.typewriter.add_message(Role::Error, err);
+            },
+            None => {},
+        }
     }
 
     fn header(&mut self) -> Paragraph<'_> {
-        let header = HEADER_TEXT.clone();
-
-        Paragraph::new(header)
+        Paragraph::new(header_text(&self.theme))
             .alignment(Alignment::Center)
             .block(
                 Block::default()
                     .borders(Borders::ALL)
-                    .border_style(Style::default().fg(Color::Magenta))
+                    .border_style(self.theme.header)
                     .title(" Welcome ")
                     .title_alignment(Alignment::Center)
             )
     }
-}
\ No newline at end of file
+}
+
+// Text dragged over in the conversation, as screen cells.
+struct Selection {
+    anchor: (u16, u16),
+    cursor: (u16, u16),
+    /// Set when the mouse button is let go, so the text is copied on the next draw
+    copy: bool,
+    /// The conversation as first drawn with the selection, a string per row
+    shown: Option<Vec<String>>,
+}
+
+// The character a key types, if it is one: plain or shifted, or with Ctrl+Alt,
+// which is how AltGr arrives on Windows.
+fn typed_char(key: &KeyEvent) -> Option<char> {
+    let chord = key.modifiers & (KeyModifiers::CONTROL | KeyModifiers::ALT);
+    match key.code {
+        KeyCode::Char(c) if chord.is_empty() || chord == KeyModifiers::CONTROL | KeyModifiers::ALT => Some(c),
+        _ => None,
+    }
+}
+
+// Shows the characters of a visual line that fall inside `selection` in the selection style.
+fn highlight(line: String, start: usize, (from, to): (usize, usize), style: Style) -> Line<'static> {
+    let chars: Vec<char> = line.chars().collect();
+    let split = |offset: usize| offset.saturating_sub(start).min(chars.len());
+    let (from, to) = (split(from), split(to));
+    Line::from(vec![
+        chars[..from].iter().collect::<String>().into(),
+        Span::styled(chars[from..to].iter().collect::<String>(), style),
+        chars[to..].iter().collect::<String>().into(),
+    ])
+}
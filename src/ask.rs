@@ -0,0 +1,217 @@
+use std::{
+    io::{Read, Write},
+    process::ExitCode,
+    thread,
+    time::{Duration, Instant},
+};
+
+use color_eyre::Result;
+use serde::Serialize;
+
+use crate::{
+    action::Action,
+    backend::{Backend, Connection},
+    commands,
+    config::Config,
+    status::estimate_tokens,
+};
+
+const DEFAULT_TIMEOUT_SECS: u64 = 300;
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Exit codes besides success. 2 is what clap uses for a bad command line.
+const EXIT_FAILED: u8 = 1;
+const EXIT_USAGE: u8 = 2;
+const EXIT_TIMED_OUT: u8 = 3;
+
+/// Arguments of the `ask` command.
+#[derive(clap::Args)]
+pub struct AskArgs {
+    /// The prompt, read from standard input when left out
+    question: Vec<String>,
+    /// Print the reply with timings and token counts as a JSON object
+    #[arg(long)]
+    json: bool,
+    /// Seconds to wait for the model to load and reply before giving up
+    #[arg(long, value_name = "SECS", default_value_t = DEFAULT_TIMEOUT_SECS)]
+    timeout: u64,
+}
+
+impl AskArgs {
+    /// For `echo question | stemmgpt`, which asks without the subcommand.
+    pub fn piped() -> Self {
+        Self { question: Vec::new(), json: false, timeout: DEFAULT_TIMEOUT_SECS }
+    }
+}
+
+/// What `--json` prints, whether the model answered or not.
+#[derive(Serialize)]
+struct Answer {
+    prompt: String,
+    reply: Option<String>,
+    error: Option<String>,
+    session: Option<String>,
+    /// From sending the prompt to the whole reply arriving
+    elapsed_ms: Option<u128>,
+    prompt_tokens: Option<usize>,
+    reply_tokens: Option<usize>,
+}
+
+/// Sends one prompt to the model and writes the reply to `output`, without the
+/// full-screen app. The prompt is read from `input` if it isn't in `args`.
+///
+/// Errors go to standard error, or to `output` as part of the JSON with `--json`.
+pub fn run(args: &AskArgs, config: &Config, backend: &mut dyn Backend, input: &mut dyn Read, output: &mut dyn Write) -> Result<ExitCode> {
+    let input = match args.question.is_empty() {
+        true => {
+            let mut text = String::new();
+            input.read_to_string(&mut text)?;
+            text
+        },
+        false => args.question.join(" "),
+    };
+    let input = input.trim();
+    if input.is_empty() {
+        eprintln!("Nothing to ask: give a question or pipe one in");
+        return Ok(ExitCode::from(EXIT_USAGE));
+    }
+
+    if let Some(command) = commands::parse(input) {
+        return Ok(match command {
+            Ok(Action::Clear) => {
+                backend.forget_conversation()?;
+                ExitCode::SUCCESS
+            },
+            Ok(_) => {
+                eprintln!("{input} only works in the app");
+                ExitCode::from(EXIT_USAGE)
+            },
+            Err(err) => {
+                eprintln!("{err}");
+                ExitCode::from(EXIT_USAGE)
+            },
+        });
+    }
+
+    let prompt = commands::prompt(input);
+    let mut answer = Answer {
+        prompt: prompt.clone(),
+        reply: None,
+        error: None,
+        session: config.session.clone(),
+        elapsed_ms: None,
+        prompt_tokens: None,
+        reply_tokens: None,
+    };
+    let deadline = Instant::now() + Duration::from_secs(args.timeout);
+    while backend.connection() == Connection::Offline {
+        if Instant::now() >= deadline {
+            return finish(args, output, answer, Err((EXIT_TIMED_OUT, "The model did not load in time, is ai.py running?".to_string())));
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+
+    let sent_at = Instant::now();
+    if let Err(err) = backend.send(&prompt) {
+        return finish(args, output, answer, Err((EXIT_FAILED, format!("Could not send message: {err}"))));
+    }
+    answer.prompt_tokens = Some(estimate_tokens(backend.context_len()));
+    let reply = loop {
+        if let Some(reply) = backend.poll() {
+            break reply;
+        }
+        if Instant::now() >= deadline {
+            break Err("The model did not reply in time".to_string());
+        }
+        thread::sleep(POLL_INTERVAL);
+    };
+    answer.elapsed_ms = Some(sent_at.elapsed().as_millis());
+    let code = match backend.is_pending() {
+        true => {
+            backend.abandon();
+            EXIT_TIMED_OUT
+        },
+        false => EXIT_FAILED,
+    };
+    finish(args, output, answer, reply.map_err(|err| (code, err)))
+}
+
+// Prints the reply or the error and picks the exit code to go with it.
+fn finish(args: &AskArgs, output: &mut dyn Write, mut answer: Answer, reply: Result<String, (u8, String)>) -> Result<ExitCode> {
+    let code = match reply {
+        Ok(reply) => {
+            answer.reply_tokens = Some(estimate_tokens(reply.chars().count()));
+            answer.reply = Some(reply);
+            ExitCode::SUCCESS
+        },
+        Err((code, err)) => {
+            answer.error = Some(err);
+            ExitCode::from(code)
+        },
+    };
+    match (args.json, &answer.reply, &answer.error) {
+        (true, _, _) => writeln!(output, "{}", serde_json::to_string(&answer)?)?,
+        (false, Some(reply), _) => writeln!(output, "{reply}")?,
+        (false, None, error) => eprintln!("{}", error.as_deref().unwrap_or_default()),
+    }
+    Ok(code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{backend::ScriptedBackend, clock::ManualClock};
+
+    // Asks `question`, or what is piped in if it is empty, of a model that gives `replies`.
+    fn ask(question: &str, piped: &str, json: bool, replies: Vec<Result<String, String>>) -> (ExitCode, String, ScriptedBackend) {
+        let args = AskArgs {
+            question: question.split_whitespace().map(str::to_string).collect(),
+            json,
+            timeout: 0,
+        };
+        let config = Config { session: Some("test".to_string()), ..Config::default() };
+        let mut backend = ScriptedBackend::new(ManualClock::new(), replies.into_iter().map(|reply| (Duration::ZERO, reply)));
+        let mut output = Vec::new();
+        let code = run(&args, &config, &mut backend, &mut piped.as_bytes(), &mut output).unwrap();
+        (code, String::from_utf8(output).unwrap(), backend)
+    }
+
+    #[test]
+    fn exit_codes() {
+        let (code, output, _) = ask("", "What is pi?\n", false, vec![Ok("About 3.14".to_string())]);
+        assert_eq!((code, output.as_str()), (ExitCode::SUCCESS, "About 3.14\n"));
+
+        let (code, output, _) = ask("What is pi?", "", false, vec![Err("Error: out of memory".to_string())]);
+        assert_eq!((code, output.as_str()), (ExitCode::from(EXIT_FAILED), ""));
+
+        for (question, piped) in [("", "  \n"), ("/save", ""), ("/speed fast", "")] {
+            assert_eq!(ask(question, piped, false, Vec::new()).0, ExitCode::from(EXIT_USAGE), "{question:?}");
+        }
+
+        let (code, _, backend) = ask("What is pi?", "", false, Vec::new());
+        assert_eq!(code, ExitCode::from(EXIT_TIMED_OUT));
+        assert!(!backend.is_pending());
+    }
+
+    #[test]
+    fn json_has_the_reply_or_the_error() {
+        let (code, output, _) = ask("What is pi?", "", true, vec![Ok("About 3.14".to_string())]);
+        assert_eq!(code, ExitCode::SUCCESS);
+        let answer: serde_json::Value = serde_json::from_str(&output).unwrap();
+        assert_eq!(answer["prompt"], "What is pi?");
+        assert_eq!(answer["reply"], "About 3.14");
+        assert!(answer["error"].is_null());
+        assert_eq!(answer["session"], "test");
+        assert!(answer["elapsed_ms"].is_u64());
+        // "User: What is pi?" and a line break
+        assert_eq!(answer["prompt_tokens"], 5);
+        assert_eq!(answer["reply_tokens"], 3);
+
+        let (code, output, _) = ask("What is pi?", "", true, Vec::new());
+        assert_eq!(code, ExitCode::from(EXIT_TIMED_OUT));
+        let answer: serde_json::Value = serde_json::from_str(&output).unwrap();
+        assert!(answer["reply"].is_null());
+        assert_eq!(answer["error"], "The model did not reply in time");
+        assert!(answer["reply_tokens"].is_null());
+    }
+}
@@ -0,0 +1,257 @@
+use std::{f32::consts::PI, time::Duration};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use rodio::{buffer::SamplesBuffer, OutputStream, OutputStreamHandle, Sink};
+use serde::{Deserialize, Serialize};
+
+use crate::braille::BrailleCanvas;
+
+pub const SAMPLE_RATE: u32 = 22_050;
+const VOLUME: f32 = 0.25;
+// Number of samples fed to the spectrum analysis and the oscilloscope.
+const WINDOW: usize = 512;
+const LOWEST_BAND_HZ: f32 = 80.0;
+const HIGHEST_BAND_HZ: f32 = 4_000.0;
+const SPECTRUM_FLOOR_DB: f32 = -48.0;
+
+// Relative harmonic strengths for a handful of vowel-ish timbres.
+const VOWELS: [[f32; 6]; 4] = [
+    [1.0, 0.8, 0.6, 0.2, 0.1, 0.05],
+    [1.0, 0.3, 0.1, 0.4, 0.5, 0.2],
+    [0.6, 1.0, 0.3, 0.1, 0.3, 0.4],
+    [1.0, 0.5, 0.5, 0.5, 0.2, 0.1],
+];
+
+/// How the talking avatar draws the sound it is making.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Visualization {
+    Oscilloscope,
+    Spectrum,
+    MirroredBars,
+}
+
+impl Visualization {
+    pub fn next(self) -> Self {
+        match self {
+            Visualization::Oscilloscope => Visualization::Spectrum,
+            Visualization::Spectrum => Visualization::MirroredBars,
+            Visualization::MirroredBars => Visualization::Oscilloscope,
+        }
+    }
+}
+
+/// Babbling synthesizer: a stream of short vowel syllables and pauses.
+pub struct Voice {
+    rng: StdRng,
+    phase: f32,
+    pitch: f32,
+    vowel: usize,
+    syllable_len: usize,
+    syllable_pos: usize,
+    silent: bool,
+}
+
+impl Voice {
+    pub fn new() -> Self {
+        Self {
+            rng: StdRng::from_entropy(),
+            phase: 0.0,
+            pitch: 160.0,
+            vowel: 0,
+            syllable_len: 0,
+            syllable_pos: 0,
+            silent: true,
+        }
+    }
+
+    pub fn seed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    /// Synthesizes the next `length` worth of samples.
+    pub fn next_chunk(&mut self, length: Duration) -> Vec<f32> {
+        let count = (length.as_secs_f32() * SAMPLE_RATE as f32) as usize;
+        (0..count).map(|_| self.next_sample()).collect()
+    }
+
+    fn next_sample(&mut self) -> f32 {
+        if self.syllable_pos >= self.syllable_len {
+            self.start_syllable();
+        }
+        let progress = self.syllable_pos as f32 / self.syllable_len as f32;
+        self.syllable_pos += 1;
+
+        // Pitch falls slightly across a syllable, like speech does.
+        let frequency = self.pitch * (1.0 - 0.15 * progress);
+        self.phase = (self.phase + 2.0 * PI * frequency / SAMPLE_RATE as f32) % (2.0 * PI);
+        if self.silent {
+            return 0.0;
+        }
+
+        let harmonics = &VOWELS[self.vowel];
+        let norm: f32 = harmonics.iter().sum();
+        let tone: f32 = harmonics.iter()
+            .enumerate()
+            .map(|(k, weight)| weight * (self.phase * (k + 1) as f32).sin())
+            .sum::<f32>() / norm;
+        let envelope = (PI * progress).sin();
+        tone * envelope * VOLUME
+    }
+
+    fn start_syllable(&mut self) {
+        self.silent = self.rng.gen_bool(0.2);
+        let millis = if self.silent { self.rng.gen_range(60..200) } else { self.rng.gen_range(80..220) };
+        self.syllable_len = (SAMPLE_RATE as usize * millis / 1000).max(1);
+        self.syllable_pos = 0;
+        self.vowel = self.rng.gen_range(0..VOWELS.len());
+        self.pitch = self.rng.gen_range(120.0..220.0);
+    }
+}
+
+/// Keeps the audio device open and queues synthesized chunks on it.
+pub struct Speaker {
+    // Dropping the stream stops playback, so it has to live as long as the sink.
+    _stream: OutputStream,
+    _handle: OutputStreamHandle,
+    sink: Sink,
+}
+
+impl Speaker {
+    /// Opens the default output device, or `None` when there is no usable one.
+    pub fn open() -> Option<Self> {
+        let (stream, handle) = OutputStream::try_default()
+            .map_err(|err| tracing::info!("No sound, as there is no audio device: {err}"))
+            .ok()?;
+        let sink = Sink::try_new(&handle)
+            .map_err(|err| tracing::warn!("No sound, as the audio device could not be used: {err}"))
+            .ok()?;
+        Some(Self { _stream: stream, _handle: handle, sink })
+    }
+
+    pub fn play(&self, samples: &[f32]) {
+        self.sink.append(SamplesBuffer::new(1, SAMPLE_RATE, samples.to_vec()));
+    }
+}
+
+/// The `WINDOW` samples of `chunk` that are audible `progress` (0..1) of the way through it.
+pub fn window_at(chunk: &[f32], progress: f32) -> &[f32] {
+    if chunk.len() <= WINDOW {
+        return chunk;
+    }
+    let start = ((chunk.len() - WINDOW) as f32 * progress.clamp(0.0, 1.0)) as usize;
+    &chunk[start..start + WINDOW]
+}
+
+/// Loudness of `bands` logarithmically spaced frequency bands, each in 0..1.
+pub fn spectrum(samples: &[f32], bands: usize) -> Vec<f32> {
+    let n = samples.len();
+    if n == 0 || bands == 0 {
+        return vec![0.0; bands];
+    }
+    // Hann window so the chunk edges do not smear energy over every band.
+    let windowed: Vec<f32> = samples.iter()
+        .enumerate()
+        .map(|(i, s)| s * (0.5 - 0.5 * (2.0 * PI * i as f32 / n as f32).cos()))
+        .collect();
+
+    let bin_of = |hz: f32| hz * n as f32 / SAMPLE_RATE as f32;
+    let magnitudes: Vec<f32> = (0..=bin_of(HIGHEST_BAND_HZ).ceil() as usize)
+        .map(|bin| dft_magnitude(&windowed, bin))
+        .collect();
+
+    let ratio = HIGHEST_BAND_HZ / LOWEST_BAND_HZ;
+    (0..bands)
+        .map(|band| {
+            let low = LOWEST_BAND_HZ * ratio.powf(band as f32 / bands as f32);
+            let high = LOWEST_BAND_HZ * ratio.powf((band + 1) as f32 / bands as f32);
+            let first_bin = bin_of(low).floor() as usize;
+            let last_bin = (bin_of(high).ceil() as usize).clamp(first_bin + 1, magnitudes.len());
+            let peak = magnitudes[first_bin.min(magnitudes.len() - 1)..last_bin]
+                .iter()
+                .copied()
+                .fold(0.0, f32::max);
+            // A full scale sine comes out of the Hann windowed DFT at n / 4.
+            let db = 20.0 * (peak / (n as f32 / 4.0) / VOLUME).max(1e-6).log10();
+            ((db - SPECTRUM_FLOOR_DB) / -SPECTRUM_FLOOR_DB).clamp(0.0, 1.0)
+        })
+        .collect()
+}
+
+fn dft_magnitude(samples: &[f32], bin: usize) -> f32 {
+    let n = samples.len() as f32;
+    let (re, im) = samples.iter()
+        .enumerate()
+        .fold((0.0, 0.0), |(re, im), (i, s)| {
+            let angle = 2.0 * PI * bin as f32 * i as f32 / n;
+            (re + s * angle.cos(), im - s * angle.sin())
+        });
+    (re * re + im * im).sqrt()
+}
+
+/// Draws `samples` into a `width` x `height` cell Braille picture.
+pub fn visualize(visualization: Visualization, samples: &[f32], width: usize, height: usize) -> Vec<String> {
+    let mut canvas = BrailleCanvas::new(width, height);
+    let dot_width = canvas.dot_width() as i32;
+    let dot_height = canvas.dot_height() as i32;
+    let center = dot_height / 2;
+
+    match visualization {
+        Visualization::Oscilloscope => {
+            let mut previous = None;
+            for x in 0..dot_width {
+                let index = x as usize * samples.len() / dot_width.max(1) as usize;
+                let sample = samples.get(index).copied().unwrap_or(0.0) / VOLUME;
+                let y = center - (sample.clamp(-1.0, 1.0) * (center - 1) as f32) as i32;
+                match previous {
+                    Some(point) => canvas.line(point, (x, y)),
+                    None => canvas.set(x, y),
+                }
+                previous = Some((x, y));
+            }
+        },
+        Visualization::Spectrum => {
+            for (column, level) in spectrum(samples, width).into_iter().enumerate() {
+                let top = dot_height - 1 - (level * (dot_height - 1) as f32) as i32;
+                for x in [column as i32 * 2, column as i32 * 2 + 1] {
+                    canvas.vertical_line(x, top, dot_height - 1);
+                }
+            }
+        },
+        Visualization::MirroredBars => {
+            for (column, level) in spectrum(samples, width).into_iter().enumerate() {
+                let reach = (level * center as f32) as i32;
+                canvas.vertical_line(column as i32 * 2, center - reach, center + reach);
+            }
+        },
+    }
+    canvas.into_lines()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(hz: f32, amplitude: f32) -> Vec<f32> {
+        (0..WINDOW).map(|i| amplitude * (2.0 * PI * hz * i as f32 / SAMPLE_RATE as f32).sin()).collect()
+    }
+
+    #[test]
+    fn spectrum_peaks_in_the_band_of_the_tone() {
+        assert_eq!(spectrum(&[], 4), vec![0.0; 4]);
+        assert!(spectrum(&[0.0; WINDOW], 8).iter().all(|&level| level == 0.0));
+
+        let bands = 8;
+        let levels = spectrum(&sine(1000.0, VOLUME), bands);
+        assert_eq!(levels.len(), bands);
+        assert!(levels.iter().all(|level| (0.0..=1.0).contains(level)));
+        // 1000Hz is in band 5 of 8 between 80Hz and 4000Hz, spaced logarithmically
+        let loudest = (0..bands).max_by(|&a, &b| levels[a].total_cmp(&levels[b])).unwrap();
+        assert_eq!(loudest, 5, "{levels:?}");
+        assert!(levels[5] > 0.9, "{levels:?}");
+        assert!(levels[0] < levels[5] / 2.0, "{levels:?}");
+
+        let quiet = spectrum(&sine(1000.0, VOLUME / 10.0), bands);
+        assert!(quiet[5] < levels[5], "{quiet:?}");
+    }
+}
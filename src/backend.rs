@@ -0,0 +1,300 @@
+use std::{collections::VecDeque, fs, io::{self, Write}, time::Duration};
+
+use tracing::{debug, info, warn};
+
+use crate::{clock::SharedClock, config::FileSettings};
+
+/// Whether the model can take a prompt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Connection {
+    /// Not started, still loading the model, or gone
+    Offline,
+    Ready,
+    /// Working on a prompt
+    Busy,
+}
+
+/// Whatever answers the prompts.
+///
+/// `send` only hands the prompt over; the reply is picked up by `poll`
+/// so the UI keeps drawing while the model is working.
+pub trait Backend {
+    fn is_pending(&self) -> bool;
+
+    fn connection(&self) -> Connection;
+
+    /// How much of the conversation the model reads with each prompt, in characters.
+    fn context_len(&self) -> usize;
+
+    fn send(&mut self, prompt: &str) -> io::Result<()>;
+
+    /// Returns the reply once there is one, or an error message if the
+    /// reply could not be read or the model reported a failure.
+    fn poll(&mut self) -> Option<Result<String, String>>;
+
+    /// Gives up waiting for the reply to the last prompt, so that if it turns
+    /// up late it isn't taken for the reply to the next one.
+    fn abandon(&mut self);
+
+    /// Drops everything said so far from the model's memory, keeping the system
+    /// prompt. Only called while no reply is pending.
+    fn forget_conversation(&mut self) -> io::Result<()>;
+}
+
+/// Puts the id of a prompt on the first line of it, or of the reply to it.
+pub fn tag(id: &str, text: &str) -> String {
+    format!("{id}\n{text}")
+}
+
+/// Splits a tagged prompt or reply into its id and text.
+pub fn untag(text: &str) -> (&str, &str) {
+    text.split_once('\n').unwrap_or(("", text))
+}
+
+/// Talks to `ai.py` through the files it watches.
+///
+/// Each prompt is tagged with an id that `ai.py` puts back on the reply, so
+/// a late reply to a prompt given up on is never taken for the next one's.
+pub struct FileBackend {
+    files: FileSettings,
+    pending: bool,
+    /// Prompts sent so far, to make the id of the next one
+    sent: u64,
+}
+
+impl FileBackend {
+    pub fn new(files: &FileSettings) -> Self {
+        Self { files: files.clone(), pending: false, sent: 0 }
+    }
+
+    fn prompt_id(&self) -> String {
+        format!("{}-{}", std::process::id(), self.sent)
+    }
+
+    fn remember(&self, speaker: &str, text: &str) -> io::Result<()> {
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.files.memory)?;
+        writeln!(file, "{speaker}: {text}")
+    }
+}
+
+impl Backend for FileBackend {
+    fn is_pending(&self) -> bool {
+        self.pending
+    }
+
+    fn connection(&self) -> Connection {
+        match (self.pending, fs::metadata(&self.files.ready).is_ok()) {
+            (true, _) => Connection::Busy,
+            (false, true) => Connection::Ready,
+            (false, false) => Connection::Offline,
+        }
+    }
+
+    fn context_len(&self) -> usize {
+        fs::read_to_string(&self.files.memory).map_or(0, |memory| memory.chars().count())
+    }
+
+    fn send(&mut self, prompt: &str) -> io::Result<()> {
+        // Left over from a prompt given up on
+        match fs::remove_file(&self.files.output) {
+            Ok(()) => warn!("Threw away a reply nobody was waiting for"),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {},
+            Err(err) => return Err(err),
+        }
+        self.sent += 1;
+        // Renamed into place so ai.py never reads half a prompt
+        let partial = self.files.input.with_extension("tmp");
+        fs::write(&partial, tag(&self.prompt_id(), prompt))?;
+        fs::rename(&partial, &self.files.input)?;
+        self.remember("User", prompt)?;
+        self.pending = true;
+        debug!(chars = prompt.chars().count(), "Sent a prompt to ai.py");
+        Ok(())
+    }
+
+    fn poll(&mut self) -> Option<Result<String, String>> {
+        if !self.pending || fs::metadata(&self.files.output).is_err() {
+            return None;
+        }
+
+        let reply = fs::read_to_string(&self.files.output)
+            .map_err(|err| format!("Error reading AI response: {err}"));
+        if let Err(err) = fs::remove_file(&self.files.output) {
+            warn!("Could not remove {}, so the reply may be read again: {err}", self.files.output.display());
+        }
+        let reply = match &reply {
+            Ok(tagged) => match untag(tagged) {
+                (id, reply) if id == self.prompt_id() => Ok(reply.to_string()),
+                (id, _) => {
+                    warn!(id, "Threw away a reply to an earlier prompt");
+                    return None;
+                },
+            },
+            Err(err) => Err(err.clone()),
+        };
+        self.pending = false;
+
+        match reply {
+            Ok(reply) if reply.starts_with("Error:") => {
+                warn!("ai.py failed: {reply}");
+                Some(Err(reply))
+            },
+            Ok(reply) => {
+                debug!(chars = reply.chars().count(), "Got a reply from ai.py");
+                if let Err(err) = self.remember("AI", &reply) {
+                    warn!("Could not add the reply to {}: {err}", self.files.memory.display());
+                }
+                Some(Ok(reply))
+            },
+            Err(err) => {
+                warn!("{err}");
+                Some(Err(err))
+            },
+        }
+    }
+
+    fn abandon(&mut self) {
+        if !std::mem::take(&mut self.pending) {
+            return;
+        }
+        // ai.py drops the reply if the prompt is gone by the time it has one
+        for path in [&self.files.input, &self.files.output] {
+            if let Err(err) = fs::remove_file(path) {
+                if err.kind() != io::ErrorKind::NotFound {
+                    warn!("Could not remove {}: {err}", path.display());
+                }
+            }
+        }
+        info!("Gave up waiting for the reply");
+    }
+
+    fn forget_conversation(&mut self) -> io::Result<()> {
+        let memory = match fs::read_to_string(&self.files.memory) {
+            Ok(memory) => memory,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(err),
+        };
+        let system_prompt = match memory.find("\nUser: ") {
+            Some(end) => &memory[..end],
+            None if memory.starts_with("User: ") => "",
+            None => &memory,
+        };
+        fs::write(&self.files.memory, system_prompt)?;
+        // ai.py also keeps the conversation itself, until it sees this
+        fs::write(&self.files.reset, "reset")?;
+        info!("Cleared the conversation in {}", self.files.memory.display());
+        Ok(())
+    }
+}
+
+/// Gives replies decided in advance, each no sooner than the time it is due,
+/// for replaying recorded sessions and for tests.
+pub struct ScriptedBackend {
+    clock: SharedClock,
+    replies: VecDeque<(Duration, Result<String, String>)>,
+    pending: bool,
+    /// Everything sent and replied so far, standing in for `memory.txt`
+    context: usize,
+    /// What `context_len` gave after each prompt when the session was recorded
+    context_lens: VecDeque<usize>,
+}
+
+impl ScriptedBackend {
+    pub fn new(clock: SharedClock, replies: impl IntoIterator<Item = (Duration, Result<String, String>)>) -> Self {
+        Self { clock, replies: replies.into_iter().collect(), pending: false, context: 0, context_lens: VecDeque::new() }
+    }
+
+    pub fn with_context_lens(mut self, lens: impl IntoIterator<Item = usize>) -> Self {
+        self.context_lens = lens.into_iter().collect();
+        self
+    }
+}
+
+impl Backend for ScriptedBackend {
+    fn is_pending(&self) -> bool {
+        self.pending
+    }
+
+    fn connection(&self) -> Connection {
+        match self.pending {
+            true => Connection::Busy,
+            false => Connection::Ready,
+        }
+    }
+
+    fn context_len(&self) -> usize {
+        self.context
+    }
+
+    fn send(&mut self, prompt: &str) -> io::Result<()> {
+        self.context += "User: \n".len() + prompt.chars().count();
+        if let Some(len) = self.context_lens.pop_front() {
+            self.context = len;
+        }
+        self.pending = true;
+        Ok(())
+    }
+
+    fn poll(&mut self) -> Option<Result<String, String>> {
+        let due = self.replies.front().is_some_and(|(at, _)| *at <= self.clock.now());
+        if !self.pending || !due {
+            return None;
+        }
+        self.pending = false;
+        let (_, reply) = self.replies.pop_front()?;
+        if let Ok(reply) = &reply {
+            self.context += "AI: \n".len() + reply.chars().count();
+        }
+        Some(reply)
+    }
+
+    fn abandon(&mut self) {
+        if std::mem::take(&mut self.pending) {
+            self.replies.pop_front();
+        }
+    }
+
+    fn forget_conversation(&mut self) -> io::Result<()> {
+        self.context = 0;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_reply_given_up_on_is_not_taken_for_the_next() {
+        let dir = std::env::temp_dir().join(format!("stemmgpt-backend-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let files = FileSettings {
+            input: dir.join("input.txt"),
+            output: dir.join("output.txt"),
+            memory: dir.join("memory.txt"),
+            ready: dir.join("ready.txt"),
+            reset: dir.join("reset.txt"),
+        };
+        let mut backend = FileBackend::new(&files);
+
+        backend.send("first").unwrap();
+        let id = || untag(&fs::read_to_string(&files.input).unwrap()).0.to_string();
+        let first = id();
+        backend.abandon();
+        assert!(!backend.is_pending());
+        assert!(!files.input.exists());
+
+        // Written by ai.py after the next prompt was sent
+        backend.send("second").unwrap();
+        let second = id();
+        fs::write(&files.output, tag(&first, "late reply")).unwrap();
+        assert_eq!(backend.poll(), None);
+        assert!(backend.is_pending());
+        fs::write(&files.output, tag(&second, "second reply")).unwrap();
+        assert_eq!(backend.poll(), Some(Ok("second reply".to_string())));
+        let _ = fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,92 @@
+/// Monochrome canvas where every terminal cell holds a 2x4 block of Braille dots.
+pub struct BrailleCanvas {
+    width: usize,
+    height: usize,
+    // One bitmask per cell, using the Unicode Braille dot numbering.
+    cells: Vec<u8>,
+}
+
+// Bit for the dot at (column, row) inside a cell.
+const DOT_BITS: [[u8; 4]; 2] = [
+    [0x01, 0x02, 0x04, 0x40],
+    [0x08, 0x10, 0x20, 0x80],
+];
+
+impl BrailleCanvas {
+    /// Creates a canvas covering `width` x `height` terminal cells.
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            cells: vec![0; width * height],
+        }
+    }
+
+    /// Horizontal resolution in dots.
+    pub fn dot_width(&self) -> usize {
+        self.width * 2
+    }
+
+    /// Vertical resolution in dots.
+    pub fn dot_height(&self) -> usize {
+        self.height * 4
+    }
+
+    pub fn set(&mut self, x: i32, y: i32) {
+        if x < 0 || y < 0 || x as usize >= self.dot_width() || y as usize >= self.dot_height() {
+            return;
+        }
+        let (x, y) = (x as usize, y as usize);
+        self.cells[(y / 4) * self.width + x / 2] |= DOT_BITS[x % 2][y % 4];
+    }
+
+    /// Sets every dot of column `x` between `y0` and `y1`, inclusive.
+    pub fn vertical_line(&mut self, x: i32, y0: i32, y1: i32) {
+        for y in y0.min(y1)..=y0.max(y1) {
+            self.set(x, y);
+        }
+    }
+
+    pub fn line(&mut self, (x0, y0): (i32, i32), (x1, y1): (i32, i32)) {
+        let steps = (x1 - x0).abs().max((y1 - y0).abs()).max(1);
+        for step in 0..=steps {
+            let t = step as f32 / steps as f32;
+            let x = x0 as f32 + (x1 - x0) as f32 * t;
+            let y = y0 as f32 + (y1 - y0) as f32 * t;
+            self.set(x.round() as i32, y.round() as i32);
+        }
+    }
+
+    pub fn into_lines(self) -> Vec<String> {
+        self.cells
+            .chunks(self.width.max(1))
+            .take(self.height)
+            .map(|row| row.iter()
+                .map(|&bits| char::from_u32(0x2800 + bits as u32).unwrap_or(' '))
+                .collect())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dots_map_to_braille_characters() {
+        let mut canvas = BrailleCanvas::new(2, 1);
+        assert_eq!((canvas.dot_width(), canvas.dot_height()), (4, 4));
+        canvas.set(0, 0);
+        canvas.set(1, 3);
+        // Off the canvas, so ignored
+        canvas.set(-1, 0);
+        canvas.set(4, 0);
+        canvas.set(0, 4);
+        canvas.vertical_line(2, 3, 0);
+        assert_eq!(canvas.into_lines(), ["⢁⡇"]);
+
+        let mut canvas = BrailleCanvas::new(1, 1);
+        canvas.line((0, 0), (1, 3));
+        assert_eq!(canvas.into_lines(), ["⢣"]);
+    }
+}
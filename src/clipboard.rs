@@ -0,0 +1,124 @@
+use std::{
+    env,
+    io::{self, Write},
+    process::{Command, Stdio},
+    thread,
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+
+/// Puts `text` on the system clipboard, returning what it was handed to.
+///
+/// An OSC 52 escape sequence asks the terminal to do the copying, which works
+/// over SSH as well. Terminals can ignore it without saying so, so when
+/// `local` is set the text also goes to `wl-copy` or `xclip` if they are there.
+pub fn copy(text: &str, local: bool) -> io::Result<String> {
+    osc52(text)?;
+    if local {
+        if let Some(tool) = copy_locally(text) {
+            return Ok(format!("the terminal and {tool}"));
+        }
+    }
+    Ok("the terminal".to_string())
+}
+
+fn osc52(text: &str) -> io::Result<()> {
+    let sequence = format!("\x1b]52;c;{}\x07", STANDARD.encode(text));
+    let mut stdout = io::stdout();
+    if env::var_os("TMUX").is_some() {
+        // tmux only passes sequences through to the outer terminal when wrapped
+        write!(stdout, "\x1bPtmux;{}\x1b\\", sequence.replace('\x1b', "\x1b\x1b"))?;
+    } else {
+        write!(stdout, "{sequence}")?;
+    }
+    stdout.flush()
+}
+
+// Hands `text` to the clipboard tool that suits the display server, if there is one.
+fn copy_locally(text: &str) -> Option<&'static str> {
+    let (tool, args): (&str, &[&str]) = if env::var_os("WAYLAND_DISPLAY").is_some() {
+        ("wl-copy", &[])
+    } else if env::var_os("DISPLAY").is_some() {
+        ("xclip", &["-selection", "clipboard"])
+    } else {
+        return None;
+    };
+    let mut child = Command::new(tool)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|err| tracing::debug!("Could not run {tool}: {err}"))
+        .ok()?;
+    let mut stdin = child.stdin.take()?;
+    let text = text.to_string();
+    // Fed and waited for on a thread of its own so a slow tool can't hold up the screen
+    thread::spawn(move || {
+        if let Err(err) = stdin.write_all(text.as_bytes()) {
+            tracing::debug!("Could not hand the text to {tool}: {err}");
+        }
+        // Closing stdin lets the tool know it has everything
+        drop(stdin);
+        match child.wait() {
+            Ok(status) if !status.success() => tracing::debug!("{tool} failed: {status}"),
+            Ok(_) => {},
+            Err(err) => tracing::debug!("Could not wait for {tool}: {err}"),
+        }
+    });
+    Some(tool)
+}
+
+/// A fenced code block in a Markdown reply.
+pub struct CodeBlock {
+    /// Line of the reply the opening fence is on
+    pub line: usize,
+    pub code: String,
+}
+
+/// Every fenced code block in `markdown`, in order.
+pub fn code_blocks(markdown: &str) -> Vec<CodeBlock> {
+    let mut blocks = Vec::new();
+    // The fence that opened the block being read, where it was and the lines so far
+    let mut open: Option<(&str, usize, Vec<&str>)> = None;
+    for (index, line) in markdown.lines().enumerate() {
+        match open.take() {
+            None => open = fence(line).map(|fence| (fence, index, Vec::new())),
+            Some((opening, start, lines)) if fence(line).is_some_and(|closing| closing.starts_with(opening) && line.trim() == closing) => {
+                blocks.push(CodeBlock { line: start, code: lines.join("\n") });
+            },
+            Some((opening, start, mut lines)) => {
+                lines.push(line);
+                open = Some((opening, start, lines));
+            },
+        }
+    }
+    // A reply cut off inside a block still has that much of it
+    if let Some((_, start, lines)) = open {
+        blocks.push(CodeBlock { line: start, code: lines.join("\n") });
+    }
+    blocks
+}
+
+// The run of three or more backticks or tildes starting a line, if there is one.
+fn fence(line: &str) -> Option<&str> {
+    let trimmed = line.trim_start();
+    let marker = trimmed.chars().next().filter(|&c| c == '`' || c == '~')?;
+    let length = trimmed.chars().take_while(|&c| c == marker).count();
+    (length >= 3).then(|| &trimmed[..length])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_fenced_blocks() {
+        let reply = "Try this:\n```python\nprint(1)\n\nprint(2)\n```\nor\n~~~\nls\n~~~\n````\nnested ```\n````\n```\nunfinished";
+        let blocks = code_blocks(reply);
+        let code: Vec<&str> = blocks.iter().map(|block| block.code.as_str()).collect();
+        assert_eq!(code, ["print(1)\n\nprint(2)", "ls", "nested ```", "unfinished"]);
+        let lines: Vec<usize> = blocks.iter().map(|block| block.line).collect();
+        assert_eq!(lines, [1, 7, 10, 13]);
+    }
+}
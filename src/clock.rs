@@ -0,0 +1,73 @@
+use std::{cell::Cell, rc::Rc, time::{Duration, Instant}};
+
+/// Source of monotonic time for everything that animates.
+///
+/// `now` is the time elapsed since the clock was created, so callers only
+/// ever deal in durations and never in wall-clock timestamps.
+pub trait Clock {
+    fn now(&self) -> Duration;
+}
+
+pub type SharedClock = Rc<dyn Clock>;
+
+/// Clock backed by `Instant`, used when running the real app.
+pub struct SystemClock {
+    start: Instant,
+}
+
+impl SystemClock {
+    pub fn new() -> Self {
+        Self { start: Instant::now() }
+    }
+
+    pub fn shared() -> SharedClock {
+        Rc::new(Self::new())
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        self.start.elapsed()
+    }
+}
+
+/// Clock that only moves when told to, for tests and headless playback.
+#[derive(Default)]
+pub struct ManualClock {
+    now: Cell<Duration>,
+}
+
+impl ManualClock {
+    pub fn new() -> Rc<Self> {
+        Rc::new(Self::default())
+    }
+
+    pub fn advance(&self, by: Duration) {
+        self.now.set(self.now.get() + by);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Duration {
+        self.now.get()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn manual_clock_only_moves_when_advanced() {
+        let clock = ManualClock::new();
+        let shared: SharedClock = clock.clone();
+        assert_eq!(shared.now(), Duration::ZERO);
+        std::thread::sleep(Duration::from_millis(5));
+        assert_eq!(shared.now(), Duration::ZERO);
+
+        clock.advance(Duration::from_millis(16));
+        clock.advance(Duration::from_millis(16));
+        assert_eq!(shared.now(), Duration::from_millis(32));
+        assert_eq!(clock.now(), shared.now());
+    }
+}
@@ -0,0 +1,196 @@
+use std::path::PathBuf;
+
+use crate::{action::{Action, CopyTarget}, layout::{self, Preset}};
+
+pub struct Command {
+    pub name: &'static str,
+    /// Shown after the name as a hint, e.g. `[file]`.
+    pub args: &'static str,
+    pub help: &'static str,
+    parse: fn(&str) -> Result<Action, String>,
+}
+
+fn no_args(args: &str, action: Action) -> Result<Action, String> {
+    match args {
+        "" => Ok(action),
+        _ => Err("takes no arguments".to_string()),
+    }
+}
+
+pub const COMMANDS: &[Command] = &[
+    Command {
+        name: "help",
+        args: "",
+        help: "List the available commands",
+        parse: |args| no_args(args, Action::Help),
+    },
+    Command {
+        name: "clear",
+        args: "",
+        help: "Clear the conversation and start afresh",
+        parse: |args| no_args(args, Action::Clear),
+    },
+    Command {
+        name: "save",
+        args: "[file]",
+        help: "Save the conversation as Markdown",
+        parse: |args| Ok(Action::Save((!args.is_empty()).then(|| PathBuf::from(args)))),
+    },
+    Command {
+        name: "quit",
+        args: "",
+        help: "Leave STEMM GPT",
+        parse: |args| no_args(args, Action::Quit),
+    },
+    Command {
+        name: "speed",
+        args: "[chars per second]",
+        help: "Show or set how fast replies are typed out",
+        parse: |args| match args {
+            "" => Ok(Action::Speed(None)),
+            _ => match args.parse() {
+                Ok(speed) if (1..=1000).contains(&speed) => Ok(Action::Speed(Some(speed))),
+                _ => Err(format!("expected a number of characters per second from 1 to 1000, got '{args}'")),
+            },
+        },
+    },
+    Command {
+        name: "theme",
+        args: "[name]",
+        help: "List the colour themes or switch to one",
+        parse: |args| Ok(Action::Theme((!args.is_empty()).then(|| args.to_string()))),
+    },
+    Command {
+        name: "layout",
+        args: "[name]",
+        help: "List the pane layouts or switch to one",
+        parse: |args| match args {
+            "" => Ok(Action::Layout(None)),
+            name => match Preset::from_name(name) {
+                Some(preset) => Ok(Action::Layout(Some(preset))),
+                None => {
+                    let names: Vec<&str> = layout::PRESETS.iter().map(|preset| preset.name()).collect();
+                    Err(format!("unknown layout '{name}', expected one of {}", names.join(", ")))
+                },
+            },
+        },
+    },
+    Command {
+        name: "copy",
+        args: "[n | code [n]]",
+        help: "Copy the nth last reply or a numbered code block",
+        parse: |args| {
+            let number = |text: &str| match text.parse() {
+                Ok(n) if n >= 1 => Ok(n),
+                _ => Err(format!("expected a number from 1 up, got '{text}'")),
+            };
+            match args.split_whitespace().collect::<Vec<_>>()[..] {
+                [] => Ok(Action::Copy(CopyTarget::Reply(1))),
+                ["code"] => Ok(Action::Copy(CopyTarget::Code(None))),
+                ["code", n] => Ok(Action::Copy(CopyTarget::Code(Some(number(n)?)))),
+                [n] => Ok(Action::Copy(CopyTarget::Reply(number(n)?))),
+                _ => Err("expected a reply number or code and a block number".to_string()),
+            }
+        },
+    },
+];
+
+impl Command {
+    /// e.g. `/save [file]`
+    pub fn usage(&self) -> String {
+        match self.args {
+            "" => format!("/{}", self.name),
+            args => format!("/{} {args}", self.name),
+        }
+    }
+}
+
+/// Interprets a line starting with `/`, or returns `None` if `input` is an ordinary prompt.
+///
+/// `//` escapes a leading slash so prompts can still start with one.
+pub fn parse(input: &str) -> Option<Result<Action, String>> {
+    let line = input.trim();
+    let rest = line.strip_prefix('/')?;
+    if rest.starts_with('/') {
+        return None;
+    }
+    let (name, args) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+    Some(match COMMANDS.iter().find(|command| command.name == name) {
+        Some(command) => (command.parse)(args.trim()).map_err(|err| format!("{}: {err}", command.usage())),
+        None => Err(format!("Unknown command /{name}. Type /help for the list.")),
+    })
+}
+
+/// What to send the model for input that is not a command, where a leading
+/// `//` stands for a prompt that really does start with a slash.
+pub fn prompt(input: &str) -> String {
+    match input.strip_prefix("//") {
+        Some(rest) => format!("/{rest}"),
+        None => input.to_string(),
+    }
+}
+
+/// Commands to offer while the input looks like the start of one.
+///
+/// Once a space has been typed only the command being completed is returned,
+/// so the popup can show its arguments.
+pub fn completions(input: &str) -> Vec<&'static Command> {
+    let Some(rest) = input.strip_prefix('/') else {
+        return Vec::new();
+    };
+    if input.contains('\n') || rest.starts_with('/') {
+        return Vec::new();
+    }
+    match rest.split_once(' ') {
+        Some((name, _)) => COMMANDS.iter().filter(|command| command.name == name).collect(),
+        None => COMMANDS.iter().filter(|command| command.name.starts_with(rest)).collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_commands_and_their_arguments() {
+        let cases: &[(&str, Result<Action, &str>)] = &[
+            ("/clear", Ok(Action::Clear)),
+            ("  /help  ", Ok(Action::Help)),
+            ("/save", Ok(Action::Save(None))),
+            ("/save notes/chat.md", Ok(Action::Save(Some(PathBuf::from("notes/chat.md"))))),
+            ("/speed", Ok(Action::Speed(None))),
+            ("/speed 40", Ok(Action::Speed(Some(40)))),
+            ("/speed 0", Err("/speed [chars per second]: expected a number of characters per second from 1 to 1000, got '0'")),
+            ("/layout", Ok(Action::Layout(None))),
+            ("/copy", Ok(Action::Copy(CopyTarget::Reply(1)))),
+            ("/copy 2", Ok(Action::Copy(CopyTarget::Reply(2)))),
+            ("/copy code", Ok(Action::Copy(CopyTarget::Code(None)))),
+            ("/copy code 3", Ok(Action::Copy(CopyTarget::Code(Some(3))))),
+            ("/copy 0", Err("/copy [n | code [n]]: expected a number from 1 up, got '0'")),
+            ("/clear now", Err("/clear: takes no arguments")),
+            ("/frobnicate", Err("Unknown command /frobnicate. Type /help for the list.")),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(parse(input), Some(expected.clone().map_err(str::to_string)), "{input}");
+        }
+    }
+
+    #[test]
+    fn ordinary_prompts_are_not_commands() {
+        for input in ["what is /dev/null?", "//clear", "", "clear"] {
+            assert_eq!(parse(input), None, "{input}");
+        }
+        assert_eq!(prompt("//clear means what?"), "/clear means what?");
+        assert_eq!(prompt("a // b"), "a // b");
+    }
+
+    #[test]
+    fn completes_command_names_then_shows_arguments() {
+        let names = |input| completions(input).iter().map(|command| command.name).collect::<Vec<_>>();
+        assert_eq!(names("/s"), ["save", "speed"]);
+        assert_eq!(names("/copy co"), ["copy"]);
+        assert!(names("hello").is_empty());
+        assert!(names("//s").is_empty());
+        assert!(names("/s\nmore").is_empty());
+    }
+}
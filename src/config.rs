@@ -0,0 +1,436 @@
+use std::{env, fs, io, path::{Path, PathBuf}};
+
+use clap::{Parser, Subcommand};
+use color_eyre::{eyre::{bail, eyre, WrapErr}, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{animation::AvatarStyle, ask::AskArgs, audio::Visualization, export::ExportArgs, layout::{self, Preset, Split}, paths, replay::ReplayArgs, vim::KeyMode};
+
+/// Environment variables starting with this override settings, e.g. `STEMMGPT_UI_TICK_MS=33`.
+const ENV_PREFIX: &str = "STEMMGPT_";
+
+/// `config.toml` in the config directory.
+pub fn default_path() -> Option<PathBuf> {
+    paths::config_dir().map(|dir| dir.join("config.toml"))
+}
+
+#[derive(Parser)]
+#[command(name = "stemmgpt", version, about = "A terminal chat with a STEM assistant")]
+pub struct Cli {
+    /// Read settings from FILE instead of $XDG_CONFIG_HOME/stemmgpt/config.toml
+    #[arg(long, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+    /// Change any setting, e.g. --set ui.tick_ms=33 (may be repeated)
+    #[arg(long = "set", value_name = "SECTION.KEY=VALUE")]
+    pub overrides: Vec<String>,
+    /// Milliseconds between characters as replies are typed out
+    #[arg(long, value_name = "MS")]
+    pub char_delay_ms: Option<u64>,
+    /// Colour theme, built in or from the themes directory
+    #[arg(long, value_name = "NAME")]
+    pub theme: Option<String>,
+    /// Name shown in the status bar, the time the app started by default
+    #[arg(long, value_name = "NAME")]
+    pub session: Option<String>,
+    /// Log the session to FILE so it can be played back with `replay`
+    #[arg(long, value_name = "FILE")]
+    pub record: Option<PathBuf>,
+    /// Keep the avatar quiet
+    #[arg(long)]
+    pub mute: bool,
+    /// Print the settings that would be used and exit
+    #[arg(long)]
+    pub print_config: bool,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Ask one question and print the reply, for scripts and pipes
+    Ask(AskArgs),
+    /// Record the avatar to an asciinema cast or a GIF
+    Export(ExportArgs),
+    /// Play back a session saved with --record
+    Replay(ReplayArgs),
+}
+
+/// Every setting, once defaults, the config file, `STEMMGPT_*` variables and
+/// command line flags have been applied in that order.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// The file settings changed in the app are saved to
+    #[serde(skip)]
+    pub path: Option<PathBuf>,
+    /// Name of this run of the app, which only comes from the command line
+    #[serde(skip)]
+    pub session: Option<String>,
+    pub files: FileSettings,
+    pub typewriter: TypewriterSettings,
+    pub ui: UiSettings,
+    pub avatar: AvatarSettings,
+    pub clipboard: ClipboardSettings,
+    pub log: LogSettings,
+}
+
+/// The files used to talk to `ai.py`.
+///
+/// `ai.py` only reads the `STEMMGPT_FILES_*` variables, so those are the only
+/// way to move them; the config file and `--set` are turned down.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FileSettings {
+    pub input: PathBuf,
+    pub output: PathBuf,
+    pub memory: PathBuf,
+    /// Created by `ai.py` once the model has loaded
+    pub ready: PathBuf,
+    /// Created to make `ai.py` forget the conversation, and removed by it once it has
+    pub reset: PathBuf,
+}
+
+impl Default for FileSettings {
+    fn default() -> Self {
+        Self {
+            input: "input.txt".into(),
+            output: "output.txt".into(),
+            memory: "memory.txt".into(),
+            ready: "ready.txt".into(),
+            reset: "reset.txt".into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TypewriterSettings {
+    pub char_delay_ms: u64,
+    /// Longest a message takes to type out, so long replies are typed faster, or 0 for no limit
+    pub max_message_secs: u64,
+    /// Pause after sentences, clauses and lines, as someone typing would
+    pub natural_pacing: bool,
+}
+
+impl Default for TypewriterSettings {
+    fn default() -> Self {
+        Self { char_delay_ms: 75, max_message_secs: 20, natural_pacing: true }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UiSettings {
+    /// How often the screen is redrawn
+    pub tick_ms: u64,
+    pub layout: Preset,
+    /// Percentage of its part of the screen given to the avatar
+    pub avatar_size: u16,
+    /// Percentage of its part of the screen given to the input box
+    pub input_size: u16,
+    /// A built-in theme or the name of a file in the themes directory
+    pub theme: String,
+    /// `vim` for modal Vim-style editing in the input box
+    pub keymode: KeyMode,
+}
+
+impl Default for UiSettings {
+    fn default() -> Self {
+        Self {
+            tick_ms: 16,
+            layout: Preset::Auto,
+            avatar_size: 50,
+            input_size: 35,
+            theme: "classic".to_string(),
+            keymode: KeyMode::Standard,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AvatarSettings {
+    pub style: AvatarStyle,
+    pub visualization: Visualization,
+    /// Whether the avatar babbles out loud while it talks
+    pub sound: bool,
+}
+
+impl Default for AvatarSettings {
+    fn default() -> Self {
+        Self { style: AvatarStyle::Procedural, visualization: Visualization::Oscilloscope, sound: true }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClipboardSettings {
+    /// Also copy with `wl-copy` or `xclip` in case the terminal ignores OSC 52
+    pub local_fallback: bool,
+}
+
+impl Default for ClipboardSettings {
+    fn default() -> Self {
+        Self { local_fallback: true }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogSettings {
+    /// Which records to keep, e.g. `debug` or `info,your_terminal_app::backend=trace`
+    pub filter: String,
+    /// Days of log files kept in the state directory, one file a day
+    pub keep_days: usize,
+}
+
+impl Default for LogSettings {
+    fn default() -> Self {
+        Self { filter: "info".to_string(), keep_days: 7 }
+    }
+}
+
+impl Config {
+    pub fn load(cli: &Cli) -> Result<Self> {
+        Self::load_with(cli, env::vars())
+    }
+
+    // `load`, with the environment passed in.
+    fn load_with(cli: &Cli, vars: impl IntoIterator<Item = (String, String)>) -> Result<Self> {
+        let mut settings = toml::Table::try_from(Self::default())?;
+
+        // A missing default file is fine, a missing --config file is not
+        if let Some(path) = cli.config.clone().or_else(default_path) {
+            match fs::read_to_string(&path) {
+                Ok(text) => {
+                    // Parsed on its own first so mistakes are reported against the file
+                    toml::from_str::<Self>(&text).wrap_err_with(|| format!("Invalid settings in {}", path.display()))?;
+                    let table: toml::Table = text.parse()?;
+                    if table.contains_key("files") {
+                        bail!("Invalid settings in {}: [files] can only be set with STEMMGPT_FILES_* variables, the ones ai.py reads", path.display());
+                    }
+                    merge(&mut settings, table);
+                },
+                Err(err) if err.kind() == io::ErrorKind::NotFound && cli.config.is_none() => {},
+                Err(err) => bail!("Could not read {}: {err}", path.display()),
+            }
+        }
+
+        let mut overrides = toml::Table::new();
+        for (name, value) in vars {
+            let Some(key) = name.strip_prefix(ENV_PREFIX).map(str::to_lowercase) else {
+                continue;
+            };
+            // Other STEMMGPT_ variables are not settings
+            let Some(section) = settings.keys().find(|section| key.starts_with(&format!("{section}_"))) else {
+                continue;
+            };
+            set(&mut overrides, section, &key[section.len() + 1..], &value);
+        }
+        for setting in &cli.overrides {
+            let (key, value) = setting.split_once('=')
+                .ok_or_else(|| eyre!("--set {setting}: expected SECTION.KEY=VALUE"))?;
+            let (section, field) = key.trim().split_once('.')
+                .ok_or_else(|| eyre!("--set {setting}: expected SECTION.KEY=VALUE, e.g. ui.tick_ms=33"))?;
+            if section.trim() == "files" {
+                bail!("--set {setting}: files.* can only be set with STEMMGPT_FILES_* variables, the ones ai.py reads");
+            }
+            set(&mut overrides, section, field, value.trim());
+        }
+        if let Some(delay) = cli.char_delay_ms {
+            set(&mut overrides, "typewriter", "char_delay_ms", &delay.to_string());
+        }
+        if let Some(theme) = &cli.theme {
+            set(&mut overrides, "ui", "theme", theme);
+        }
+        if cli.mute {
+            set(&mut overrides, "avatar", "sound", "false");
+        }
+        merge(&mut settings, overrides);
+
+        let mut config: Self = settings.try_into()
+            .wrap_err("Invalid settings in STEMMGPT_* variables or command line flags")?;
+        config.validate()?;
+        config.path = cli.config.clone().or_else(default_path);
+        config.session = Some(cli.session.clone().unwrap_or_else(|| chrono::Local::now().format("%Y-%m-%d %H:%M").to_string()));
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<()> {
+        if !(1..=1000).contains(&self.typewriter.char_delay_ms) {
+            bail!("typewriter.char_delay_ms must be from 1 to 1000, got {}", self.typewriter.char_delay_ms);
+        }
+        if self.typewriter.max_message_secs > 600 {
+            bail!("typewriter.max_message_secs must be at most 600, got {}", self.typewriter.max_message_secs);
+        }
+        if !(1..=1000).contains(&self.ui.tick_ms) {
+            bail!("ui.tick_ms must be from 1 to 1000, got {}", self.ui.tick_ms);
+        }
+        if let Err(err) = tracing_subscriber::EnvFilter::try_new(&self.log.filter) {
+            bail!("log.filter is not a valid filter: {err}");
+        }
+        if !(1..=365).contains(&self.log.keep_days) {
+            bail!("log.keep_days must be from 1 to 365, got {}", self.log.keep_days);
+        }
+        for (name, percent) in [("ui.avatar_size", self.ui.avatar_size), ("ui.input_size", self.ui.input_size)] {
+            if !(layout::MIN_SIZE..=layout::MAX_SIZE).contains(&percent) {
+                bail!("{name} is a percentage from {} to {}, got {percent}", layout::MIN_SIZE, layout::MAX_SIZE);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A layout setting that can be changed from within the app.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LayoutSetting {
+    Preset,
+    Size(Split),
+}
+
+/// Writes the layout setting just changed to the config file, leaving the rest
+/// of it as it was. Other settings may have come from `STEMMGPT_*` variables or
+/// the command line, which are meant to last only as long as the app.
+pub fn save_layout(path: &Path, ui: &UiSettings, changed: LayoutSetting) -> Result<()> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(err) => bail!("Could not read {}: {err}", path.display()),
+    };
+    let mut document: toml_edit::DocumentMut = text.parse()
+        .wrap_err_with(|| format!("Could not update {}", path.display()))?;
+    let section = document.entry("ui").or_insert_with(toml_edit::table);
+    let Some(section) = section.as_table_like_mut() else {
+        bail!("Could not update {}: ui is not a table", path.display());
+    };
+    match changed {
+        LayoutSetting::Preset => section.insert("layout", toml_edit::value(ui.layout.name())),
+        LayoutSetting::Size(Split::Avatar) => section.insert("avatar_size", toml_edit::value(i64::from(ui.avatar_size))),
+        LayoutSetting::Size(Split::Input) => section.insert("input_size", toml_edit::value(i64::from(ui.input_size))),
+    };
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, document.to_string()).wrap_err_with(|| format!("Could not write {}", path.display()))
+}
+
+// Sets `section.field`, reading `value` as TOML where it can be, so `33` is a number
+// and `false` a boolean, and as a plain string otherwise.
+fn set(table: &mut toml::Table, section: &str, field: &str, value: &str) {
+    let value = format!("value = {value}").parse::<toml::Table>().ok()
+        .and_then(|mut parsed| parsed.remove("value"))
+        .unwrap_or_else(|| toml::Value::String(value.to_string()));
+    let section = table.entry(section).or_insert_with(|| toml::Value::Table(toml::Table::new()));
+    if let Some(section) = section.as_table_mut() {
+        section.insert(field.to_string(), value);
+    }
+}
+
+// Copies every setting in `overlay` over `base`, section by section.
+fn merge(base: &mut toml::Table, overlay: toml::Table) {
+    for (key, value) in overlay {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base)), toml::Value::Table(overlay)) => base.extend(overlay),
+            (_, value) => {
+                base.insert(key, value);
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A config file with `contents` in a directory of its own.
+    fn config_file(name: &str, contents: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("stemmgpt-config-test-{}-{name}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.toml");
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn load(path: &Path, args: &[&str], vars: &[(&str, &str)]) -> Result<Config> {
+        let cli = Cli::try_parse_from(["stemmgpt", "--config", path.to_str().unwrap()].iter().chain(args))?;
+        Config::load_with(&cli, vars.iter().map(|(name, value)| (name.to_string(), value.to_string())))
+    }
+
+    #[test]
+    fn later_layers_win() {
+        let path = config_file("layers", concat!(
+            "[typewriter]\nchar_delay_ms = 40\nmax_message_secs = 5\n",
+            "[ui]\ntick_ms = 20\navatar_size = 60\ntheme = \"mono\"\n",
+        ));
+        let config = load(
+            &path,
+            &["--set", "ui.tick_ms=30", "--set", "typewriter.max_message_secs = 7", "--set", "typewriter.char_delay_ms=45", "--char-delay-ms", "50", "--mute"],
+            &[("STEMMGPT_UI_TICK_MS", "25"), ("STEMMGPT_UI_INPUT_SIZE", "40"), ("STEMMGPT_FILES_OUTPUT", "/tmp/reply.txt"), ("STEMMGPT_UI_KEYMODE", "vim"), ("STEMMGPT_COLUMNS", "80")],
+        ).unwrap();
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+
+        // Defaults
+        assert!(config.typewriter.natural_pacing);
+        assert_eq!(config.files.input, PathBuf::from("input.txt"));
+        // The file
+        assert_eq!(config.ui.avatar_size, 60);
+        assert_eq!(config.ui.theme, "mono");
+        // The environment
+        assert_eq!(config.ui.input_size, 40);
+        assert_eq!(config.files.output, PathBuf::from("/tmp/reply.txt"));
+        assert_eq!(config.ui.keymode, KeyMode::Vim);
+        // --set over the environment, and flags over --set
+        assert_eq!(config.ui.tick_ms, 30);
+        assert_eq!(config.typewriter.max_message_secs, 7);
+        assert_eq!(config.typewriter.char_delay_ms, 50);
+        assert!(!config.avatar.sound);
+        assert_eq!(config.path.as_deref(), Some(path.as_path()));
+    }
+
+    #[test]
+    fn files_only_come_from_the_environment() {
+        let path = config_file("files", "[files]\ninput = \"elsewhere.txt\"\n");
+        let err = load(&path, &[], &[]).unwrap_err();
+        assert!(err.to_string().contains("STEMMGPT_FILES_"), "{err}");
+
+        let path = config_file("files", "");
+        let err = load(&path, &["--set", "files.input=elsewhere.txt"], &[]).unwrap_err();
+        assert!(err.to_string().contains("STEMMGPT_FILES_"), "{err}");
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn saves_only_the_layout_setting_changed() {
+        let path = config_file("save", "# Mine\n[ui]\ntheme = \"dark\"\navatar_size = 40\n");
+        let config = load(&path, &["--set", "ui.input_size=60"], &[("STEMMGPT_UI_LAYOUT", "zen"), ("STEMMGPT_UI_TICK_MS", "33")]).unwrap();
+        let mut ui = config.ui;
+        ui.avatar_size = 55;
+        save_layout(&path, &ui, LayoutSetting::Size(Split::Avatar)).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "# Mine\n[ui]\ntheme = \"dark\"\navatar_size = 55\n");
+
+        ui.layout = Preset::Stacked;
+        save_layout(&path, &ui, LayoutSetting::Preset).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "# Mine\n[ui]\ntheme = \"dark\"\navatar_size = 55\nlayout = \"stacked\"\n");
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn rejects_settings_out_of_range() {
+        let path = config_file("validate", "");
+        for setting in [
+            "typewriter.char_delay_ms=0",
+            "typewriter.max_message_secs=601",
+            "ui.tick_ms=1001",
+            "ui.avatar_size=5",
+            "ui.input_size=100",
+            "log.filter=[nonsense",
+            "log.keep_days=0",
+        ] {
+            let err = load(&path, &["--set", setting], &[]).unwrap_err();
+            assert!(err.to_string().starts_with(setting.split_once('=').unwrap().0), "{setting}: {err}");
+        }
+        assert!(load(&path, &["--set", "ui.tick_ms"], &[]).is_err());
+        assert!(load(&path, &["--set", "ui.colour=red"], &[]).is_err());
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+}
@@ -0,0 +1,169 @@
+//! Crash reports: if the app panics, the terminal is put back and what went
+//! wrong is written to a file along with the last things that happened.
+
+use std::{
+    backtrace::Backtrace,
+    collections::VecDeque,
+    fmt::Write as _,
+    fs,
+    io,
+    panic::{self, PanicHookInfo},
+    path::PathBuf,
+    sync::Mutex,
+};
+
+use crossterm::event::{Event, KeyCode, KeyEventKind, KeyModifiers};
+
+use crate::{keymap::Key, paths, terminal};
+
+/// How many recent events a report includes.
+const RECENT: usize = 200;
+
+static RECENT_EVENTS: RecentEvents = RecentEvents::new();
+
+/// The last things that happened, oldest first.
+struct RecentEvents(Mutex<VecDeque<String>>);
+
+impl RecentEvents {
+    const fn new() -> Self {
+        Self(Mutex::new(VecDeque::new()))
+    }
+
+    fn note(&self, entry: impl Into<String>) {
+        let entry = format!("{} {}", chrono::Local::now().format("%H:%M:%S%.3f"), entry.into());
+        let mut events = self.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if events.len() == RECENT {
+            events.pop_front();
+        }
+        events.push_back(entry);
+    }
+}
+
+/// Adds `entry` to the recent events, dropping the oldest once there are enough.
+pub fn note(entry: impl Into<String>) {
+    RECENT_EVENTS.note(entry);
+}
+
+/// Notes a terminal event, leaving out what was typed or pasted beyond its size.
+pub fn note_event(event: &Event) {
+    if let Some(entry) = describe(event) {
+        note(entry);
+    }
+}
+
+fn describe(event: &Event) -> Option<String> {
+    Some(match event {
+        Event::Key(key) if key.kind == KeyEventKind::Press => {
+            // Characters, including AltGr ones sent as Ctrl+Alt, only by what they are
+            let chord = key.modifiers & (KeyModifiers::CONTROL | KeyModifiers::ALT);
+            match key.code {
+                KeyCode::Char(_) if chord.is_empty() || chord == KeyModifiers::CONTROL | KeyModifiers::ALT => "key <char>".to_string(),
+                _ => format!("key {}", Key::from(*key)),
+            }
+        },
+        Event::Key(_) => return None,
+        Event::Mouse(mouse) => format!("mouse {:?} at {},{}", mouse.kind, mouse.column, mouse.row),
+        Event::Paste(text) => format!("paste of {} characters", text.chars().count()),
+        Event::Resize(width, height) => format!("resize to {width}x{height}"),
+        Event::FocusGained => "focus gained".to_string(),
+        Event::FocusLost => "focus lost".to_string(),
+    })
+}
+
+/// Puts the terminal back and writes a crash report before any panic hook
+/// already installed, such as color-eyre's, prints the panic.
+pub fn install_hook() {
+    let previous = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        terminal::restore();
+        let report = report(info);
+        previous(info);
+        match write(&report) {
+            Ok(path) => eprintln!("A crash report was written to {}", path.display()),
+            Err(err) => eprintln!("Could not write a crash report: {err}"),
+        }
+    }));
+}
+
+fn report(info: &PanicHookInfo) -> String {
+    let message = match (info.payload().downcast_ref::<&str>(), info.payload().downcast_ref::<String>()) {
+        (Some(message), _) => message,
+        (_, Some(message)) => message.as_str(),
+        _ => "(no message)",
+    };
+    let location = info.location().map_or("an unknown place".to_string(), ToString::to_string);
+    tracing::error!("Panicked at {location}: {message}");
+    format_report(message, &location, &Backtrace::force_capture(), &RECENT_EVENTS)
+}
+
+fn format_report(message: &str, location: &str, backtrace: &Backtrace, events: &RecentEvents) -> String {
+    let mut report = String::new();
+    let _ = writeln!(report, "STEMM GPT {} crashed at {}", env!("CARGO_PKG_VERSION"), chrono::Local::now().to_rfc3339());
+    let _ = writeln!(report, "\nPanicked at {location}:\n{message}");
+    let _ = writeln!(report, "\nRecent events, oldest first:");
+    // The panic may have happened while noting an event, so don't wait for the lock
+    match events.0.try_lock() {
+        Ok(events) => events.iter().for_each(|event| {
+            let _ = writeln!(report, "  {event}");
+        }),
+        Err(_) => report.push_str("  (unavailable)\n"),
+    }
+    let _ = writeln!(report, "\nBacktrace:\n{backtrace}");
+    report
+}
+
+// Saved in the state directory, or the temporary one if there is none.
+fn write(report: &str) -> io::Result<PathBuf> {
+    let dir = paths::state_dir().unwrap_or_else(std::env::temp_dir);
+    fs::create_dir_all(&dir)?;
+    let path = dir.join(chrono::Local::now().format("crash-%Y%m%d-%H%M%S.txt").to_string());
+    fs::write(&path, report)?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use crossterm::event::KeyEvent;
+
+    use super::*;
+
+    fn noted(events: &[Event]) -> RecentEvents {
+        let recent = RecentEvents::new();
+        events.iter().filter_map(describe).for_each(|entry| recent.note(entry));
+        recent
+    }
+
+    #[test]
+    fn notes_characters_without_saying_which() {
+        let key = |code, modifiers| Event::Key(KeyEvent::new(code, modifiers));
+        let recent = noted(&[
+            key(KeyCode::Char('Q'), KeyModifiers::SHIFT),
+            key(KeyCode::Char('@'), KeyModifiers::CONTROL | KeyModifiers::ALT),
+            key(KeyCode::Enter, KeyModifiers::NONE),
+            key(KeyCode::Char('x'), KeyModifiers::ALT),
+        ]);
+        let events: Vec<String> = recent.0.into_inner().unwrap().into_iter()
+            .map(|event| event.split_once(' ').unwrap().1.to_string())
+            .collect();
+        assert_eq!(events, ["key <char>", "key <char>", "key Enter", "key Alt+x"]);
+    }
+
+    #[test]
+    fn reports_recent_events_without_what_was_typed() {
+        let recent = noted(&[
+            Event::Key(KeyEvent::new(KeyCode::Char('z'), KeyModifiers::CONTROL)),
+            Event::Paste("my password".to_string()),
+        ]);
+        for number in 0..RECENT {
+            recent.note(format!("filler {number}"));
+        }
+        recent.note("sent prompt of 12 characters");
+
+        let report = format_report("index out of bounds", "src/animation.rs:1:1", &Backtrace::disabled(), &recent);
+        assert!(report.contains("Panicked at src/animation.rs:1:1:\nindex out of bounds"), "{report}");
+        assert!(report.contains("sent prompt of 12 characters"));
+        assert!(!report.contains("filler 0\n"));
+        assert!(!report.contains("password"));
+        assert_eq!(recent.0.lock().unwrap().len(), RECENT);
+    }
+}
@@ -0,0 +1,649 @@
+use unicode_width::UnicodeWidthChar;
+
+const UNDO_LIMIT: usize = 200;
+const KILL_RING_SIZE: usize = 16;
+
+/// Multi-line text buffer behind the input box.
+///
+/// The cursor is kept as a (line, character) pair; byte offsets are only
+/// worked out at the moment the text is modified.
+pub struct Editor {
+    lines: Vec<String>,
+    row: usize,
+    col: usize,
+    undo: Vec<Snapshot>,
+    redo: Vec<Snapshot>,
+    /// Kind of the run of edits in progress; moving the cursor ends it.
+    run: Option<EditKind>,
+    /// Killed text, newest last. Survives `clear` so it can be yanked into the next prompt.
+    kill_ring: Vec<String>,
+    /// Whether the last edit was a kill, in which case the next kill adds to it.
+    killing: bool,
+}
+
+// Consecutive inserts or deletes are undone together; anything else is undone on its own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EditKind {
+    Insert,
+    Delete,
+    Other,
+}
+
+struct Snapshot {
+    lines: Vec<String>,
+    row: usize,
+    col: usize,
+}
+
+/// The text laid out for a box of a given width.
+pub struct Layout {
+    /// Visual lines, already wrapped.
+    pub lines: Vec<String>,
+    /// Cursor position in columns and visual lines.
+    pub cursor: (u16, u16),
+    /// Offset into `Editor::text` of the first character of each visual line.
+    pub starts: Vec<usize>,
+}
+
+impl Layout {
+    /// Offset into `Editor::text` of the character shown at a column of a visual line.
+    pub fn offset_at(&self, column: u16, row: u16) -> usize {
+        let row = (row as usize).min(self.lines.len().saturating_sub(1));
+        let Some(line) = self.lines.get(row) else {
+            return 0;
+        };
+        let mut used = 0;
+        let before = line.chars()
+            .take_while(|c| {
+                used += c.width().unwrap_or(0);
+                used <= column as usize
+            })
+            .count();
+        self.starts[row] + before
+    }
+}
+
+fn byte_index(line: &str, col: usize) -> usize {
+    line.char_indices()
+        .map(|(i, _)| i)
+        .nth(col)
+        .unwrap_or(line.len())
+}
+
+fn char_count(line: &str) -> usize {
+    line.chars().count()
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+impl Editor {
+    pub fn new() -> Self {
+        Self {
+            lines: vec![String::new()],
+            row: 0,
+            col: 0,
+            undo: Vec::new(),
+            redo: Vec::new(),
+            run: None,
+            kill_ring: Vec::new(),
+            killing: false,
+        }
+    }
+
+    pub fn text(&self) -> String {
+        self.lines.join("\n")
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.len() == 1 && self.lines[0].is_empty()
+    }
+
+    /// Empties the buffer and forgets its undo history, keeping the kill ring.
+    pub fn clear(&mut self) {
+        let kill_ring = std::mem::take(&mut self.kill_ring);
+        *self = Self { kill_ring, ..Self::new() };
+    }
+
+    /// Replaces the whole buffer, leaving the cursor at the very end.
+    pub fn set_text(&mut self, text: &str) {
+        self.begin(EditKind::Other);
+        self.load(text, text.chars().count());
+    }
+
+    pub fn on_first_line(&self) -> bool {
+        self.row == 0
+    }
+
+    pub fn on_last_line(&self) -> bool {
+        self.row + 1 == self.lines.len()
+    }
+
+    fn line(&self) -> &str {
+        &self.lines[self.row]
+    }
+
+    pub fn insert_char(&mut self, c: char) {
+        if c == '\n' {
+            return self.insert_newline();
+        }
+        self.begin(EditKind::Insert);
+        let index = byte_index(self.line(), self.col);
+        self.lines[self.row].insert(index, c);
+        self.col += 1;
+    }
+
+    pub fn insert_newline(&mut self) {
+        self.begin(EditKind::Other);
+        let index = byte_index(self.line(), self.col);
+        let rest = self.lines[self.row].split_off(index);
+        self.lines.insert(self.row + 1, rest);
+        self.row += 1;
+        self.col = 0;
+    }
+
+    /// Deletes the character before the cursor, joining lines at the start of one.
+    pub fn backspace(&mut self) {
+        if self.col == 0 && self.row == 0 {
+            return;
+        }
+        self.begin(EditKind::Delete);
+        if self.col > 0 {
+            self.col -= 1;
+            let index = byte_index(self.line(), self.col);
+            self.lines[self.row].remove(index);
+        } else if self.row > 0 {
+            let line = self.lines.remove(self.row);
+            self.row -= 1;
+            self.col = char_count(self.line());
+            self.lines[self.row].push_str(&line);
+        }
+    }
+
+    /// Deletes the character under the cursor, joining lines at the end of one.
+    pub fn delete(&mut self) {
+        if self.cursor() == self.end() {
+            return;
+        }
+        self.begin(EditKind::Delete);
+        if self.col < char_count(self.line()) {
+            let index = byte_index(self.line(), self.col);
+            self.lines[self.row].remove(index);
+        } else if self.row + 1 < self.lines.len() {
+            let next = self.lines.remove(self.row + 1);
+            self.lines[self.row].push_str(&next);
+        }
+    }
+
+    pub fn move_left(&mut self) {
+        self.end_run();
+        if self.col > 0 {
+            self.col -= 1;
+        } else if self.row > 0 {
+            self.row -= 1;
+            self.col = char_count(self.line());
+        }
+    }
+
+    pub fn move_right(&mut self) {
+        self.end_run();
+        if self.col < char_count(self.line()) {
+            self.col += 1;
+        } else if self.row + 1 < self.lines.len() {
+            self.row += 1;
+            self.col = 0;
+        }
+    }
+
+    pub fn move_up(&mut self) {
+        self.end_run();
+        if self.row > 0 {
+            self.row -= 1;
+            self.col = self.col.min(char_count(self.line()));
+        }
+    }
+
+    pub fn move_down(&mut self) {
+        self.end_run();
+        if self.row + 1 < self.lines.len() {
+            self.row += 1;
+            self.col = self.col.min(char_count(self.line()));
+        }
+    }
+
+    pub fn move_home(&mut self) {
+        self.end_run();
+        self.col = 0;
+    }
+
+    pub fn move_end(&mut self) {
+        self.end_run();
+        self.col = char_count(self.line());
+    }
+
+    /// Moves to the start of the previous word, crossing line breaks.
+    pub fn move_word_left(&mut self) {
+        self.end_run();
+        (self.row, self.col) = self.word_start(is_word_char);
+    }
+
+    /// Moves past the end of the next word, crossing line breaks.
+    pub fn move_word_right(&mut self) {
+        self.end_run();
+        (self.row, self.col) = self.word_end();
+    }
+
+    // Start of the word before the cursor, where `in_word` says what a word is made of.
+    fn word_start(&self, in_word: fn(char) -> bool) -> (usize, usize) {
+        if self.col == 0 {
+            return self.before(self.cursor());
+        }
+        let chars: Vec<char> = self.line().chars().collect();
+        let mut col = self.col;
+        while col > 0 && !in_word(chars[col - 1]) {
+            col -= 1;
+        }
+        while col > 0 && in_word(chars[col - 1]) {
+            col -= 1;
+        }
+        (self.row, col)
+    }
+
+    fn word_end(&self) -> (usize, usize) {
+        let chars: Vec<char> = self.line().chars().collect();
+        if self.col == chars.len() {
+            return self.after(self.cursor());
+        }
+        let mut col = self.col;
+        while col < chars.len() && !is_word_char(chars[col]) {
+            col += 1;
+        }
+        while col < chars.len() && is_word_char(chars[col]) {
+            col += 1;
+        }
+        (self.row, col)
+    }
+
+    /// Kills from the cursor to the end of the line, or the line break when already there.
+    pub fn kill_to_end(&mut self) {
+        let end = if self.col < char_count(self.line()) {
+            (self.row, char_count(self.line()))
+        } else {
+            self.after(self.cursor())
+        };
+        self.kill(self.cursor(), end, false);
+    }
+
+    /// Kills from the start of the line to the cursor, or the line break when already there.
+    pub fn kill_to_start(&mut self) {
+        let start = if self.col > 0 { (self.row, 0) } else { self.before(self.cursor()) };
+        self.kill(start, self.cursor(), true);
+    }
+
+    /// Kills back to the previous whitespace, like Ctrl+W in a shell.
+    pub fn kill_word_left(&mut self) {
+        self.kill(self.word_start(|c| !c.is_whitespace()), self.cursor(), true);
+    }
+
+    /// Kills back to the start of the previous word.
+    pub fn delete_word_left(&mut self) {
+        self.kill(self.word_start(is_word_char), self.cursor(), true);
+    }
+
+    /// Kills up to the end of the next word.
+    pub fn delete_word_right(&mut self) {
+        self.kill(self.cursor(), self.word_end(), false);
+    }
+
+    /// Inserts the most recently killed text.
+    pub fn yank(&mut self) {
+        let Some(text) = self.kill_ring.last().cloned() else {
+            return;
+        };
+        self.insert_str(&text);
+    }
+
+    /// Inserts `text` at the cursor as a single undo step, leaving the cursor after it.
+    pub fn insert_str(&mut self, text: &str) {
+        self.begin(EditKind::Other);
+        let offset = self.cursor_offset();
+        let mut all = self.text();
+        all.insert_str(byte_index(&all, offset), text);
+        self.load(&all, offset + text.chars().count());
+    }
+
+    /// Removes the characters between two offsets into `text()` as a single undo step.
+    pub fn delete_range(&mut self, start: usize, end: usize) -> String {
+        self.begin(EditKind::Other);
+        let mut all = self.text();
+        let range = byte_index(&all, start)..byte_index(&all, end);
+        let removed: String = all.drain(range).collect();
+        self.load(&all, start);
+        removed
+    }
+
+    /// The cursor as a character offset into `text()`, line breaks included.
+    pub fn cursor_offset(&self) -> usize {
+        self.offset(self.cursor())
+    }
+
+    pub fn set_cursor_offset(&mut self, offset: usize) {
+        self.end_run();
+        (self.row, self.col) = self.position(offset);
+    }
+
+    /// Reverts the last run of edits, returning false if there was nothing to undo.
+    pub fn undo(&mut self) -> bool {
+        let Some(snapshot) = self.undo.pop() else {
+            return false;
+        };
+        self.redo.push(self.snapshot());
+        self.restore(snapshot);
+        true
+    }
+
+    /// Reapplies the last undone run of edits.
+    pub fn redo(&mut self) -> bool {
+        let Some(snapshot) = self.redo.pop() else {
+            return false;
+        };
+        self.undo.push(self.snapshot());
+        self.restore(snapshot);
+        true
+    }
+
+    fn cursor(&self) -> (usize, usize) {
+        (self.row, self.col)
+    }
+
+    fn end(&self) -> (usize, usize) {
+        (self.lines.len() - 1, char_count(&self.lines[self.lines.len() - 1]))
+    }
+
+    // The position one character before `pos`, counting line breaks as characters.
+    fn before(&self, (row, col): (usize, usize)) -> (usize, usize) {
+        match (row, col) {
+            (0, 0) => (0, 0),
+            (row, 0) => (row - 1, char_count(&self.lines[row - 1])),
+            (row, col) => (row, col - 1),
+        }
+    }
+
+    fn after(&self, (row, col): (usize, usize)) -> (usize, usize) {
+        if col < char_count(&self.lines[row]) {
+            (row, col + 1)
+        } else if row + 1 < self.lines.len() {
+            (row + 1, 0)
+        } else {
+            (row, col)
+        }
+    }
+
+    // Characters before `pos` in `text()`, line breaks included.
+    fn offset(&self, (row, col): (usize, usize)) -> usize {
+        self.lines[..row].iter().map(|line| char_count(line) + 1).sum::<usize>() + col
+    }
+
+    // Replaces the buffer with `text`, putting the cursor `offset` characters in.
+    fn load(&mut self, text: &str, offset: usize) {
+        self.lines = text.split('\n').map(String::from).collect();
+        (self.row, self.col) = self.position(offset);
+    }
+
+    fn position(&self, mut offset: usize) -> (usize, usize) {
+        let mut row = 0;
+        while offset > char_count(&self.lines[row]) && row + 1 < self.lines.len() {
+            offset -= char_count(&self.lines[row]) + 1;
+            row += 1;
+        }
+        (row, offset.min(char_count(&self.lines[row])))
+    }
+
+    fn kill(&mut self, from: (usize, usize), to: (usize, usize), backward: bool) {
+        if from == to {
+            return;
+        }
+        let continuing = self.killing;
+        let killed = self.delete_range(self.offset(from), self.offset(to));
+
+        match self.kill_ring.last_mut() {
+            Some(last) if continuing && backward => last.insert_str(0, &killed),
+            Some(last) if continuing => last.push_str(&killed),
+            _ => {
+                self.kill_ring.push(killed);
+                if self.kill_ring.len() > KILL_RING_SIZE {
+                    self.kill_ring.remove(0);
+                }
+            },
+        }
+        self.killing = true;
+    }
+
+    // Called before every change; saves an undo point unless the change continues the current run.
+    fn begin(&mut self, kind: EditKind) {
+        if kind == EditKind::Other || self.run != Some(kind) {
+            self.undo.push(self.snapshot());
+            if self.undo.len() > UNDO_LIMIT {
+                self.undo.remove(0);
+            }
+        }
+        self.redo.clear();
+        self.run = Some(kind);
+        self.killing = false;
+    }
+
+    fn end_run(&mut self) {
+        self.run = None;
+        self.killing = false;
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            lines: self.lines.clone(),
+            row: self.row,
+            col: self.col,
+        }
+    }
+
+    fn restore(&mut self, snapshot: Snapshot) {
+        self.lines = snapshot.lines;
+        self.row = snapshot.row;
+        self.col = snapshot.col;
+        self.end_run();
+    }
+
+    /// Wraps the text at `width` display columns and finds where the cursor ends up.
+    ///
+    /// Wrapping is per character rather than per word so that the cursor
+    /// position always matches what is drawn, wide characters included.
+    pub fn layout(&self, width: u16) -> Layout {
+        let width = width.max(1) as usize;
+        let mut lines = Vec::new();
+        let mut starts = Vec::new();
+        let mut cursor = (0, 0);
+        let mut offset = 0;
+
+        for (row, line) in self.lines.iter().enumerate() {
+            let mut visual = String::new();
+            let mut used = 0;
+            starts.push(offset);
+            for (col, c) in line.chars().enumerate() {
+                let c_width = c.width().unwrap_or(0);
+                if used + c_width > width && used > 0 {
+                    lines.push(std::mem::take(&mut visual));
+                    starts.push(offset + col);
+                    used = 0;
+                }
+                if row == self.row && col == self.col {
+                    cursor = (used, lines.len());
+                }
+                visual.push(c);
+                used += c_width;
+            }
+            if row == self.row && self.col >= char_count(line) {
+                // A cursor past a full line sits at the start of the next visual line.
+                if used >= width {
+                    lines.push(std::mem::take(&mut visual));
+                    starts.push(offset + char_count(line));
+                    used = 0;
+                }
+                cursor = (used, lines.len());
+            }
+            lines.push(visual);
+            offset += char_count(line) + 1;
+        }
+
+        Layout {
+            lines,
+            cursor: (cursor.0 as u16, cursor.1 as u16),
+            starts,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn typed(text: &str) -> Editor {
+        let mut editor = Editor::new();
+        text.chars().for_each(|c| editor.insert_char(c));
+        editor
+    }
+
+    #[test]
+    fn undo_reverts_a_whole_run_of_typing() {
+        let mut editor = typed("hello");
+        editor.move_left();
+        editor.insert_char('!');
+        assert_eq!(editor.text(), "hell!o");
+
+        assert!(editor.undo());
+        assert_eq!(editor.text(), "hello");
+        assert!(editor.undo());
+        assert_eq!(editor.text(), "");
+        assert!(!editor.undo());
+    }
+
+    #[test]
+    fn deleting_after_typing_starts_a_new_run() {
+        let mut editor = typed("abc");
+        editor.backspace();
+        editor.backspace();
+        assert_eq!(editor.text(), "a");
+        editor.undo();
+        assert_eq!(editor.text(), "abc");
+        assert_eq!(editor.cursor(), (0, 3));
+    }
+
+    #[test]
+    fn redo_reapplies_until_something_new_is_typed() {
+        let mut editor = typed("one");
+        editor.insert_newline();
+        editor.undo();
+        editor.undo();
+        assert!(editor.is_empty());
+        assert!(editor.redo());
+        assert_eq!(editor.text(), "one");
+        assert!(editor.redo());
+        assert_eq!(editor.text(), "one\n");
+
+        editor.undo();
+        editor.insert_char('s');
+        assert!(!editor.redo());
+        assert_eq!(editor.text(), "ones");
+    }
+
+    #[test]
+    fn kill_to_end_then_yank_moves_text() {
+        let mut editor = typed("hello world");
+        editor.move_home();
+        editor.move_word_right();
+        editor.kill_to_end();
+        assert_eq!(editor.text(), "hello");
+        editor.move_home();
+        editor.yank();
+        assert_eq!(editor.text(), " worldhello");
+        assert_eq!(editor.cursor(), (0, 6));
+    }
+
+    #[test]
+    fn kill_to_end_of_line_joins_the_next_line() {
+        let mut editor = typed("a\nb");
+        editor.move_up();
+        editor.move_end();
+        editor.kill_to_end();
+        assert_eq!(editor.text(), "ab");
+        editor.yank();
+        assert_eq!(editor.text(), "a\nb");
+    }
+
+    #[test]
+    fn consecutive_kills_are_yanked_together() {
+        let mut editor = typed("the quick brown fox");
+        editor.kill_word_left();
+        editor.kill_word_left();
+        assert_eq!(editor.text(), "the quick ");
+        editor.yank();
+        assert_eq!(editor.text(), "the quick brown fox");
+
+        // Moving in between starts a fresh kill.
+        editor.move_home();
+        editor.delete_word_right();
+        editor.move_end();
+        editor.kill_to_start();
+        editor.yank();
+        assert_eq!(editor.text(), " quick brown fox");
+    }
+
+    #[test]
+    fn delete_word_left_stops_at_punctuation() {
+        let mut editor = typed("foo.bar_baz");
+        editor.delete_word_left();
+        assert_eq!(editor.text(), "foo.");
+        editor.undo();
+        assert_eq!(editor.text(), "foo.bar_baz");
+    }
+
+    #[test]
+    fn kills_handle_wide_characters() {
+        let mut editor = typed("héllo 世界");
+        editor.kill_word_left();
+        assert_eq!(editor.text(), "héllo ");
+        editor.move_home();
+        editor.yank();
+        assert_eq!(editor.text(), "世界héllo ");
+        assert_eq!(editor.cursor(), (0, 2));
+    }
+
+    #[test]
+    fn clear_keeps_the_kill_ring_but_not_undo() {
+        let mut editor = typed("keep me");
+        editor.kill_to_start();
+        editor.insert_char('x');
+        editor.clear();
+        assert!(!editor.undo());
+        editor.yank();
+        assert_eq!(editor.text(), "keep me");
+    }
+
+    #[test]
+    fn set_text_can_be_undone() {
+        let mut editor = typed("draft");
+        editor.set_text("from\nhistory");
+        assert_eq!(editor.cursor(), (1, 7));
+        editor.undo();
+        assert_eq!(editor.text(), "draft");
+    }
+
+    #[test]
+    fn clicks_map_to_offsets_in_wrapped_lines() {
+        let layout = typed("hello world\nab").layout(6);
+        assert_eq!(layout.lines, ["hello ", "world", "ab"]);
+        assert_eq!(layout.offset_at(2, 1), 8);
+        // Past the end of a line, and below the last one
+        assert_eq!(layout.offset_at(40, 2), 14);
+        assert_eq!(layout.offset_at(1, 9), 13);
+    }
+}
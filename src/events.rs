@@ -0,0 +1,72 @@
+use std::{io, time::Duration};
+
+use crossterm::event::{self, Event};
+
+use crate::terminal;
+
+/// A request from outside the app, by way of a signal, to stop or step aside.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Interrupt {
+    /// SIGINT or SIGTERM
+    Quit,
+    /// SIGTSTP
+    Suspend,
+}
+
+/// Where the app gets key presses, mouse movements and the like from.
+pub trait EventSource {
+    /// The next event, or `None` if nothing happened within `timeout`.
+    fn next(&mut self, timeout: Duration) -> io::Result<Option<Event>>;
+
+    /// A signal that arrived since the last call, if any.
+    fn interrupt(&mut self) -> Option<Interrupt> {
+        None
+    }
+
+    /// Hands the terminal back to the shell until the app is resumed.
+    fn suspend(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Events from the real terminal.
+pub struct TerminalEvents {
+    #[cfg(unix)]
+    signals: signal_hook::iterator::Signals,
+}
+
+impl TerminalEvents {
+    /// Starts catching SIGINT, SIGTERM and SIGTSTP, so the app gets the chance
+    /// to put the terminal back before it stops.
+    pub fn new() -> io::Result<Self> {
+        #[cfg(unix)]
+        {
+            use signal_hook::consts::{SIGINT, SIGTERM, SIGTSTP};
+            Ok(Self { signals: signal_hook::iterator::Signals::new([SIGINT, SIGTERM, SIGTSTP])? })
+        }
+        #[cfg(not(unix))]
+        Ok(Self {})
+    }
+}
+
+impl EventSource for TerminalEvents {
+    fn next(&mut self, timeout: Duration) -> io::Result<Option<Event>> {
+        match event::poll(timeout)? {
+            true => event::read().map(Some),
+            false => Ok(None),
+        }
+    }
+
+    #[cfg(unix)]
+    fn interrupt(&mut self) -> Option<Interrupt> {
+        // Quitting wins over suspending if both are waiting
+        self.signals.pending().fold(None, |interrupt, signal| match signal {
+            signal_hook::consts::SIGTSTP => interrupt.or(Some(Interrupt::Suspend)),
+            _ => Some(Interrupt::Quit),
+        })
+    }
+
+    fn suspend(&mut self) -> io::Result<()> {
+        terminal::suspend()
+    }
+}
@@ -0,0 +1,314 @@
+use std::{borrow::Cow, fs::File, io::{BufWriter, Write}, path::PathBuf, time::Duration};
+
+use color_eyre::{eyre::{bail, eyre, WrapErr}, Result};
+use ratatui::{style::Color, text::Text};
+
+use crate::{animation::{Animation, AvatarStyle}, clock::ManualClock, state::State, theme::Theme};
+
+// X11 misc-fixed 8x13 (public domain): printable ASCII, 16 glyphs per row, 1 bit per pixel.
+static FONT: &[u8] = include_bytes!("fonts/font_8x13.raw");
+const GLYPH_WIDTH: usize = 8;
+const GLYPH_HEIGHT: usize = 13;
+const FONT_ROW_BYTES: usize = 16 * GLYPH_WIDTH / 8;
+/// GIF delays are whole hundredths of a second, and browsers treat less than two as slow.
+const MAX_GIF_FPS: u32 = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Format {
+    Cast,
+    Gif,
+}
+
+/// Arguments of the `export` command.
+#[derive(clap::Args)]
+pub struct ExportArgs {
+    /// Where to write the recording; a .cast or .gif extension picks the format
+    output: PathBuf,
+    /// States to play, each with an optional duration
+    #[arg(long, value_name = "STATE[:DURATION],...", default_value = "idle:2s,blinking,idle:1s,talking:3s")]
+    states: String,
+    /// Size in character cells
+    #[arg(long, value_name = "WIDTHxHEIGHT", default_value = "60x20")]
+    size: String,
+    /// Frames per second, at most 50 for a GIF
+    #[arg(long, default_value_t = 20, value_parser = clap::value_parser!(u32).range(1..))]
+    fps: u32,
+    #[arg(long, value_enum)]
+    format: Option<Format>,
+    #[arg(long, value_enum, default_value = "procedural")]
+    style: AvatarStyle,
+}
+
+/// What to play and where to write it.
+pub struct ExportOptions {
+    pub output: PathBuf,
+    pub format: Format,
+    pub steps: Vec<(State, Duration)>,
+    pub width: usize,
+    pub height: usize,
+    pub fps: u32,
+    pub style: AvatarStyle,
+}
+
+impl ExportOptions {
+    pub fn from_args(args: &ExportArgs) -> Result<Self> {
+        let format = match args.format {
+            Some(format) => format,
+            None => parse_format(args.output.extension().and_then(|ext| ext.to_str()).unwrap_or_default())
+                .wrap_err("cannot tell the format from the file name, pass --format")?,
+        };
+        let (width, height) = parse_size(&args.size)?;
+        if format == Format::Gif && args.fps > MAX_GIF_FPS {
+            bail!("a GIF can play at most {MAX_GIF_FPS} frames a second, got --fps {}", args.fps);
+        }
+        Ok(Self {
+            output: args.output.clone(),
+            format,
+            steps: parse_steps(&args.states)?,
+            width,
+            height,
+            fps: args.fps,
+            style: args.style,
+        })
+    }
+}
+
+fn parse_format(name: &str) -> Result<Format> {
+    match name {
+        "cast" => Ok(Format::Cast),
+        "gif" => Ok(Format::Gif),
+        other => Err(eyre!("unknown format '{other}', expected cast or gif")),
+    }
+}
+
+fn parse_size(size: &str) -> Result<(usize, usize)> {
+    let (width, height) = size.split_once('x')
+        .ok_or_else(|| eyre!("size '{size}' should look like 60x20"))?;
+    let cells = |text: &str| match text.trim().parse::<usize>() {
+        Ok(0) | Err(_) => Err(eyre!("size '{size}' should be two whole numbers above 0, like 60x20")),
+        Ok(cells) => Ok(cells),
+    };
+    Ok((cells(width)?, cells(height)?))
+}
+
+/// Parses `state[:duration]` pairs separated by commas, e.g. `idle:2s,talking:500ms`.
+fn parse_steps(spec: &str) -> Result<Vec<(State, Duration)>> {
+    spec.split(',')
+        .map(|step| {
+            let (name, duration) = step.split_once(':').unwrap_or((step, "2s"));
+            let state = name.trim().parse::<State>().map_err(|err| eyre!(err))?;
+            Ok((state, parse_duration(duration.trim())?))
+        })
+        .collect()
+}
+
+fn parse_duration(text: &str) -> Result<Duration> {
+    let invalid = || eyre!("invalid duration '{text}', use e.g. 2s or 500ms");
+    if let Some(millis) = text.strip_suffix("ms") {
+        return Ok(Duration::from_millis(millis.parse().map_err(|_| invalid())?));
+    }
+    let seconds: f64 = text.strip_suffix('s').unwrap_or(text).parse().map_err(|_| invalid())?;
+    Duration::try_from_secs_f64(seconds).map_err(|_| invalid())
+}
+
+/// One distinct picture of the avatar and how long it stays on screen.
+struct Still {
+    lines: Vec<String>,
+    colour: Color,
+    duration: Duration,
+}
+
+pub fn run(options: &ExportOptions) -> Result<()> {
+    let stills = record(options);
+    let file = File::create(&options.output)
+        .wrap_err_with(|| format!("cannot create {}", options.output.display()))?;
+    let mut out = BufWriter::new(file);
+    match options.format {
+        Format::Cast => write_cast(&mut out, options, &stills)?,
+        Format::Gif => write_gif(&mut out, options, &stills)?,
+    }
+    out.flush()?;
+    println!("Wrote {} frames to {}", stills.len(), options.output.display());
+    Ok(())
+}
+
+// Plays the requested states on a manual clock, merging frames that did not change.
+fn record(options: &ExportOptions) -> Vec<Still> {
+    let clock = ManualClock::new();
+    let mut animation = Animation::silent(clock.clone());
+    animation.set_style(options.style);
+    let theme = Theme::default();
+    let frame_time = Duration::from_secs(1) / options.fps;
+    let mut stills: Vec<Still> = Vec::new();
+
+    for &(state, duration) in &options.steps {
+        animation.show_state(state);
+        let mut elapsed = Duration::ZERO;
+        while elapsed < duration {
+            let lines = text_lines(&animation.frame_text(options.width, options.height));
+            let colour = animation.style(&theme).fg.unwrap_or(Color::White);
+            match stills.last_mut() {
+                Some(last) if last.lines == lines && last.colour == colour => last.duration += frame_time,
+                _ => stills.push(Still { lines, colour, duration: frame_time }),
+            }
+            clock.advance(frame_time);
+            elapsed += frame_time;
+        }
+    }
+    stills
+}
+
+fn text_lines(text: &Text) -> Vec<String> {
+    text.lines.iter()
+        .map(|line| line.spans.iter().map(|span| span.content.as_ref()).collect())
+        .collect()
+}
+
+fn rgb(colour: Color) -> (u8, u8, u8) {
+    match colour {
+        Color::Rgb(r, g, b) => (r, g, b),
+        _ => (255, 255, 255),
+    }
+}
+
+// asciinema v2: a JSON header line followed by one `[time, "o", data]` line per frame.
+fn write_cast(out: &mut impl Write, options: &ExportOptions, stills: &[Still]) -> Result<()> {
+    let header = serde_json::json!({
+        "version": 2,
+        "width": options.width,
+        "height": options.height,
+        "timestamp": chrono::Utc::now().timestamp(),
+        "title": "STEMM GPT",
+    });
+    writeln!(out, "{header}")?;
+    writeln!(out, "{}", serde_json::json!([0.0, "o", "\u{1b}[2J\u{1b}[?25l"]))?;
+
+    let mut time = Duration::ZERO;
+    for still in stills {
+        let (r, g, b) = rgb(still.colour);
+        let data = format!("\u{1b}[H\u{1b}[38;2;{r};{g};{b}m{}\u{1b}[0m", still.lines.join("\r\n"));
+        writeln!(out, "{}", serde_json::json!([time.as_secs_f64(), "o", data]))?;
+        time += still.duration;
+    }
+    writeln!(out, "{}", serde_json::json!([time.as_secs_f64(), "o", "\u{1b}[?25h"]))?;
+    Ok(())
+}
+
+fn write_gif(out: &mut impl Write, options: &ExportOptions, stills: &[Still]) -> Result<()> {
+    let width = options.width * GLYPH_WIDTH;
+    let height = options.height * GLYPH_HEIGHT;
+    if width > u16::MAX as usize || height > u16::MAX as usize {
+        bail!("{}x{} cells is too large for a GIF", options.width, options.height);
+    }
+
+    let mut encoder = gif::Encoder::new(out, width as u16, height as u16, &[])?;
+    encoder.set_repeat(gif::Repeat::Infinite)?;
+    for still in stills {
+        let (r, g, b) = rgb(still.colour);
+        let mut pixels = vec![0u8; width * height];
+        for (row, line) in still.lines.iter().take(options.height).enumerate() {
+            for (column, ch) in line.chars().take(options.width).enumerate() {
+                draw_glyph(&mut pixels, width, column * GLYPH_WIDTH, row * GLYPH_HEIGHT, ch);
+            }
+        }
+
+        let mut frame = gif::Frame {
+            width: width as u16,
+            height: height as u16,
+            buffer: Cow::Owned(pixels),
+            palette: Some(vec![0, 0, 0, r, g, b]),
+            // GIF delays are in hundredths of a second; `--fps` is capped so none is under 2
+            delay: (still.duration.as_millis() / 10).clamp(2, u16::MAX as u128) as u16,
+            ..gif::Frame::default()
+        };
+        frame.dispose = gif::DisposalMethod::Keep;
+        encoder.write_frame(&frame)?;
+    }
+    Ok(())
+}
+
+// Sets the foreground pixels of `ch` in a cell whose top left corner is (x, y).
+fn draw_glyph(pixels: &mut [u8], stride: usize, x: usize, y: usize, ch: char) {
+    let mut plot = |px: usize, py: usize| pixels[(y + py) * stride + x + px] = 1;
+    match ch as u32 {
+        0x20..=0x7e => {
+            let index = ch as usize - 0x20;
+            let (glyph_x, glyph_y) = ((index % 16) * GLYPH_WIDTH, (index / 16) * GLYPH_HEIGHT);
+            for py in 0..GLYPH_HEIGHT {
+                for px in 0..GLYPH_WIDTH {
+                    let bit = glyph_x + px;
+                    if FONT[(glyph_y + py) * FONT_ROW_BYTES + bit / 8] >> (7 - bit % 8) & 1 == 1 {
+                        plot(px, py);
+                    }
+                }
+            }
+        },
+        // Braille: two columns of four dots, bit layout as in `BrailleCanvas`.
+        0x2800..=0x28ff => {
+            let bits = ch as u32 - 0x2800;
+            let dots = [(0, 0, 0x01), (0, 1, 0x02), (0, 2, 0x04), (0, 3, 0x40),
+                        (1, 0, 0x08), (1, 1, 0x10), (1, 2, 0x20), (1, 3, 0x80)];
+            for (column, row, mask) in dots {
+                if bits & mask != 0 {
+                    for (px, py) in [(0, 0), (1, 0), (2, 0), (0, 1), (1, 1), (2, 1)] {
+                        plot(1 + column * 4 + px, 1 + row * 3 + py);
+                    }
+                }
+            }
+        },
+        // Block elements used by older frames.
+        0x2588 => (0..GLYPH_HEIGHT).for_each(|py| (0..GLYPH_WIDTH).for_each(|px| plot(px, py))),
+        0x2592 => (0..GLYPH_HEIGHT).for_each(|py| (0..GLYPH_WIDTH).filter(|px| (px + py) % 2 == 0).for_each(|px| plot(px, py))),
+        _ => {},
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(output: &str, fps: u32) -> ExportArgs {
+        ExportArgs {
+            output: output.into(),
+            states: "idle:2s,blinking,talking:500ms".to_string(),
+            size: "40x12".to_string(),
+            fps,
+            format: None,
+            style: AvatarStyle::Procedural,
+        }
+    }
+
+    #[test]
+    fn parses_sizes_and_steps() {
+        assert_eq!(parse_size("60x20").unwrap(), (60, 20));
+        for size in ["0x20", "60x0", "60", "60x", "axb", "-1x5"] {
+            assert!(parse_size(size).is_err(), "{size}");
+        }
+
+        let steps = parse_steps("idle:2s, blinking ,talking:500ms,thinking:1.5").unwrap();
+        assert_eq!(steps, [
+            (State::IDLE, Duration::from_secs(2)),
+            (State::BLINKING, Duration::from_secs(2)),
+            (State::TALKING, Duration::from_millis(500)),
+            (State::THINKING, Duration::from_millis(1500)),
+        ]);
+        assert!(parse_steps("sleeping:1s").is_err());
+        assert!(parse_steps("idle:soon").is_err());
+        assert!(parse_steps("idle:-1s").is_err());
+    }
+
+    #[test]
+    fn options_from_args() {
+        let options = ExportOptions::from_args(&args("avatar.gif", 20)).unwrap();
+        assert_eq!(options.format, Format::Gif);
+        assert_eq!((options.width, options.height, options.fps), (40, 12, 20));
+        assert_eq!(options.steps.len(), 3);
+
+        assert_eq!(ExportOptions::from_args(&args("avatar.cast", 60)).unwrap().format, Format::Cast);
+        assert!(ExportOptions::from_args(&args("avatar.gif", 60)).is_err());
+        assert!(ExportOptions::from_args(&args("avatar.txt", 20)).is_err());
+        let mut explicit = args("avatar.txt", 20);
+        explicit.format = Some(Format::Cast);
+        assert_eq!(ExportOptions::from_args(&explicit).unwrap().format, Format::Cast);
+    }
+}
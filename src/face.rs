@@ -0,0 +1,229 @@
+use std::f32::consts::PI;
+
+use crate::braille::BrailleCanvas;
+
+// Width of the face relative to its height, in Braille dots (which are roughly square).
+const ASPECT: f32 = 1.3;
+
+const POSITIVE_WORDS: &[&str] = &[
+    "great", "good", "glad", "happy", "love", "awesome", "excellent", "nice", "fun", "cool",
+    "amazing", "perfect", "correct", "yes", "congrats", "congratulations", "brilliant", "easy",
+    "fantastic", "wonderful", "enjoy", "welcome", "thanks", "beautiful", "elegant",
+];
+const NEGATIVE_WORDS: &[&str] = &[
+    "sorry", "unfortunately", "bad", "wrong", "error", "fail", "failed", "cannot", "can't",
+    "impossible", "sad", "hard", "difficult", "problem", "no", "not", "never", "dangerous",
+    "toxic", "death", "disease", "broken", "incorrect", "mistake", "worse", "worst",
+];
+const ANGRY_WORDS: &[&str] = &["stupid", "ridiculous", "nonsense", "seriously", "ugh", "annoying"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mood {
+    Neutral,
+    Happy,
+    Sad,
+    Angry,
+    Surprised,
+}
+
+/// Everything that shapes the face; every number is in -1..1 or 0..1.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FaceParams {
+    /// 0 is closed lips, 1 is wide open.
+    pub mouth_open: f32,
+    /// Where the pupils point, -1..1 on each axis with +y looking down.
+    pub gaze: (f32, f32),
+    pub mood: Mood,
+    /// 0 is wide awake, 1 is eyes shut.
+    pub eyelids: f32,
+}
+
+impl Default for FaceParams {
+    fn default() -> Self {
+        Self {
+            mouth_open: 0.0,
+            gaze: (0.0, 0.0),
+            mood: Mood::Neutral,
+            eyelids: 0.0,
+        }
+    }
+}
+
+/// How wide the mouth should be while saying `c`.
+pub fn mouth_openness(c: char) -> f32 {
+    match c.to_ascii_lowercase() {
+        'a' | 'o' => 1.0,
+        'e' | 'u' => 0.75,
+        'i' | 'y' => 0.55,
+        'm' | 'b' | 'p' => 0.0,
+        'f' | 'v' | 'w' => 0.2,
+        c if c.is_alphanumeric() => 0.35,
+        _ => 0.05,
+    }
+}
+
+/// Guesses a mood from the words in `text`.
+pub fn mood_for(text: &str) -> Mood {
+    let mut score = 0i32;
+    let mut anger = 0;
+    for word in text.split(|c: char| !c.is_alphanumeric() && c != '\'') {
+        let word = word.to_lowercase();
+        if POSITIVE_WORDS.contains(&word.as_str()) {
+            score += 1;
+        } else if NEGATIVE_WORDS.contains(&word.as_str()) {
+            score -= 1;
+        } else if ANGRY_WORDS.contains(&word.as_str()) {
+            anger += 1;
+        }
+    }
+    let exclamations = text.matches('!').count();
+
+    if anger > 0 && score <= 0 {
+        Mood::Angry
+    } else if score >= 2 || (score > 0 && exclamations > 0) {
+        Mood::Happy
+    } else if score <= -2 {
+        Mood::Sad
+    } else if exclamations >= 2 {
+        Mood::Surprised
+    } else {
+        Mood::Neutral
+    }
+}
+
+/// Draws the face described by `params` into a `width` x `height` cell Braille picture.
+pub fn draw(params: &FaceParams, width: usize, height: usize) -> Vec<String> {
+    let mut canvas = BrailleCanvas::new(width, height);
+    let (dot_width, dot_height) = (canvas.dot_width() as f32, canvas.dot_height() as f32);
+
+    // Largest face with the right proportions, centred in the canvas.
+    let face_height = dot_height.min(dot_width / ASPECT);
+    let face_width = face_height * ASPECT;
+    let origin = ((dot_width - face_width) / 2.0, (dot_height - face_height) / 2.0);
+    let at = |x: f32, y: f32| (origin.0 + x * face_width, origin.1 + y * face_height);
+
+    let eye_radius = (face_width * 0.13, face_height * 0.17);
+    for side in [-1.0, 1.0] {
+        let centre = at(0.5 + side * 0.2, 0.36);
+        draw_eye(&mut canvas, centre, eye_radius, params);
+        draw_brow(&mut canvas, centre, eye_radius, side, params.mood);
+    }
+    draw_mouth(&mut canvas, at(0.5, 0.78), (face_width * 0.2, face_height * 0.12), params);
+
+    canvas.into_lines()
+}
+
+fn plot(canvas: &mut BrailleCanvas, (x, y): (f32, f32)) {
+    canvas.set(x.round() as i32, y.round() as i32);
+}
+
+fn draw_eye(canvas: &mut BrailleCanvas, (cx, cy): (f32, f32), (rx, ry): (f32, f32), params: &FaceParams) {
+    let ry = if params.mood == Mood::Surprised { ry * 1.15 } else { ry };
+    // The lid comes down from the top; nothing above it is visible.
+    let lid = cy - ry + 2.0 * ry * params.eyelids.clamp(0.0, 1.0);
+
+    if params.eyelids >= 0.95 {
+        canvas.line(((cx - rx) as i32, cy as i32), ((cx + rx) as i32, cy as i32));
+        return;
+    }
+
+    let steps = ((rx + ry) * 4.0).max(16.0) as usize;
+    for step in 0..steps {
+        let angle = 2.0 * PI * step as f32 / steps as f32;
+        let point = (cx + rx * angle.cos(), cy + ry * angle.sin());
+        if point.1 >= lid {
+            plot(canvas, point);
+        }
+    }
+    if params.eyelids > 0.05 {
+        let half_width = rx * (1.0 - ((lid - cy) / ry).powi(2)).max(0.0).sqrt();
+        canvas.line(((cx - half_width) as i32, lid as i32), ((cx + half_width) as i32, lid as i32));
+    }
+
+    // Filled pupil, pushed towards the gaze direction but kept inside the eye.
+    let pupil = (rx.min(ry) * 0.45).max(1.0);
+    let px = cx + params.gaze.0.clamp(-1.0, 1.0) * (rx - pupil) * 0.8;
+    let py = cy + params.gaze.1.clamp(-1.0, 1.0) * (ry - pupil) * 0.8;
+    let reach = pupil.ceil() as i32;
+    for dy in -reach..=reach {
+        for dx in -reach..=reach {
+            let (x, y) = (px + dx as f32, py + dy as f32);
+            if (dx * dx + dy * dy) as f32 <= pupil * pupil && y >= lid {
+                plot(canvas, (x, y));
+            }
+        }
+    }
+}
+
+// `side` is -1 for the left brow and 1 for the right one.
+fn draw_brow(canvas: &mut BrailleCanvas, (cx, cy): (f32, f32), (rx, ry): (f32, f32), side: f32, mood: Mood) {
+    // Height of the inner and outer ends above the eye, in eye radii.
+    let (inner, outer) = match mood {
+        Mood::Neutral => (1.4, 1.4),
+        Mood::Happy => (1.55, 1.45),
+        Mood::Sad => (1.7, 1.25),
+        Mood::Angry => (1.1, 1.6),
+        Mood::Surprised => (1.9, 1.8),
+    };
+    let inner_point = (cx - side * rx, cy - ry * inner);
+    let outer_point = (cx + side * rx * 1.1, cy - ry * outer);
+    canvas.line(
+        (inner_point.0 as i32, inner_point.1 as i32),
+        (outer_point.0 as i32, outer_point.1 as i32),
+    );
+}
+
+fn draw_mouth(canvas: &mut BrailleCanvas, (cx, cy): (f32, f32), (half_width, depth): (f32, f32), params: &FaceParams) {
+    // How far the middle of the mouth sits below its corners: positive smiles.
+    let smile = match params.mood {
+        Mood::Neutral => 0.15,
+        Mood::Happy => 0.8,
+        Mood::Sad => -0.6,
+        Mood::Angry => -0.3,
+        Mood::Surprised => 0.0,
+    };
+    let open = match params.mood {
+        Mood::Surprised => params.mouth_open.max(0.6),
+        _ => params.mouth_open.clamp(0.0, 1.0),
+    };
+
+    let steps = (half_width * 2.0).max(8.0) as i32;
+    let mut previous: Option<((i32, i32), (i32, i32))> = None;
+    for step in 0..=steps {
+        let u = step as f32 / steps as f32 * 2.0 - 1.0;
+        let bulge = 1.0 - u * u;
+        let x = cx + u * half_width;
+        let middle = cy + smile * depth * bulge;
+        let upper = ((x.round()) as i32, (middle - open * depth * bulge).round() as i32);
+        let lower = ((x.round()) as i32, (middle + open * depth * bulge).round() as i32);
+        if let Some((last_upper, last_lower)) = previous {
+            canvas.line(last_upper, upper);
+            canvas.line(last_lower, lower);
+        }
+        previous = Some((upper, lower));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn guesses_the_mood_from_the_words() {
+        let cases = [
+            ("Here is the answer.", Mood::Neutral),
+            ("That's a GREAT idea, glad to help", Mood::Happy),
+            ("Nice!", Mood::Happy),
+            ("I can't, sorry", Mood::Sad),
+            ("Sorry, that is not possible", Mood::Sad),
+            ("Ugh, seriously?", Mood::Angry),
+            ("Seriously, that is wrong", Mood::Angry),
+            ("Seriously, great work", Mood::Neutral),
+            ("Wow! Really!", Mood::Surprised),
+            ("", Mood::Neutral),
+        ];
+        for (text, mood) in cases {
+            assert_eq!(mood_for(text), mood, "{text:?}");
+        }
+    }
+}
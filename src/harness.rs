@@ -0,0 +1,383 @@
+//! Drives the whole app headless: key presses go in, the fake clock is moved
+//! on by hand and what would have been on screen comes out as text.
+//!
+//! Everything a harness does is recorded, so a test can check that playing
+//! it back reproduces the same screens.
+//!
+//! Snapshots live in `src/snapshots`. After a deliberate change to the look of
+//! the app, review and accept the new ones with `cargo insta review`, or
+//! rerun the tests with `INSTA_UPDATE=always`.
+
+use std::{fs, path::PathBuf, rc::Rc, sync::atomic::{AtomicUsize, Ordering}, time::Duration};
+
+use crossterm::event::{Event, KeyCode, KeyEvent};
+use ratatui::{backend::TestBackend, layout::Rect, Terminal};
+
+use crate::{
+    app::App,
+    backend,
+    clock::ManualClock,
+    config::Config,
+    history::History,
+    keymap,
+    replay::{self, Header, Recorder, Recording},
+};
+
+/// Keeps tests running side by side from sharing the files meant for `ai.py`.
+static NEXT_DIR: AtomicUsize = AtomicUsize::new(0);
+
+/// A part of the screen to compare against a snapshot.
+#[derive(Debug, Clone, Copy)]
+pub enum Pane {
+    Header,
+    Avatar,
+    Input,
+    Output,
+    Status,
+}
+
+pub struct Harness {
+    pub app: App,
+    pub clock: Rc<ManualClock>,
+    terminal: Terminal<TestBackend>,
+    config: Config,
+    /// Holds the files the app talks to `ai.py` through
+    dir: PathBuf,
+}
+
+impl Harness {
+    /// The app on a `width` x `height` screen with the default settings, no
+    /// sound, no history and a model that is ready and waiting.
+    pub fn new(width: u16, height: u16) -> Self {
+        let dir = Self::temp_dir();
+        let mut config = Config::default();
+        config.files.input = dir.join("input.txt");
+        config.files.output = dir.join("output.txt");
+        config.files.memory = dir.join("memory.txt");
+        config.files.ready = dir.join("ready.txt");
+        config.files.reset = dir.join("reset.txt");
+        config.avatar.sound = false;
+        config.session = Some("test".to_string());
+        fs::write(&config.files.ready, "ready").unwrap();
+
+        let clock = ManualClock::new();
+        let header = Header::new(width, height, 0, false, &config, None);
+        let recorder = Recorder::create(&dir.join("session.jsonl"), &header, clock.clone()).unwrap();
+        let app = App::with_clock(&config, clock.clone())
+            .with_history(History::in_memory())
+            .with_seed(0)
+            .with_recorder(recorder);
+        let terminal = Terminal::new(TestBackend::new(width, height)).unwrap();
+        let mut harness = Self { app, clock, terminal, config, dir };
+        harness.draw();
+        harness
+    }
+
+    /// Plays `recording` back from start to finish.
+    pub fn replay(recording: &Recording) -> Self {
+        let dir = Self::temp_dir();
+        let clock = ManualClock::new();
+        let app = recording.app(clock.clone()).unwrap();
+        let header = &recording.header;
+        let terminal = Terminal::new(TestBackend::new(header.width, header.height)).unwrap();
+        let mut harness = Self { app, clock, terminal, config: header.config.clone(), dir };
+        for step in &recording.steps {
+            replay::apply(&mut harness.app, &mut harness.terminal, &harness.clock, step).unwrap();
+        }
+        harness
+    }
+
+    /// Everything done so far.
+    pub fn recording(&self) -> Recording {
+        Recording::load(&self.dir.join("session.jsonl")).unwrap()
+    }
+
+    fn temp_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "stemmgpt-test-{}-{}",
+            std::process::id(),
+            NEXT_DIR.fetch_add(1, Ordering::Relaxed),
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    pub fn draw(&mut self) {
+        self.app.draw(&mut self.terminal).unwrap();
+    }
+
+    pub fn send(&mut self, event: Event) {
+        self.app.handle_event(event);
+        self.draw();
+    }
+
+    /// Presses each key in turn, written as in the keymap file, e.g. `"esc ctrl+p"`.
+    pub fn press(&mut self, keys: &str) {
+        for key in keymap::parse_keys(keys).unwrap() {
+            self.send(Event::Key(key.into()));
+        }
+    }
+
+    /// Types `text` one character at a time.
+    pub fn type_text(&mut self, text: &str) {
+        for c in text.chars() {
+            self.send(Event::Key(KeyEvent::from(KeyCode::Char(c))));
+        }
+    }
+
+    /// Moves the clock on a tick at a time, drawing after each as the real app would.
+    pub fn advance(&mut self, by: Duration) {
+        let tick = Duration::from_millis(self.config.ui.tick_ms);
+        let mut left = by;
+        while !left.is_zero() {
+            let step = tick.min(left);
+            self.clock.advance(step);
+            left -= step;
+            self.draw();
+        }
+    }
+
+    /// Answers the prompt that was sent, as `ai.py` would.
+    pub fn reply(&mut self, text: &str) {
+        let prompt = fs::read_to_string(&self.config.files.input).unwrap();
+        fs::write(&self.config.files.output, backend::tag(backend::untag(&prompt).0, text)).unwrap();
+        self.draw();
+    }
+
+    /// The prompt the app handed to `ai.py`, if it is still waiting to be read.
+    pub fn prompt(&self) -> Option<String> {
+        fs::read_to_string(&self.config.files.input).ok().map(|prompt| backend::untag(&prompt).1.to_string())
+    }
+
+    /// The text in `pane` as last drawn, a line per row with trailing spaces removed.
+    pub fn pane(&self, pane: Pane) -> String {
+        let panes = self.app.panes();
+        let screen = self.terminal.backend().buffer().area;
+        let area = match pane {
+            Pane::Header => panes.header.unwrap_or_default(),
+            Pane::Avatar => panes.avatar.unwrap_or_default(),
+            Pane::Input => panes.input,
+            Pane::Output => panes.output,
+            Pane::Status => Rect::new(0, screen.bottom() - 1, screen.width, 1),
+        };
+        self.text(area)
+    }
+
+    /// Everything on screen.
+    pub fn screen(&self) -> String {
+        self.text(self.terminal.backend().buffer().area)
+    }
+
+    fn text(&self, area: Rect) -> String {
+        let buffer = self.terminal.backend().buffer();
+        let mut lines = Vec::new();
+        for y in area.top()..area.bottom() {
+            let line: String = (area.left()..area.right()).map(|x| buffer.get(x, y).symbol()).collect();
+            lines.push(line.trim_end().to_string());
+        }
+        lines.join("\n")
+    }
+}
+
+impl Drop for Harness {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crossterm::event::{KeyModifiers, MouseButton, MouseEvent, MouseEventKind};
+    use insta::assert_snapshot;
+    use ratatui::style::Modifier;
+
+    use super::*;
+
+    #[test]
+    fn welcome_screen() {
+        let mut harness = Harness::new(120, 40);
+        harness.advance(Duration::from_millis(500));
+        assert_snapshot!("welcome_header", harness.pane(Pane::Header));
+        assert_snapshot!("welcome_avatar", harness.pane(Pane::Avatar));
+        assert_snapshot!("welcome_input", harness.pane(Pane::Input));
+        assert_snapshot!("welcome_output", harness.pane(Pane::Output));
+        assert_snapshot!("welcome_status", harness.pane(Pane::Status));
+    }
+
+    #[test]
+    fn prompt_and_reply() {
+        let mut harness = Harness::new(120, 40);
+        harness.type_text("What is a prime number?");
+        assert_snapshot!("typing_input", harness.pane(Pane::Input));
+
+        harness.press("enter");
+        assert_eq!(harness.prompt().as_deref(), Some("What is a prime number?"));
+        harness.advance(Duration::from_millis(1200));
+        harness.reply("One with exactly two divisors, obviously.");
+        // 75ms a character and a pause after the comma, with time to spare
+        harness.advance(Duration::from_secs(4));
+        assert_snapshot!("reply_output", harness.pane(Pane::Output));
+        assert_snapshot!("reply_status", harness.pane(Pane::Status));
+    }
+
+    #[test]
+    fn replaying_a_session_draws_the_same_screens() {
+        let mut harness = Harness::new(100, 30);
+        harness.type_text("Show me a loop");
+        harness.press("enter");
+        harness.advance(Duration::from_millis(900));
+        harness.reply("Fine.\n```python\nfor i in range(3):\n    print(i)\n```");
+        harness.advance(Duration::from_secs(2));
+        harness.press("esc t");
+        harness.advance(Duration::from_secs(3));
+
+        let replayed = Harness::replay(&harness.recording());
+        assert!(replayed.pane(Pane::Output).contains("print(i)"));
+        assert_eq!(replayed.screen(), harness.screen());
+        assert_eq!(replayed.pane(Pane::Status), harness.pane(Pane::Status));
+    }
+
+    #[test]
+    fn pasting_during_a_search_goes_back_to_the_draft() {
+        let mut harness = Harness::new(80, 24);
+        harness.type_text("hello");
+        harness.press("enter");
+        harness.reply("Hi.");
+        harness.advance(Duration::from_secs(1));
+        harness.type_text("draft ");
+        harness.press("ctrl+r");
+        harness.type_text("hel");
+
+        let pasted: String = (1..=12).map(|number| format!("line {number}\r")).collect();
+        harness.send(Event::Paste(pasted));
+        harness.type_text(" or [pasted 12 lines]");
+        harness.press("enter");
+        let prompt = harness.prompt().unwrap();
+        assert!(prompt.starts_with("draft line 1\nline 2\n"), "{prompt}");
+        assert!(prompt.ends_with("line 12\n or [pasted 12 lines]"), "{prompt}");
+    }
+
+    #[test]
+    fn clearing_tells_the_model_to_forget_once_it_has_replied() {
+        let mut harness = Harness::new(80, 24);
+        harness.type_text("hello");
+        harness.press("enter");
+        harness.type_text("/clear");
+        harness.press("enter");
+        assert!(!harness.dir.join("reset.txt").exists());
+        assert!(harness.pane(Pane::Status).contains("Wait for the reply"));
+
+        harness.reply("Hi.");
+        harness.advance(Duration::from_secs(1));
+        harness.type_text("/clear");
+        harness.press("enter");
+        assert!(harness.dir.join("reset.txt").exists());
+        assert!(!harness.pane(Pane::Output).contains("Hi."));
+    }
+
+    #[test]
+    fn a_selection_goes_once_the_conversation_moves_under_it() {
+        let mut harness = Harness::new(80, 24);
+        harness.type_text("hello");
+        harness.press("enter");
+        harness.reply("The first line of a reply that takes a while to type out.");
+        harness.advance(Duration::from_millis(300));
+
+        let output = harness.app.panes().output;
+        let mouse = |kind, column| Event::Mouse(MouseEvent { kind, column, row: output.y + 1, modifiers: KeyModifiers::NONE });
+        harness.send(mouse(MouseEventKind::Down(MouseButton::Left), output.x + 1));
+        harness.send(mouse(MouseEventKind::Drag(MouseButton::Left), output.x + 6));
+        let selected = |harness: &Harness| {
+            let buffer = harness.terminal.backend().buffer();
+            (output.left()..output.right()).any(|x| buffer.get(x, output.y + 1).modifier.contains(Modifier::REVERSED))
+        };
+        assert!(selected(&harness));
+
+        harness.advance(Duration::from_millis(300));
+        assert!(!selected(&harness));
+    }
+
+    #[test]
+    fn replaying_saves_nothing() {
+        let mut harness = Harness::new(80, 24);
+        let saved = harness.dir.join("chat.md");
+        harness.type_text(&format!("/save {}", saved.display()));
+        harness.press("enter");
+        assert!(fs::remove_file(&saved).is_ok());
+
+        let replayed = Harness::replay(&harness.recording());
+        assert!(!saved.exists());
+        assert!(replayed.pane(Pane::Status).contains("Not saved while replaying"));
+    }
+
+    #[test]
+    fn the_log_leaves_typing_alone() {
+        let mut harness = Harness::new(80, 24);
+        harness.press("esc L e");
+        harness.type_text("vm");
+        harness.press("up enter");
+        assert_eq!(harness.prompt().as_deref(), Some("vm"));
+
+        harness.press("esc v");
+        assert!(harness.screen().contains("Log: error and above"));
+    }
+
+    #[test]
+    fn typing_speed_keys() {
+        let mut harness = Harness::new(80, 24);
+        harness.type_text("hello");
+        harness.press("enter");
+        harness.reply("Hello there, how are you today?");
+        harness.advance(Duration::from_millis(100));
+
+        harness.press("esc ]");
+        assert!(harness.pane(Pane::Status).contains("Typing replies at 20 characters per second"));
+        harness.press("[ [");
+        assert!(harness.pane(Pane::Status).contains("Typing replies at 9 characters per second"));
+        assert!(!harness.pane(Pane::Output).contains("today?"));
+        harness.press("s");
+        assert!(harness.pane(Pane::Output).contains("how are you today?"));
+
+        // A long reply is sped up to fit in the time allowed
+        harness.press("e");
+        harness.type_text("more");
+        harness.press("enter");
+        harness.reply(&"word ".repeat(400));
+        harness.advance(Duration::from_millis(100));
+        harness.press("esc ]");
+        assert!(harness.pane(Pane::Status).contains(", this long one at "));
+    }
+
+    #[test]
+    fn skipping_the_typing_while_editing() {
+        let mut harness = Harness::new(80, 24);
+        harness.type_text("hello");
+        harness.press("enter");
+        harness.reply("Hello there, how are you today?");
+        harness.advance(Duration::from_millis(100));
+        harness.type_text("fine");
+        harness.press("ctrl+f");
+        assert!(harness.pane(Pane::Output).contains("how are you today?"));
+        assert!(harness.pane(Pane::Input).contains("fine"));
+    }
+
+    #[test]
+    fn a_prompt_that_could_not_be_sent_stays_in_the_input() {
+        let mut harness = Harness::new(80, 24);
+        fs::remove_dir_all(&harness.dir).unwrap();
+        harness.type_text("hello");
+        harness.press("enter");
+        harness.advance(Duration::from_secs(5));
+        assert!(harness.pane(Pane::Output).contains("Could not send message"));
+        assert!(harness.pane(Pane::Input).contains("hello"));
+    }
+
+    #[test]
+    fn quitting_from_normal_mode() {
+        let mut harness = Harness::new(80, 24);
+        harness.press("esc");
+        assert_snapshot!("normal_mode", harness.screen());
+        harness.press("q");
+        assert!(harness.app.exited());
+    }
+}
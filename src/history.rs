@@ -0,0 +1,268 @@
+use std::{fs, io::{self, Write}, path::PathBuf};
+
+use crate::paths;
+
+const MAX_ENTRIES: usize = 1000;
+
+/// Path of the history file for the current user.
+pub fn default_path() -> Option<PathBuf> {
+    paths::state_dir().map(|dir| dir.join(format!("history-{}.jsonl", paths::user_name())))
+}
+
+/// Previously submitted prompts, oldest first.
+///
+/// Stored one JSON string per line so prompts can contain newlines.
+pub struct History {
+    entries: Vec<String>,
+    path: Option<PathBuf>,
+    /// How many entries the file holds, counting those too old to be loaded,
+    /// as far as this session knows.
+    saved: usize,
+    /// Entry being shown while browsing with Up/Down.
+    position: Option<usize>,
+    /// What was in the input box before browsing started.
+    draft: String,
+}
+
+impl History {
+    /// History that lives only as long as the app does.
+    pub fn in_memory() -> Self {
+        Self {
+            entries: Vec::new(),
+            path: None,
+            saved: 0,
+            position: None,
+            draft: String::new(),
+        }
+    }
+
+    /// Loads the history at `path`; a missing or unreadable file gives an empty history.
+    pub fn load(path: Option<PathBuf>) -> Self {
+        let entries = path.as_ref()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .map(|contents| contents.lines()
+                .filter_map(|line| serde_json::from_str::<String>(line).ok())
+                .collect::<Vec<_>>())
+            .unwrap_or_default();
+        let saved = entries.len();
+        let skip = saved.saturating_sub(MAX_ENTRIES);
+        Self {
+            entries: entries.into_iter().skip(skip).collect(),
+            path,
+            saved,
+            ..Self::in_memory()
+        }
+    }
+
+    /// Records a submitted prompt, skipping blanks and immediate repeats.
+    pub fn push(&mut self, entry: &str) {
+        self.reset_navigation();
+        if entry.trim().is_empty() || self.entries.last().map(String::as_str) == Some(entry) {
+            return;
+        }
+        self.entries.push(entry.to_string());
+        if self.entries.len() > MAX_ENTRIES {
+            self.entries.remove(0);
+        }
+        if let Err(err) = self.save(entry) {
+            tracing::warn!("Could not save the prompt to the history file: {err}");
+        }
+    }
+
+    // Appends `entry` to the file, and cuts the file back to the newest
+    // entries once it holds twice as many as are loaded.
+    fn save(&mut self, entry: &str) -> io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut file = fs::OpenOptions::new().create(true).append(true).open(path)?;
+        writeln!(file, "{}", serde_json::to_string(entry)?)?;
+        self.saved += 1;
+        if self.saved < 2 * MAX_ENTRIES {
+            return Ok(());
+        }
+        // Read back rather than written from `entries`, so prompts other
+        // sessions have added since this one started are kept
+        let contents = fs::read_to_string(path)?;
+        let lines: Vec<&str> = contents.lines().collect();
+        let kept = &lines[lines.len().saturating_sub(MAX_ENTRIES)..];
+        // Written next to the file first so a crash part way through can't lose it
+        let temporary = path.with_extension("jsonl.tmp");
+        fs::write(&temporary, kept.join("\n") + "\n")?;
+        fs::rename(&temporary, path)?;
+        self.saved = kept.len();
+        Ok(())
+    }
+
+    pub fn reset_navigation(&mut self) {
+        self.position = None;
+        self.draft.clear();
+    }
+
+    /// Steps back to an older entry, remembering `current` so `next` can return to it.
+    pub fn previous(&mut self, current: &str) -> Option<&str> {
+        let position = match self.position {
+            None if self.entries.is_empty() => return None,
+            None => {
+                self.draft = current.to_string();
+                self.entries.len() - 1
+            },
+            Some(0) => return None,
+            Some(position) => position - 1,
+        };
+        self.position = Some(position);
+        Some(&self.entries[position])
+    }
+
+    /// Steps forward to a newer entry, ending with the text that was being written.
+    pub fn next(&mut self) -> Option<String> {
+        let position = self.position?;
+        if position + 1 < self.entries.len() {
+            self.position = Some(position + 1);
+            Some(self.entries[position + 1].clone())
+        } else {
+            self.position = None;
+            Some(std::mem::take(&mut self.draft))
+        }
+    }
+
+    /// Index of the newest entry older than `before` that contains `query`.
+    pub fn search(&self, query: &str, before: usize) -> Option<usize> {
+        self.entries[..before.min(self.entries.len())]
+            .iter()
+            .rposition(|entry| entry.contains(query))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&str> {
+        self.entries.get(index).map(String::as_str)
+    }
+}
+
+/// State of an in-progress Ctrl+R search.
+pub struct HistorySearch {
+    pub query: String,
+    /// Entry currently matching `query`, if any.
+    pub found: Option<usize>,
+    /// Input box contents to restore if the search is cancelled.
+    pub original: String,
+}
+
+impl HistorySearch {
+    pub fn new(original: String) -> Self {
+        Self {
+            query: String::new(),
+            found: None,
+            original,
+        }
+    }
+
+    /// Searches again from the newest entry, e.g. after the query changed.
+    pub fn refresh(&mut self, history: &History) {
+        self.found = if self.query.is_empty() {
+            None
+        } else {
+            history.search(&self.query, history.len())
+        };
+    }
+
+    /// Moves on to the next older match, staying put if there is none.
+    pub fn older(&mut self, history: &History) {
+        let before = self.found.unwrap_or(history.len());
+        if let Some(index) = history.search(&self.query, before) {
+            self.found = Some(index);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history(entries: &[&str]) -> History {
+        let mut history = History::in_memory();
+        entries.iter().for_each(|entry| history.push(entry));
+        history
+    }
+
+    #[test]
+    fn skips_blanks_and_repeats_and_browses_back_to_the_draft() {
+        let mut history = history(&["first", "  ", "second", "second", "third"]);
+        assert_eq!(history.len(), 3);
+
+        assert_eq!(history.previous("draft"), Some("third"));
+        assert_eq!(history.previous("third"), Some("second"));
+        assert_eq!(history.previous("second"), Some("first"));
+        assert_eq!(history.previous("first"), None);
+        assert_eq!(history.next().as_deref(), Some("second"));
+        assert_eq!(history.next().as_deref(), Some("third"));
+        assert_eq!(history.next().as_deref(), Some("draft"));
+        assert_eq!(history.next(), None);
+        assert_eq!(History::in_memory().previous("draft"), None);
+    }
+
+    #[test]
+    fn searches_older_and_older_matches() {
+        let history = history(&["cargo build", "git status", "cargo test", "ls"]);
+        let mut search = HistorySearch::new("draft".to_string());
+        search.refresh(&history);
+        assert_eq!(search.found, None);
+
+        search.query.push_str("cargo");
+        search.refresh(&history);
+        assert_eq!(search.found.and_then(|index| history.get(index)), Some("cargo test"));
+        search.older(&history);
+        assert_eq!(search.found.and_then(|index| history.get(index)), Some("cargo build"));
+        search.older(&history);
+        assert_eq!(search.found.and_then(|index| history.get(index)), Some("cargo build"));
+
+        search.query.push('!');
+        search.refresh(&history);
+        assert_eq!(search.found, None);
+        assert_eq!(search.original, "draft");
+    }
+
+    #[test]
+    fn keeps_the_file_from_growing_past_the_limit() {
+        let dir = std::env::temp_dir().join(format!("stemmgpt-history-test-{}", std::process::id()));
+        let path = dir.join("history.jsonl");
+        let _ = fs::remove_file(&path);
+        let mut history = History::load(Some(path.clone()));
+        for number in 0..2 * MAX_ENTRIES + 10 {
+            history.push(&format!("prompt {number}\nsecond line"));
+        }
+        let lines = fs::read_to_string(&path).unwrap().lines().count();
+        assert_eq!(lines, MAX_ENTRIES + 10);
+
+        let loaded = History::load(Some(path));
+        assert_eq!(loaded.len(), MAX_ENTRIES);
+        assert_eq!(loaded.get(0), Some(format!("prompt {}\nsecond line", MAX_ENTRIES + 10).as_str()));
+        assert_eq!(loaded.get(MAX_ENTRIES - 1), Some(format!("prompt {}\nsecond line", 2 * MAX_ENTRIES + 9).as_str()));
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn cutting_the_file_back_keeps_other_sessions_prompts() {
+        let dir = std::env::temp_dir().join(format!("stemmgpt-history-sessions-test-{}", std::process::id()));
+        let path = dir.join("history.jsonl");
+        let _ = fs::remove_file(&path);
+        let mut this = History::load(Some(path.clone()));
+        let mut other = History::load(Some(path.clone()));
+        for number in 0..2 * MAX_ENTRIES - 1 {
+            this.push(&format!("prompt {number}"));
+        }
+        other.push("from another session");
+        this.push("the last prompt");
+
+        let loaded = History::load(Some(path));
+        assert_eq!(loaded.get(MAX_ENTRIES - 2), Some("from another session"));
+        assert_eq!(loaded.get(MAX_ENTRIES - 1), Some("the last prompt"));
+        let _ = fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,458 @@
+use std::{fmt, fs, io, path::{Path, PathBuf}};
+
+use color_eyre::{eyre::eyre, Result};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+use crate::{action::{Action, CopyTarget}, paths};
+
+/// `keymap.toml` in the config directory.
+pub fn default_path() -> Option<PathBuf> {
+    paths::config_dir().map(|dir| dir.join("keymap.toml"))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputMode {
+    Normal,
+    Editing,
+}
+
+/// A key and the modifiers held with it.
+///
+/// Shift is folded into the character for printable keys, so Shift+z and Z
+/// are the same key whichever way the terminal reports them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Key {
+    code: KeyCode,
+    modifiers: KeyModifiers,
+}
+
+impl Key {
+    pub fn new(code: KeyCode, modifiers: KeyModifiers) -> Self {
+        let modifiers = modifiers & (KeyModifiers::SHIFT | KeyModifiers::CONTROL | KeyModifiers::ALT);
+        match code {
+            KeyCode::Char(c) if modifiers.contains(KeyModifiers::SHIFT) => Self {
+                code: KeyCode::Char(c.to_ascii_uppercase()),
+                modifiers: modifiers - KeyModifiers::SHIFT,
+            },
+            _ => Self { code, modifiers },
+        }
+    }
+
+    fn plain(code: KeyCode) -> Self {
+        Self::new(code, KeyModifiers::NONE)
+    }
+
+    fn ctrl(code: KeyCode) -> Self {
+        Self::new(code, KeyModifiers::CONTROL)
+    }
+
+    fn alt(code: KeyCode) -> Self {
+        Self::new(code, KeyModifiers::ALT)
+    }
+}
+
+impl std::str::FromStr for Key {
+    type Err = String;
+
+    /// Parses keys as written in the keymap file, e.g. `ctrl+shift+z`, `alt+enter` or `F5`.
+    fn from_str(text: &str) -> std::result::Result<Self, String> {
+        // `+` on its own, or after modifiers as in `ctrl++`
+        let (modifiers, name) = match text.strip_suffix("++") {
+            Some(modifiers) => (Some(modifiers), "+"),
+            None if text == "+" => (None, "+"),
+            None => match text.rsplit_once('+') {
+                Some((modifiers, name)) => (Some(modifiers), name),
+                None => (None, text),
+            },
+        };
+        let mut held = KeyModifiers::NONE;
+        for modifier in modifiers.into_iter().flat_map(|modifiers| modifiers.split('+')) {
+            held |= match modifier.to_lowercase().as_str() {
+                "ctrl" | "control" => KeyModifiers::CONTROL,
+                "alt" | "meta" => KeyModifiers::ALT,
+                "shift" => KeyModifiers::SHIFT,
+                _ => return Err(format!("unknown modifier '{modifier}' in '{text}'")),
+            };
+        }
+        let mut chars = name.chars();
+        let code = match (chars.next(), chars.next()) {
+            (Some(c), None) => KeyCode::Char(c),
+            _ => match name.to_lowercase().as_str() {
+                "space" => KeyCode::Char(' '),
+                "enter" | "return" => KeyCode::Enter,
+                "esc" | "escape" => KeyCode::Esc,
+                "tab" => KeyCode::Tab,
+                "backspace" => KeyCode::Backspace,
+                "delete" | "del" => KeyCode::Delete,
+                "left" => KeyCode::Left,
+                "right" => KeyCode::Right,
+                "up" => KeyCode::Up,
+                "down" => KeyCode::Down,
+                "home" => KeyCode::Home,
+                "end" => KeyCode::End,
+                "pageup" => KeyCode::PageUp,
+                "pagedown" => KeyCode::PageDown,
+                lower => match lower.strip_prefix('f').and_then(|n| n.parse().ok()) {
+                    Some(n @ 1..=12) => KeyCode::F(n),
+                    _ => return Err(format!("unknown key '{name}' in '{text}'")),
+                },
+            },
+        };
+        Ok(Self::new(code, held))
+    }
+}
+
+impl From<KeyEvent> for Key {
+    fn from(event: KeyEvent) -> Self {
+        Self::new(event.code, event.modifiers)
+    }
+}
+
+impl From<Key> for KeyEvent {
+    fn from(key: Key) -> Self {
+        KeyEvent::new(key.code, key.modifiers)
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.modifiers.contains(KeyModifiers::CONTROL) {
+            write!(f, "Ctrl+")?;
+        }
+        if self.modifiers.contains(KeyModifiers::ALT) {
+            write!(f, "Alt+")?;
+        }
+        if self.modifiers.contains(KeyModifiers::SHIFT) {
+            write!(f, "Shift+")?;
+        }
+        match self.code {
+            KeyCode::Char(' ') => write!(f, "Space"),
+            KeyCode::Char(c) if c.is_ascii_uppercase() && !self.modifiers.is_empty() => write!(f, "Shift+{c}"),
+            KeyCode::Char(c) => write!(f, "{c}"),
+            KeyCode::Enter => write!(f, "Enter"),
+            KeyCode::Esc => write!(f, "Esc"),
+            KeyCode::Tab => write!(f, "Tab"),
+            KeyCode::Backspace => write!(f, "Backspace"),
+            KeyCode::Delete => write!(f, "Delete"),
+            KeyCode::Left => write!(f, "Left"),
+            KeyCode::Right => write!(f, "Right"),
+            KeyCode::Up => write!(f, "Up"),
+            KeyCode::Down => write!(f, "Down"),
+            KeyCode::Home => write!(f, "Home"),
+            KeyCode::End => write!(f, "End"),
+            KeyCode::PageUp => write!(f, "PageUp"),
+            KeyCode::PageDown => write!(f, "PageDown"),
+            KeyCode::F(n) => write!(f, "F{n}"),
+            other => write!(f, "{other:?}"),
+        }
+    }
+}
+
+/// Parses a space separated chord such as `ctrl+x ctrl+s`.
+pub fn parse_keys(text: &str) -> std::result::Result<Vec<Key>, String> {
+    let keys = text.split_whitespace().map(str::parse).collect::<std::result::Result<Vec<Key>, _>>()?;
+    if keys.is_empty() {
+        return Err("empty key".to_string());
+    }
+    Ok(keys)
+}
+
+fn describe(keys: &[Key]) -> String {
+    keys.iter().map(Key::to_string).collect::<Vec<_>>().join(" ")
+}
+
+pub struct Binding {
+    pub mode: InputMode,
+    /// More than one key for a chord, pressed one after another.
+    pub keys: Vec<Key>,
+    pub action: Action,
+}
+
+/// What the keys pressed so far amount to.
+pub enum Resolution<'a> {
+    Action(&'a Action),
+    /// The start of a chord; wait for the next key.
+    Prefix,
+    Unbound,
+}
+
+/// Which action each key triggers in each input mode.
+pub struct Keymap {
+    bindings: Vec<Binding>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        use Action::*;
+        use InputMode::{Editing, Normal};
+
+        let bindings = [
+            (Normal, Key::plain(KeyCode::Char('e')), Edit),
+            (Normal, Key::plain(KeyCode::Char('q')), Quit),
+            (Normal, Key::plain(KeyCode::Char('!')), Panic),
+            (Normal, Key::plain(KeyCode::Char('r')), Revive),
+            (Normal, Key::plain(KeyCode::Char('v')), CycleVisualization),
+            (Normal, Key::plain(KeyCode::Char('a')), ToggleAvatarStyle),
+            (Normal, Key::plain(KeyCode::Char('t')), NextTheme),
+            (Normal, Key::plain(KeyCode::Char('l')), NextLayout),
+            (Normal, Key::plain(KeyCode::Char('y')), Copy(CopyTarget::Reply(1))),
+            (Normal, Key::plain(KeyCode::Char('Y')), Copy(CopyTarget::Code(None))),
+            (Normal, Key::plain(KeyCode::Char('+')), GrowInput),
+            (Normal, Key::plain(KeyCode::Char('-')), ShrinkInput),
+            (Normal, Key::plain(KeyCode::Char('>')), GrowAvatar),
+            (Normal, Key::plain(KeyCode::Char('<')), ShrinkAvatar),
+            (Normal, Key::ctrl(KeyCode::Char('p')), OpenPalette),
+            (Normal, Key::ctrl(KeyCode::Char('z')), Suspend),
+            (Normal, Key::plain(KeyCode::Char('L')), ToggleLog),
+            (Normal, Key::plain(KeyCode::Char('s')), SkipTyping),
+            (Normal, Key::plain(KeyCode::Char(']')), TypeFaster),
+            (Normal, Key::plain(KeyCode::Char('[')), TypeSlower),
+            (Normal, Key::plain(KeyCode::PageUp), ScrollUp),
+            (Normal, Key::plain(KeyCode::PageDown), ScrollDown),
+            (Editing, Key::plain(KeyCode::Esc), StopEditing),
+            (Editing, Key::plain(KeyCode::Enter), Submit),
+            (Editing, Key::new(KeyCode::Enter, KeyModifiers::SHIFT), InsertNewline),
+            (Editing, Key::alt(KeyCode::Enter), InsertNewline),
+            (Editing, Key::plain(KeyCode::Tab), Complete),
+            (Editing, Key::ctrl(KeyCode::Char('p')), OpenPalette),
+            (Editing, Key::plain(KeyCode::PageUp), ScrollUp),
+            (Editing, Key::plain(KeyCode::PageDown), ScrollDown),
+            (Editing, Key::alt(KeyCode::Char('!')), Panic),
+            (Editing, Key::alt(KeyCode::Char('r')), Revive),
+            (Editing, Key::plain(KeyCode::Backspace), Backspace),
+            (Editing, Key::plain(KeyCode::Delete), Delete),
+            (Editing, Key::plain(KeyCode::Left), MoveLeft),
+            (Editing, Key::plain(KeyCode::Right), MoveRight),
+            (Editing, Key::plain(KeyCode::Up), MoveUp),
+            (Editing, Key::plain(KeyCode::Down), MoveDown),
+            (Editing, Key::plain(KeyCode::Home), MoveHome),
+            (Editing, Key::plain(KeyCode::End), MoveEnd),
+            (Editing, Key::ctrl(KeyCode::Left), MoveWordLeft),
+            (Editing, Key::ctrl(KeyCode::Right), MoveWordRight),
+            (Editing, Key::ctrl(KeyCode::Backspace), DeleteWordLeft),
+            (Editing, Key::alt(KeyCode::Backspace), DeleteWordLeft),
+            (Editing, Key::ctrl(KeyCode::Delete), DeleteWordRight),
+            (Editing, Key::alt(KeyCode::Char('d')), DeleteWordRight),
+            (Editing, Key::ctrl(KeyCode::Char('k')), KillToEnd),
+            (Editing, Key::ctrl(KeyCode::Char('u')), KillToStart),
+            (Editing, Key::ctrl(KeyCode::Char('w')), KillWordLeft),
+            (Editing, Key::ctrl(KeyCode::Char('y')), Yank),
+            (Editing, Key::ctrl(KeyCode::Char('z')), Undo),
+            (Editing, Key::ctrl(KeyCode::Char('Z')), Redo),
+            (Editing, Key::alt(KeyCode::Char('z')), Redo),
+            (Editing, Key::ctrl(KeyCode::Char('r')), SearchHistory),
+            (Editing, Key::ctrl(KeyCode::Char('o')), ExpandPastes),
+            (Editing, Key::ctrl(KeyCode::Char('f')), SkipTyping),
+        ];
+        Self {
+            bindings: bindings.into_iter().map(|(mode, key, action)| Binding { mode, keys: vec![key], action }).collect(),
+        }
+    }
+}
+
+impl Keymap {
+    /// The default bindings with the user's keymap file, if there is one, on top.
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => return Err(eyre!("Could not read {}: {err}", path.display())),
+        };
+        Self::from_toml(&text).map_err(|errors| {
+            eyre!("Invalid key bindings in {}:\n  {}", path.display(), errors.join("\n  "))
+        })
+    }
+
+    /// Applies a keymap file on top of the defaults, or lists everything wrong with it.
+    ///
+    /// Each `[normal]` or `[editing]` table maps keys to action names, e.g.
+    /// `"ctrl+x ctrl+s" = "save"`. Binding a key to `"none"` removes its default.
+    /// A binding replaces any default that uses the same keys or would shadow it.
+    pub fn from_toml(text: &str) -> std::result::Result<Self, Vec<String>> {
+        let table: toml::Table = text.parse().map_err(|err: toml::de::Error| vec![err.to_string().trim_end().to_string()])?;
+        let mut errors = Vec::new();
+        // (mode, keys, action or None to unbind, as written)
+        let mut entries: Vec<(InputMode, Vec<Key>, Option<Action>, String)> = Vec::new();
+        for (section, value) in &table {
+            let mode = match section.as_str() {
+                "normal" => InputMode::Normal,
+                "editing" => InputMode::Editing,
+                _ => {
+                    errors.push(format!("unknown section [{section}], expected [normal] or [editing]"));
+                    continue;
+                },
+            };
+            let Some(bindings) = value.as_table() else {
+                errors.push(format!("{section} should be a table of key = \"action\" pairs"));
+                continue;
+            };
+            for (spec, value) in bindings {
+                let keys = match parse_keys(spec) {
+                    Ok(keys) => keys,
+                    Err(err) => {
+                        errors.push(format!("[{section}] {err}"));
+                        continue;
+                    },
+                };
+                let action = match value.as_str() {
+                    Some("none") => None,
+                    Some(name) => match Action::from_name(name) {
+                        Some(action) => Some(action),
+                        None => {
+                            errors.push(format!("[{section}] \"{spec}\": {}", unknown_action(name)));
+                            continue;
+                        },
+                    },
+                    None => {
+                        errors.push(format!("[{section}] \"{spec}\" should name an action, e.g. \"submit\""));
+                        continue;
+                    },
+                };
+                for (other_mode, other_keys, other_action, other_spec) in &entries {
+                    if *other_mode != mode {
+                        continue;
+                    }
+                    if *other_keys == keys {
+                        errors.push(format!("[{section}] \"{other_spec}\" and \"{spec}\" are both {}", describe(&keys)));
+                    } else if action.is_some() && other_action.is_some() {
+                        let (short, long) = if keys.len() < other_keys.len() { (spec, other_spec) } else { (other_spec, spec) };
+                        if keys.starts_with(other_keys) || other_keys.starts_with(&keys) {
+                            errors.push(format!("[{section}] \"{short}\" is bound, so the chord \"{long}\" can never be finished"));
+                        }
+                    }
+                }
+                entries.push((mode, keys, action, spec.clone()));
+            }
+        }
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        let mut keymap = Self::default();
+        keymap.bindings.retain(|binding| {
+            !entries.iter().any(|(mode, keys, _, _)| {
+                *mode == binding.mode && (keys.starts_with(&binding.keys) || binding.keys.starts_with(keys))
+            })
+        });
+        keymap.bindings.extend(entries.into_iter().filter_map(|(mode, keys, action, _)| {
+            Some(Binding { mode, keys, action: action? })
+        }));
+        Ok(keymap)
+    }
+
+    /// Looks up the keys pressed so far, which may be the first part of a chord.
+    pub fn resolve(&self, mode: InputMode, keys: &[Key]) -> Resolution<'_> {
+        let mut bindings = self.bindings.iter().filter(|binding| binding.mode == mode);
+        if let Some(binding) = bindings.clone().find(|binding| binding.keys == keys) {
+            return Resolution::Action(&binding.action);
+        }
+        match bindings.any(|binding| binding.keys.starts_with(keys)) {
+            true => Resolution::Prefix,
+            false => Resolution::Unbound,
+        }
+    }
+
+    /// The keys for `action`, preferring ones that work in `mode`.
+    pub fn key_for(&self, action: &Action, mode: InputMode) -> Option<String> {
+        let mut bindings = self.bindings.iter().filter(|binding| binding.action == *action);
+        let first = bindings.next()?;
+        if first.mode == mode {
+            return Some(describe(&first.keys));
+        }
+        Some(describe(&bindings.find(|binding| binding.mode == mode).unwrap_or(first).keys))
+    }
+}
+
+// Names the closest action, since most mistakes are typos.
+fn unknown_action(name: &str) -> String {
+    let closest = crate::action::ALL.iter()
+        .map(|action| (distance(name, action.name()), action.name()))
+        .min();
+    match closest {
+        Some((distance, closest)) if distance <= 3 => format!("unknown action '{name}', did you mean '{closest}'?"),
+        _ => format!("unknown action '{name}'; the command palette lists them all"),
+    }
+}
+
+// Levenshtein distance
+fn distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = (above + 1).min(row[j] + 1).min(diagonal + usize::from(ca != cb));
+            diagonal = above;
+        }
+    }
+    row[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(text: &str) -> Vec<Key> {
+        parse_keys(text).unwrap()
+    }
+
+    #[test]
+    fn parses_modifiers_and_names() {
+        assert_eq!(keys("ctrl+shift+z"), keys("Ctrl+Z"));
+        assert_eq!(keys("alt+enter"), vec![Key::alt(KeyCode::Enter)]);
+        assert_eq!(keys("ctrl++"), vec![Key::ctrl(KeyCode::Char('+'))]);
+        assert_eq!(keys("F5 space"), vec![Key::plain(KeyCode::F(5)), Key::plain(KeyCode::Char(' '))]);
+        assert!(parse_keys("hyper+x").is_err());
+        assert!(parse_keys("pgup").is_err());
+    }
+
+    #[test]
+    fn page_keys_scroll_rather_than_kill_the_avatar() {
+        let keymap = Keymap::default();
+        for mode in [InputMode::Normal, InputMode::Editing] {
+            assert!(matches!(keymap.resolve(mode, &keys("pageup")), Resolution::Action(Action::ScrollUp)));
+            assert!(matches!(keymap.resolve(mode, &keys("pagedown")), Resolution::Action(Action::ScrollDown)));
+        }
+        assert!(matches!(keymap.resolve(InputMode::Normal, &keys("!")), Resolution::Action(Action::Panic)));
+        assert!(matches!(keymap.resolve(InputMode::Editing, &keys("alt+!")), Resolution::Action(Action::Panic)));
+        assert!(matches!(keymap.resolve(InputMode::Editing, &keys("alt+r")), Resolution::Action(Action::Revive)));
+    }
+
+    #[test]
+    fn chords_wait_for_their_last_key() {
+        let keymap = Keymap::from_toml("[editing]\n\"ctrl+x ctrl+s\" = \"save\"").unwrap();
+        let mode = InputMode::Editing;
+        assert!(matches!(keymap.resolve(mode, &keys("ctrl+x")), Resolution::Prefix));
+        assert!(matches!(keymap.resolve(mode, &keys("ctrl+x ctrl+s")), Resolution::Action(Action::Save(None))));
+        assert!(matches!(keymap.resolve(mode, &keys("ctrl+x q")), Resolution::Unbound));
+        // Defaults are untouched elsewhere
+        assert!(matches!(keymap.resolve(mode, &keys("ctrl+k")), Resolution::Action(Action::KillToEnd)));
+    }
+
+    #[test]
+    fn user_bindings_replace_defaults() {
+        let keymap = Keymap::from_toml("[normal]\nq = \"none\"\n\"ctrl+q\" = \"quit\"\n[editing]\n\"ctrl+k ctrl+k\" = \"kill-to-end\"").unwrap();
+        assert!(matches!(keymap.resolve(InputMode::Normal, &keys("q")), Resolution::Unbound));
+        assert_eq!(keymap.key_for(&Action::Quit, InputMode::Normal).as_deref(), Some("Ctrl+q"));
+        assert!(matches!(keymap.resolve(InputMode::Editing, &keys("ctrl+k")), Resolution::Prefix));
+    }
+
+    #[test]
+    fn reports_every_problem() {
+        let errors = Keymap::from_toml(concat!(
+            "[insert]\n",
+            "[editing]\n",
+            "\"ctrl+x\" = \"sav\"\n",
+            "\"ctrl+y\" = \"yank\"\n",
+            "\"ctrl+y ctrl+y\" = \"undo\"\n",
+            "\"ctrl+shift+z\" = \"redo\"\n",
+            "\"ctrl+Z\" = \"undo\"\n",
+        )).err().unwrap();
+        assert_eq!(errors.len(), 4, "{errors:?}");
+        for expected in ["unknown section [insert]", "did you mean 'save'", "can never be finished", "are both Ctrl+Shift+Z"] {
+            assert!(errors.iter().any(|error| error.contains(expected)), "{expected} not in {errors:?}");
+        }
+    }
+}
@@ -0,0 +1,195 @@
+use ratatui::layout::{Constraint, Direction, Layout, Rect};
+use serde::{Deserialize, Serialize};
+
+/// Smallest and largest share of its space a pane can be dragged to, in percent.
+pub const MIN_SIZE: u16 = 10;
+pub const MAX_SIZE: u16 = 90;
+
+/// How the panes are arranged on screen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Preset {
+    /// Picks one of the others to suit the size of the terminal
+    Auto,
+    /// Avatar on the left, input above the conversation on the right
+    SideBySide,
+    /// Avatar, conversation and input one above the other
+    Stacked,
+    /// Conversation across the top, input and a small avatar below it, no banner
+    OutputFocus,
+    /// Just the conversation and the input
+    Zen,
+}
+
+pub const PRESETS: &[Preset] = &[Preset::Auto, Preset::SideBySide, Preset::Stacked, Preset::OutputFocus, Preset::Zen];
+
+impl Preset {
+    pub fn name(self) -> &'static str {
+        match self {
+            Preset::Auto => "auto",
+            Preset::SideBySide => "side-by-side",
+            Preset::Stacked => "stacked",
+            Preset::OutputFocus => "output-focus",
+            Preset::Zen => "zen",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        PRESETS.iter().copied().find(|preset| preset.name() == name)
+    }
+
+    pub fn next(self) -> Self {
+        let index = PRESETS.iter().position(|&preset| preset == self).unwrap_or(0);
+        PRESETS[(index + 1) % PRESETS.len()]
+    }
+
+    // What `Auto` turns into on a screen of this size.
+    fn fit(screen: Rect) -> Self {
+        match (screen.width, screen.height) {
+            (width, height) if width < 60 || height < 20 => Preset::Zen,
+            (_, height) if height < 30 => Preset::OutputFocus,
+            (width, _) if width < 100 => Preset::Stacked,
+            _ => Preset::SideBySide,
+        }
+    }
+}
+
+/// The two adjustable splits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Split {
+    Avatar,
+    Input,
+}
+
+/// A border between two panes that can be dragged to resize them.
+#[derive(Debug, Clone, Copy)]
+pub struct Divider {
+    pub split: Split,
+    container: Rect,
+    direction: Direction,
+    /// Whether the pane the split sizes comes before the other one
+    first: bool,
+    /// Where the second pane starts
+    at: u16,
+}
+
+impl Divider {
+    /// Whether a cell is on the border, which is drawn on both sides of `at`.
+    pub fn contains(&self, column: u16, row: u16) -> bool {
+        let (along, across, start, end) = match self.direction {
+            Direction::Horizontal => (column, row, self.container.y, self.container.bottom()),
+            Direction::Vertical => (row, column, self.container.x, self.container.right()),
+        };
+        (along == self.at || along + 1 == self.at) && (start..end).contains(&across)
+    }
+
+    /// The size the split would have with the border dragged to a cell.
+    pub fn size_at(&self, column: u16, row: u16) -> u16 {
+        let (position, start, length) = match self.direction {
+            Direction::Horizontal => (column, self.container.x, self.container.width),
+            Direction::Vertical => (row, self.container.y, self.container.height),
+        };
+        let before = (position.saturating_sub(start) as u32 * 100 / length.max(1) as u32) as u16;
+        let size = if self.first { before } else { 100 - before.min(100) };
+        size.clamp(MIN_SIZE, MAX_SIZE)
+    }
+}
+
+/// Where each pane goes, once a preset has been applied to the screen.
+#[derive(Debug, Clone, Default)]
+pub struct Panes {
+    pub header: Option<Rect>,
+    pub avatar: Option<Rect>,
+    pub input: Rect,
+    pub output: Rect,
+    pub dividers: Vec<Divider>,
+}
+
+/// Lays the panes out on `screen`.
+///
+/// `avatar_size` and `input_size` are the percentages of their part of the screen
+/// given to the avatar and to the input box.
+pub fn split(screen: Rect, preset: Preset, avatar_size: u16, input_size: u16, header_height: u16) -> Panes {
+    let preset = match preset {
+        Preset::Auto => Preset::fit(screen),
+        preset => preset,
+    };
+    let mut panes = Panes::default();
+    let with_header = |panes: &mut Panes| {
+        let [header, body] = pair(Layout::vertical([Constraint::Length(header_height), Constraint::Min(0)]).split(screen));
+        panes.header = Some(header);
+        body
+    };
+
+    match preset {
+        Preset::Auto | Preset::SideBySide => {
+            let body = with_header(&mut panes);
+            let (avatar, right) = divide(&mut panes, body, Direction::Horizontal, Split::Avatar, avatar_size, true);
+            let (input, output) = divide(&mut panes, right, Direction::Vertical, Split::Input, input_size, true);
+            (panes.avatar, panes.input, panes.output) = (Some(avatar), input, output);
+        },
+        Preset::Stacked => {
+            let body = with_header(&mut panes);
+            let (avatar, rest) = divide(&mut panes, body, Direction::Vertical, Split::Avatar, avatar_size, true);
+            let (input, output) = divide(&mut panes, rest, Direction::Vertical, Split::Input, input_size, false);
+            (panes.avatar, panes.input, panes.output) = (Some(avatar), input, output);
+        },
+        Preset::OutputFocus => {
+            let (bottom, output) = divide(&mut panes, screen, Direction::Vertical, Split::Input, input_size, false);
+            let (avatar, input) = divide(&mut panes, bottom, Direction::Horizontal, Split::Avatar, avatar_size, false);
+            (panes.avatar, panes.input, panes.output) = (Some(avatar), input, output);
+        },
+        Preset::Zen => {
+            let (input, output) = divide(&mut panes, screen, Direction::Vertical, Split::Input, input_size, false);
+            (panes.input, panes.output) = (input, output);
+        },
+    }
+    panes
+}
+
+// Splits `container` in two, returning the pane sized by `split` followed by the other.
+fn divide(panes: &mut Panes, container: Rect, direction: Direction, split: Split, size: u16, first: bool) -> (Rect, Rect) {
+    let (before, after) = if first { (size, 100 - size) } else { (100 - size, size) };
+    let [a, b] = pair(Layout::new(direction, [Constraint::Percentage(before), Constraint::Percentage(after)]).split(container));
+    let at = match direction {
+        Direction::Horizontal => b.x,
+        Direction::Vertical => b.y,
+    };
+    panes.dividers.push(Divider { split, container, direction, first, at });
+    if first { (a, b) } else { (b, a) }
+}
+
+fn pair(parts: std::rc::Rc<[Rect]>) -> [Rect; 2] {
+    [parts[0], parts[1]]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn auto_fits_the_screen() {
+        let panes = split(Rect::new(0, 0, 160, 50), Preset::Auto, 50, 35, 6);
+        assert!(panes.header.is_some());
+        assert_eq!(panes.avatar.map(|avatar| avatar.width), Some(80));
+        assert!(panes.input.y < panes.output.y);
+
+        let panes = split(Rect::new(0, 0, 80, 40), Preset::Auto, 50, 35, 6);
+        assert!(panes.output.y < panes.input.y, "stacked puts the input at the bottom");
+        assert_eq!(panes.output.width, 80);
+
+        let panes = split(Rect::new(0, 0, 40, 12), Preset::Auto, 50, 35, 6);
+        assert!(panes.header.is_none() && panes.avatar.is_none());
+    }
+
+    #[test]
+    fn dragging_a_border_resizes_the_split() {
+        let panes = split(Rect::new(0, 0, 100, 40), Preset::Zen, 50, 50, 6);
+        let input = panes.dividers.iter().find(|divider| divider.split == Split::Input).unwrap();
+        assert!(input.contains(10, panes.input.y));
+        assert!(!input.contains(10, panes.input.y + 2));
+        // The input box is below the border, so dragging up grows it
+        assert_eq!(input.size_at(10, 16), 60);
+        assert_eq!(input.size_at(10, 0), MAX_SIZE);
+    }
+}
@@ -0,0 +1,128 @@
+use ratatui::{
+    layout::Rect,
+    style::{Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Clear, Paragraph},
+    Frame,
+};
+use tracing::Level;
+
+use crate::{logging::{Recent, Record}, theme::Theme};
+
+/// Levels from least to most detailed, in the order `v` steps through them.
+const LEVELS: [Level; 5] = [Level::ERROR, Level::WARN, Level::INFO, Level::DEBUG, Level::TRACE];
+
+/// The overlay tailing the log, showing records at `level` or above, from
+/// one module or all of them.
+pub struct LogView {
+    level: Level,
+    module: Option<String>,
+    /// Records scrolled back from the newest
+    scroll: usize,
+}
+
+impl LogView {
+    pub fn new() -> Self {
+        Self { level: Level::TRACE, module: None, scroll: 0 }
+    }
+
+    fn shows(&self, record: &Record) -> bool {
+        record.level <= self.level && self.module.as_ref().is_none_or(|module| record.module() == module)
+    }
+
+    /// Steps through showing only errors, then warnings as well, and so on down to everything.
+    pub fn next_level(&mut self) {
+        let index = LEVELS.iter().position(|level| *level == self.level).unwrap_or(0);
+        self.level = LEVELS[(index + 1) % LEVELS.len()];
+        self.scroll = 0;
+    }
+
+    /// Steps through the modules that have logged something, then back to all of them.
+    pub fn next_module(&mut self, recent: &Recent) {
+        let mut modules: Vec<String> = recent.with(|records| records.iter().map(|record| record.module().to_string()).collect());
+        modules.sort();
+        modules.dedup();
+        self.module = match &self.module {
+            None => modules.first().cloned(),
+            Some(current) => modules.into_iter().find(|module| module > current),
+        };
+        self.scroll = 0;
+    }
+
+    pub fn scroll_up(&mut self, by: usize) {
+        self.scroll += by;
+    }
+
+    pub fn scroll_down(&mut self, by: usize) {
+        self.scroll = self.scroll.saturating_sub(by);
+    }
+
+    /// Draws the overlay over the lower part of `screen`, newest records at the bottom.
+    pub fn render(&mut self, frame: &mut Frame, screen: Rect, recent: &Recent, theme: &Theme) {
+        let height = (screen.height * 2 / 3).max(5.min(screen.height));
+        let area = Rect::new(screen.x, screen.bottom() - height, screen.width, height);
+        let visible = height.saturating_sub(2) as usize;
+
+        let mut lines = recent.with(|records| {
+            let shown: Vec<&Record> = records.iter().filter(|record| self.shows(record)).collect();
+            self.scroll = self.scroll.min(shown.len().saturating_sub(visible));
+            let end = shown.len() - self.scroll;
+            shown[end.saturating_sub(visible)..end].iter().map(|record| line(record, theme)).collect::<Vec<_>>()
+        });
+
+        let module = self.module.as_deref().unwrap_or("all modules");
+        let title = format!(" Log: {} and above · {module} (v level, m module, Esc to close) ", self.level.as_str().to_lowercase());
+        let block = Block::bordered().title(title).style(theme.popup);
+        if lines.is_empty() {
+            lines.push(Line::styled(" Nothing logged yet", Style::default().add_modifier(Modifier::ITALIC)));
+        }
+        frame.render_widget(Clear, area);
+        frame.render_widget(Paragraph::new(lines).block(block), area);
+    }
+}
+
+fn line(record: &Record, theme: &Theme) -> Line<'static> {
+    let level_style = match record.level {
+        Level::ERROR | Level::WARN => theme.error,
+        Level::INFO => theme.key,
+        _ => Style::default().add_modifier(Modifier::DIM),
+    };
+    Line::from(vec![
+        Span::styled(record.at.format("%H:%M:%S%.3f ").to_string(), Style::default().add_modifier(Modifier::DIM)),
+        Span::styled(format!("{:<5} ", record.level.as_str()), level_style),
+        Span::styled(format!("{} ", record.module()), Style::default().add_modifier(Modifier::BOLD)),
+        Span::raw(record.message.clone()),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(level: Level, target: &str, message: &str) -> Record {
+        Record { at: chrono::Local::now(), level, target: target.to_string(), message: message.to_string() }
+    }
+
+    #[test]
+    fn filters_by_level_and_module() {
+        let recent = Recent::default();
+        recent.push(record(Level::DEBUG, "your_terminal_app::backend", "sent"));
+        recent.push(record(Level::WARN, "your_terminal_app::history", "could not save"));
+        recent.push(record(Level::ERROR, "your_terminal_app::backend", "no reply"));
+        let shown = |view: &LogView| recent.with(|records| {
+            records.iter().filter(|record| view.shows(record)).map(|record| record.message.clone()).collect::<Vec<_>>()
+        });
+
+        let mut view = LogView::new();
+        assert_eq!(shown(&view).len(), 3);
+        view.next_module(&recent);
+        assert_eq!(shown(&view), ["sent", "no reply"]);
+        view.next_level();
+        assert_eq!(shown(&view), ["no reply"]);
+        view.next_module(&recent);
+        assert!(shown(&view).is_empty());
+        view.next_module(&recent);
+        view.next_level();
+        assert_eq!(shown(&view), ["could not save", "no reply"]);
+    }
+}
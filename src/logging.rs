@@ -0,0 +1,158 @@
+//! Logging with `tracing`: records go to a file in the state directory, a
+//! new one each day, and the latest are kept in memory for the log viewer.
+//!
+//! Use `STEMMGPT_LOG_FILTER=debug` or `--set log.filter=debug` to see more.
+
+use std::{
+    collections::VecDeque,
+    fmt::{self, Write as _},
+    sync::{Arc, Mutex},
+};
+
+use chrono::{DateTime, Local};
+use tracing::{field::{Field, Visit}, Event, Level, Subscriber};
+use tracing_appender::{non_blocking::WorkerGuard, rolling::{Builder, Rotation}};
+use tracing_subscriber::{layer::{Context, SubscriberExt}, util::SubscriberInitExt, EnvFilter, Layer};
+
+use crate::{config::LogSettings, paths};
+
+/// How many records the log viewer can look back through.
+const RECENT: usize = 1000;
+
+/// Records from this crate have targets such as `your_terminal_app::backend`.
+const CRATE: &str = env!("CARGO_CRATE_NAME");
+
+pub struct Record {
+    pub at: DateTime<Local>,
+    pub level: Level,
+    pub target: String,
+    pub message: String,
+}
+
+impl Record {
+    /// Where the record came from, e.g. `backend` or `main`, or the full target for other crates.
+    pub fn module(&self) -> &str {
+        match self.target.strip_prefix(CRATE) {
+            Some("") => "main",
+            Some(module) if module.starts_with("::") => &module[2..],
+            _ => &self.target,
+        }
+    }
+}
+
+/// The latest records, oldest first. Clones share the same records.
+#[derive(Clone, Default)]
+pub struct Recent(Arc<Mutex<VecDeque<Record>>>);
+
+impl Recent {
+    pub fn push(&self, record: Record) {
+        let mut records = self.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if records.len() == RECENT {
+            records.pop_front();
+        }
+        records.push_back(record);
+    }
+
+    /// Runs `f` with the records, holding the lock for as long as it takes.
+    pub fn with<T>(&self, f: impl FnOnce(&VecDeque<Record>) -> T) -> T {
+        f(&self.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner()))
+    }
+}
+
+impl<S: Subscriber> Layer<S> for Recent {
+    fn on_event(&self, event: &Event<'_>, _: Context<'_, S>) {
+        let mut message = Message::default();
+        event.record(&mut message);
+        let metadata = event.metadata();
+        self.push(Record {
+            at: Local::now(),
+            level: *metadata.level(),
+            target: metadata.target().to_string(),
+            message: message.0,
+        });
+    }
+}
+
+// The message of an event followed by its other fields as `name=value`.
+#[derive(Default)]
+struct Message(String);
+
+impl Visit for Message {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        match field.name() {
+            "message" if self.0.is_empty() => {
+                let _ = write!(self.0, "{value:?}");
+            },
+            "message" => self.0.insert_str(0, &format!("{value:?} ")),
+            name => {
+                let separator = if self.0.is_empty() { "" } else { " " };
+                let _ = write!(self.0, "{separator}{name}={value:?}");
+            },
+        }
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        match field.name() {
+            "message" => self.record_debug(field, &format_args!("{value}")),
+            _ => self.record_debug(field, &value),
+        }
+    }
+}
+
+/// Starts logging to `stemmgpt.YYYY-MM-DD.log` in the `logs` folder of the
+/// state directory, and to the records returned.
+///
+/// Records are written on another thread, so the guard has to be kept until
+/// the app exits for the last of them to reach the file.
+pub fn init(settings: &LogSettings) -> (Recent, Option<WorkerGuard>) {
+    let recent = Recent::default();
+    let appender = paths::state_dir().map(|dir| {
+        Builder::new()
+            .rotation(Rotation::DAILY)
+            .filename_prefix("stemmgpt")
+            .filename_suffix("log")
+            .max_log_files(settings.keep_days)
+            .build(dir.join("logs"))
+    });
+    let (file, guard, error) = match appender {
+        Some(Ok(appender)) => {
+            let (writer, guard) = tracing_appender::non_blocking(appender);
+            (Some(tracing_subscriber::fmt::layer().with_writer(writer).with_ansi(false)), Some(guard), None)
+        },
+        Some(Err(err)) => (None, None, Some(err)),
+        None => (None, None, None),
+    };
+    // Checked when the settings were loaded
+    let filter = EnvFilter::try_new(&settings.filter).unwrap_or_else(|_| EnvFilter::new("info"));
+    let _ = tracing_subscriber::registry().with(filter).with(file).with(recent.clone()).try_init();
+    if let Some(err) = error {
+        tracing::warn!("Could not open a log file, so records are only kept in memory: {err}");
+    }
+    (recent, guard)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_the_latest_records_with_their_fields() {
+        let recent = Recent::default();
+        let subscriber = tracing_subscriber::registry().with(recent.clone());
+        tracing::subscriber::with_default(subscriber, || {
+            for number in 0..=RECENT {
+                tracing::info!(number, "record");
+            }
+            tracing::warn!(path = "output.txt", "Could not remove the reply");
+        });
+
+        recent.with(|records| {
+            assert_eq!(records.len(), RECENT);
+            assert_eq!(records[0].message, "record number=2");
+            let last = records.back().unwrap();
+            assert_eq!(last.level, Level::WARN);
+            assert_eq!(last.module(), "logging::tests");
+            assert_eq!(last.message, "Could not remove the reply path=\"output.txt\"");
+        });
+    }
+}
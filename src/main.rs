@@ -1,35 +1,129 @@
+mod action;
 mod app;
+mod ask;
 mod typewriter;
 mod animation;
+mod clock;
+mod commands;
+mod config;
+mod crash;
+mod state;
+mod status;
+mod theme;
+mod backend;
+mod audio;
+mod braille;
+mod clipboard;
+mod export;
+mod face;
+mod editor;
+mod events;
+#[cfg(test)]
+mod harness;
+mod history;
+mod keymap;
+mod layout;
+mod log_view;
+mod logging;
+mod palette;
+mod paths;
+mod paste;
+mod replay;
+mod terminal;
+mod vim;
 
 use app::App;
+use clap::Parser;
 use color_eyre::eyre::Report;
+use config::{Cli, Command, Config};
 use ratatui::{prelude::CrosstermBackend, Terminal};
 use std::fs;
+use std::io::IsTerminal;
+use std::process::ExitCode;
 use std::time::Duration;
 use std::thread;
 
-fn main() -> Result<(), Report> {
+fn main() -> Result<ExitCode, Report> {
     color_eyre::install()?;
+    crash::install_hook();
+
+    let cli = Cli::parse();
+    match &cli.command {
+        Some(Command::Export(args)) => {
+            export::run(&export::ExportOptions::from_args(args)?)?;
+            return Ok(ExitCode::SUCCESS);
+        },
+        Some(Command::Replay(args)) => {
+            let recording = replay::Recording::load(&args.file)?;
+            in_terminal(|| replay::run(args, &recording))?;
+            return Ok(ExitCode::SUCCESS);
+        },
+        Some(Command::Ask(_)) | None => {},
+    }
+    let config = Config::load(&cli)?;
+    if cli.print_config {
+        print!("{}", toml::to_string_pretty(&config)?);
+        return Ok(ExitCode::SUCCESS);
+    }
+    // Kept until the end so the last records reach the file
+    let (log, _log_guard) = logging::init(&config.log);
+    tracing::info!(version = env!("CARGO_PKG_VERSION"), session = config.session.as_deref(), "Starting");
+    let ask = |args: &ask::AskArgs| {
+        ask::run(args, &config, &mut backend::FileBackend::new(&config.files), &mut std::io::stdin(), &mut std::io::stdout())
+    };
+    match &cli.command {
+        Some(Command::Ask(args)) => return ask(args),
+        // A question piped in is answered without taking over the screen
+        None if !std::io::stdin().is_terminal() => return ask(&ask::AskArgs::piped()),
+        _ => {},
+    }
+
+    // Checked before anything else so mistakes in the file are reported up front
+    let keymap_path = keymap::default_path();
+    let keymap = match &keymap_path {
+        Some(path) => keymap::Keymap::load(path)?,
+        None => keymap::Keymap::default(),
+    };
+
+    let theme = theme::Theme::named(&config.ui.theme).map_err(|err| color_eyre::eyre::eyre!(err))?;
 
     // Wait for Python to be ready
     println!("Waiting for AI model to load...");
-    while fs::metadata("ready.txt").is_err() {
+    while fs::metadata(&config.files.ready).is_err() {
         thread::sleep(Duration::from_millis(100));
     }
     println!("AI model ready!");
+    tracing::info!("The model is ready");
 
-    crossterm::terminal::enable_raw_mode()?;
-    crossterm::execute!(std::io::stdout(), crossterm::terminal::EnterAlternateScreen)?;
-
-    let backend = CrosstermBackend::new(std::io::stdout());
-    let mut terminal = Terminal::new(backend)?;
-    terminal.clear()?;
-
-    let result = App::new().run(terminal);
+    let clock = clock::SystemClock::shared();
+    let app = App::with_clock(&config, clock.clone()).with_keymap(keymap).with_theme(theme).with_log(log);
+    let app = match &cli.record {
+        Some(path) => {
+            // Seeded so the avatar does the same again when played back
+            let seed = rand::random();
+            let (width, height) = crossterm::terminal::size()?;
+            let keymap_text = keymap_path.as_deref().and_then(|path| fs::read_to_string(path).ok());
+            let header = replay::Header::new(width, height, seed, app.vim(), &config, keymap_text);
+            app.with_seed(seed).with_recorder(replay::Recorder::create(path, &header, clock)?)
+        },
+        None => app,
+    };
 
-    let _ = crossterm::execute!(std::io::stdout(), crossterm::terminal::LeaveAlternateScreen);
-    let _ = crossterm::terminal::disable_raw_mode();
+    in_terminal(|| {
+        let backend = CrosstermBackend::new(std::io::stdout());
+        let mut terminal = Terminal::new(backend)?;
+        terminal.clear()?;
+        app.run(&mut terminal, &mut events::TerminalEvents::new()?)
+    })?;
+    tracing::info!("Quitting");
+    Ok(ExitCode::SUCCESS)
+}
 
+// Runs `f` with the terminal in raw mode on the alternate screen, putting it
+// back afterwards. The panic hook puts it back if `f` panics.
+fn in_terminal(f: impl FnOnce() -> Result<(), Report>) -> Result<(), Report> {
+    terminal::enter()?;
+    let result = f();
+    terminal::restore();
     result
-}
\ No newline at end of file
+}
@@ -0,0 +1,106 @@
+use crate::action::{self, Action};
+
+/// The Ctrl+P overlay: a query and the actions that match it.
+pub struct Palette {
+    pub query: String,
+    selected: usize,
+    /// Whether the palette was opened while editing, so actions on the input box are offered
+    editing: bool,
+}
+
+impl Palette {
+    pub fn new(editing: bool) -> Self {
+        Self {
+            query: String::new(),
+            selected: 0,
+            editing,
+        }
+    }
+
+    /// Matching actions, best first. An empty query lists everything.
+    pub fn matches(&self) -> Vec<&'static Action> {
+        let offered = action::ALL.iter().filter(|action| self.editing || !action.edits_input());
+        if self.query.trim().is_empty() {
+            return offered.collect();
+        }
+        let mut scored: Vec<(i32, &Action)> = offered
+            .filter_map(|action| {
+                let name = fuzzy_score(&self.query, action.name());
+                let description = fuzzy_score(&self.query, action.description()).map(|score| score - 50);
+                Some((name.max(description)?, action))
+            })
+            .collect();
+        // Stable, so equally good matches keep the order of `action::ALL`
+        scored.sort_by_key(|&(score, _)| -score);
+        scored.into_iter().map(|(_, action)| action).collect()
+    }
+
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    pub fn selected_action(&self) -> Option<Action> {
+        self.matches().get(self.selected).map(|&action| action.clone())
+    }
+
+    pub fn type_char(&mut self, c: char) {
+        self.query.push(c);
+        self.selected = 0;
+    }
+
+    pub fn backspace(&mut self) {
+        self.query.pop();
+        self.selected = 0;
+    }
+
+    pub fn select_previous(&mut self) {
+        self.selected = self.selected.saturating_sub(1);
+    }
+
+    pub fn select_next(&mut self) {
+        let count = self.matches().len();
+        self.selected = (self.selected + 1).min(count.saturating_sub(1));
+    }
+}
+
+/// Scores `candidate` if every character of `query` appears in it in order.
+///
+/// Runs of consecutive characters and matches at the start of words score higher,
+/// so `kte` prefers `kill-to-end` over words that merely contain those letters.
+fn fuzzy_score(query: &str, candidate: &str) -> Option<i32> {
+    let candidate: Vec<char> = candidate.to_lowercase().chars().collect();
+    let mut score = 0;
+    let mut position = 0;
+    let mut previous: Option<usize> = None;
+    for wanted in query.to_lowercase().chars().filter(|c| !c.is_whitespace()) {
+        let found = (position..candidate.len()).find(|&i| candidate[i] == wanted)?;
+        score += 1;
+        if previous.is_some_and(|previous| previous + 1 == found) {
+            score += 3;
+        }
+        if found == 0 || !candidate[found - 1].is_alphanumeric() {
+            score += 2;
+        }
+        previous = Some(found);
+        position = found + 1;
+    }
+    // Shorter candidates are a closer match for the same letters.
+    Some(score * 100 - candidate.len() as i32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn offers_actions_on_the_input_box_only_while_editing() {
+        let mut normal = Palette::new(false);
+        normal.query.push_str("undo");
+        assert!(normal.matches().iter().all(|action| !action.edits_input()));
+        assert!(Palette::new(false).matches().contains(&&Action::Quit));
+
+        let mut editing = Palette::new(true);
+        editing.query.push_str("undo");
+        assert_eq!(editing.selected_action(), Some(Action::Undo));
+    }
+}
@@ -0,0 +1,146 @@
+/// Pastes with more lines than this are shown as a placeholder until expanded.
+const COLLAPSE_LINES: usize = 10;
+
+/// Large pastes that are standing in the input box as a `[pasted N lines]` placeholder.
+///
+/// Each placeholder is followed by where it is in the input, so the same text
+/// typed by hand is left alone.
+pub struct Pastes {
+    /// Placeholders in the order they were pasted.
+    collapsed: Vec<Collapsed>,
+    /// The input as it was at the last `follow`.
+    input: String,
+}
+
+struct Collapsed {
+    placeholder: String,
+    text: String,
+    /// Byte offset of the placeholder in the input, `None` until it has been inserted.
+    at: Option<usize>,
+}
+
+/// Terminals send pasted line breaks as `\r`; the editor only knows `\n`.
+pub fn normalize(text: &str) -> String {
+    text.replace("\r\n", "\n").replace('\r', "\n")
+}
+
+impl Pastes {
+    pub fn new() -> Self {
+        Self { collapsed: Vec::new(), input: String::new() }
+    }
+
+    /// Returns what to put in the input box for `text`: the text itself, or a placeholder if it is long.
+    pub fn add(&mut self, text: String) -> String {
+        let lines = text.lines().count();
+        if lines <= COLLAPSE_LINES {
+            return text;
+        }
+        let mut placeholder = format!("[pasted {lines} lines]");
+        let mut copy = 1;
+        while self.collapsed.iter().any(|collapsed| collapsed.placeholder == placeholder) {
+            copy += 1;
+            placeholder = format!("[pasted {lines} lines #{copy}]");
+        }
+        self.collapsed.push(Collapsed { placeholder: placeholder.clone(), text, at: None });
+        placeholder
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.collapsed.is_empty()
+    }
+
+    /// Catches up with the input after it was edited: placeholders after the
+    /// edit move with it and any the edit touched are forgotten.
+    pub fn follow(&mut self, input: &str) {
+        if input == self.input {
+            return;
+        }
+        let old = std::mem::replace(&mut self.input, input.to_string());
+        let prefix = old.char_indices().zip(input.chars())
+            .find(|((_, a), b)| a != b)
+            .map_or(old.len().min(input.len()), |((index, _), _)| index);
+        let suffix = old[prefix..].chars().rev().zip(input[prefix..].chars().rev())
+            .take_while(|(a, b)| a == b)
+            .map(|(c, _)| c.len_utf8())
+            .sum::<usize>();
+        let (old_end, new_end) = (old.len() - suffix, input.len() - suffix);
+
+        self.collapsed.retain_mut(|collapsed| match collapsed.at {
+            None => {
+                let inserted = &input[prefix..new_end];
+                collapsed.at = inserted.find(&collapsed.placeholder).map(|index| prefix + index);
+                collapsed.at.is_some()
+            },
+            Some(at) if at + collapsed.placeholder.len() <= prefix => true,
+            Some(at) if at >= old_end => {
+                collapsed.at = Some(at + new_end - old_end);
+                true
+            },
+            Some(_) => false,
+        });
+    }
+
+    /// Puts the pasted text back in place of the placeholders in `input`.
+    pub fn expand(&self, input: &str) -> String {
+        let mut placed: Vec<(usize, &Collapsed)> = self.collapsed.iter()
+            .filter_map(|collapsed| collapsed.at.map(|at| (at, collapsed)))
+            .filter(|(at, collapsed)| input.get(*at..*at + collapsed.placeholder.len()) == Some(collapsed.placeholder.as_str()))
+            .collect();
+        placed.sort_by_key(|(at, _)| *at);
+        let mut expanded = input.to_string();
+        for (at, collapsed) in placed.into_iter().rev() {
+            expanded.replace_range(at..at + collapsed.placeholder.len(), &collapsed.text);
+        }
+        expanded
+    }
+
+    pub fn clear(&mut self) {
+        self.collapsed.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(count: usize) -> String {
+        (1..=count).map(|number| format!("line {number}")).collect::<Vec<_>>().join("\n")
+    }
+
+    #[test]
+    fn collapses_long_pastes_and_expands_them_where_they_are() {
+        let mut pastes = Pastes::new();
+        assert_eq!(pastes.add("short\npaste".to_string()), "short\npaste");
+        assert!(pastes.is_empty());
+
+        let first = pastes.add(lines(12));
+        assert_eq!(first, "[pasted 12 lines]");
+        pastes.follow(&format!("see {first}"));
+        let second = pastes.add(lines(12));
+        assert_eq!(second, "[pasted 12 lines #2]");
+        pastes.follow(&format!("see {first} and {second}"));
+
+        // Typing before a placeholder moves it along
+        pastes.follow(&format!("please see {first} and {second}"));
+        let input = format!("please see {first} and {second}!");
+        pastes.follow(&input);
+        assert_eq!(pastes.expand(&input), format!("please see {} and {}!", lines(12), lines(12)));
+    }
+
+    #[test]
+    fn leaves_placeholders_typed_by_hand_alone() {
+        let mut pastes = Pastes::new();
+        let placeholder = pastes.add(lines(11));
+        pastes.follow(&placeholder);
+
+        let input = format!("{placeholder} is not {placeholder}");
+        pastes.follow(&input);
+        assert_eq!(pastes.expand(&input), format!("{} is not {placeholder}", lines(11)));
+
+        // Editing inside the placeholder forgets the paste
+        let input = input.replacen("11", "1", 1);
+        pastes.follow(&input);
+        assert!(pastes.is_empty());
+        assert_eq!(pastes.expand(&input), input);
+    }
+}
@@ -0,0 +1,38 @@
+use std::{env, path::PathBuf};
+
+const APP_DIR: &str = "stemmgpt";
+
+fn home_subdir(fallback: &str) -> Option<PathBuf> {
+    env::var_os("HOME").map(|home| PathBuf::from(home).join(fallback))
+}
+
+/// Where per-user data that should survive restarts lives, e.g. prompt history.
+///
+/// Follows the XDG base directory spec: `$XDG_STATE_HOME/stemmgpt`, falling
+/// back to `~/.local/state/stemmgpt`.
+pub fn state_dir() -> Option<PathBuf> {
+    env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| home_subdir(".local/state"))
+        .map(|dir| dir.join(APP_DIR))
+}
+
+/// Where the user's configuration lives: `$XDG_CONFIG_HOME/stemmgpt`, falling
+/// back to `~/.config/stemmgpt`.
+pub fn config_dir() -> Option<PathBuf> {
+    env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| home_subdir(".config"))
+        .map(|dir| dir.join(APP_DIR))
+}
+
+/// Name of the person running the app, used to keep their files apart.
+pub fn user_name() -> String {
+    env::var("USER")
+        .or_else(|_| env::var("USERNAME"))
+        .ok()
+        .filter(|name| !name.is_empty() && !name.contains(['/', '\\']))
+        .unwrap_or_else(|| "default".to_string())
+}
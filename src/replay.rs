@@ -0,0 +1,261 @@
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, LineWriter, Write},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use color_eyre::{eyre::{bail, eyre, WrapErr}, Result};
+use crossterm::event::{Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::{backend::CrosstermBackend, layout::Rect, Terminal, TerminalOptions, Viewport};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app::App,
+    backend::ScriptedBackend,
+    clock::{Clock, ManualClock, SharedClock},
+    config::Config,
+    events::{EventSource, Interrupt, TerminalEvents},
+    history::History,
+    keymap::Keymap,
+    theme::Theme,
+};
+
+/// Bumped whenever recordings made by older versions can no longer be played.
+const VERSION: u32 = 1;
+
+/// Longest a replay waits between looking for signals.
+const SIGNAL_CHECK: Duration = Duration::from_millis(50);
+
+/// Arguments of the `replay` command.
+#[derive(clap::Args)]
+pub struct ReplayArgs {
+    /// A recording made with --record
+    pub file: PathBuf,
+    /// How many times faster than it was recorded to play
+    #[arg(long, default_value_t = 1.0)]
+    speed: f64,
+}
+
+/// The first line of a recording, with what it takes to set the app up as it was.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Header {
+    pub version: u32,
+    pub width: u16,
+    pub height: u16,
+    /// What the avatar's randomness was seeded with
+    pub seed: u64,
+    pub session: Option<String>,
+    /// Whether the input box had vim keys
+    pub vim: bool,
+    pub config: Config,
+    /// The keymap file as it was, if there was one
+    pub keymap: Option<String>,
+}
+
+impl Header {
+    pub fn new(width: u16, height: u16, seed: u64, vim: bool, config: &Config, keymap: Option<String>) -> Self {
+        Self { version: VERSION, width, height, seed, session: config.session.clone(), vim, config: config.clone(), keymap }
+    }
+}
+
+/// Something that happened while recording, in the order it happened.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Step {
+    /// The screen was drawn, which is when replies arrive and the typewriter moves on
+    Draw,
+    Event(Event),
+    /// A prompt went to the model, which then had this many characters of conversation to read
+    Sent(usize),
+    Reply(Result<String, String>),
+}
+
+/// Writes each step to a file as it happens, a line of JSON apiece.
+pub struct Recorder {
+    clock: SharedClock,
+    file: LineWriter<File>,
+}
+
+impl Recorder {
+    pub fn create(path: &Path, header: &Header, clock: SharedClock) -> Result<Self> {
+        let file = File::create(path).wrap_err_with(|| format!("Could not create {}", path.display()))?;
+        let mut recorder = Self { clock, file: LineWriter::new(file) };
+        recorder.write(header)?;
+        Ok(recorder)
+    }
+
+    pub fn record(&mut self, step: &Step) -> io::Result<()> {
+        let at = self.clock.now().as_micros() as u64;
+        self.write(&(at, step))
+    }
+
+    fn write(&mut self, value: &impl Serialize) -> io::Result<()> {
+        serde_json::to_writer(&mut self.file, value)?;
+        self.file.write_all(b"\n")
+    }
+}
+
+/// A session read back from a file.
+pub struct Recording {
+    pub header: Header,
+    /// Each step with how long after the start it happened
+    pub steps: Vec<(Duration, Step)>,
+}
+
+impl Recording {
+    pub fn load(path: &Path) -> Result<Self> {
+        let file = File::open(path).wrap_err_with(|| format!("Could not open {}", path.display()))?;
+        let mut lines = BufReader::new(file).lines();
+        let header: Header = match lines.next() {
+            Some(line) => serde_json::from_str(&line?).wrap_err_with(|| format!("{} is not a recording", path.display()))?,
+            None => bail!("{} is empty", path.display()),
+        };
+        if header.version != VERSION {
+            bail!("{} was recorded by another version, which wrote format {} rather than {VERSION}", path.display(), header.version);
+        }
+        let lines = lines.collect::<io::Result<Vec<_>>>()?;
+        let mut steps = Vec::new();
+        for (number, line) in lines.iter().enumerate() {
+            match serde_json::from_str::<(u64, Step)>(line) {
+                Ok((at, step)) => steps.push((Duration::from_micros(at), step)),
+                // A session that ended in a crash can leave half a line at the end
+                Err(_) if number + 1 == lines.len() => break,
+                Err(err) => bail!("{}:{}: not a step of a recording: {err}", path.display(), number + 2),
+            }
+        }
+        Ok(Self { header, steps })
+    }
+
+    /// The app as it was when the recording started, with the recorded replies
+    /// lined up to arrive when they did.
+    pub fn app(&self, clock: SharedClock) -> Result<App> {
+        let header = &self.header;
+        let keymap = match &header.keymap {
+            Some(text) => Keymap::from_toml(text)
+                .map_err(|errors| eyre!("The recorded keymap is invalid:\n  {}", errors.join("\n  ")))?,
+            None => Keymap::default(),
+        };
+        let theme = Theme::named(&header.config.ui.theme).map_err(|err| eyre!(err))?;
+        let replies = self.steps.iter().filter_map(|(at, step)| match step {
+            Step::Reply(reply) => Some((*at, reply.clone())),
+            _ => None,
+        });
+        let context_lens = self.steps.iter().filter_map(|(_, step)| match step {
+            Step::Sent(len) => Some(*len),
+            _ => None,
+        });
+        let backend = ScriptedBackend::new(clock.clone(), replies.collect::<Vec<_>>()).with_context_lens(context_lens);
+        let mut config = header.config.clone();
+        config.session = header.session.clone();
+        // Nothing done while replaying should change the settings, the history,
+        // the clipboard or any other file
+        config.path = None;
+        Ok(App::with_clock(&config, clock)
+            .with_history(History::in_memory())
+            .without_writing_out()
+            .with_keymap(keymap)
+            .with_theme(theme)
+            .with_vim(header.vim)
+            .with_seed(header.seed)
+            .with_backend(Box::new(backend)))
+    }
+}
+
+/// Plays one step back into `app`, first moving `clock` on to when it happened.
+///
+/// Replies are handed over by the app's scripted backend, so `Sent` and
+/// `Reply` steps need nothing doing here.
+pub fn apply<B: ratatui::backend::Backend>(
+    app: &mut App,
+    terminal: &mut Terminal<B>,
+    clock: &ManualClock,
+    (at, step): &(Duration, Step),
+) -> Result<()> {
+    clock.advance(at.saturating_sub(clock.now()));
+    match step {
+        Step::Draw => app.draw(terminal)?,
+        Step::Event(event) => app.handle_event(event.clone()),
+        Step::Sent(_) | Step::Reply(_) => {},
+    }
+    Ok(())
+}
+
+/// Plays a recording in the terminal at the speed it was recorded, times `speed`.
+///
+/// Esc, q or Ctrl+C stop it early; otherwise the last screen stays up until a key is pressed.
+pub fn run(args: &ReplayArgs, recording: &Recording) -> Result<()> {
+    if args.speed <= 0.0 {
+        bail!("--speed must be more than 0");
+    }
+    let screen = crossterm::terminal::size()?;
+    // The recorded screen size, or as much of it as fits
+    let area = Rect::new(0, 0, recording.header.width.min(screen.0), recording.header.height.min(screen.1));
+    let mut terminal = Terminal::with_options(
+        CrosstermBackend::new(io::stdout()),
+        TerminalOptions { viewport: Viewport::Fixed(area) },
+    )?;
+    terminal.clear()?;
+
+    let clock = ManualClock::new();
+    let mut app = recording.app(clock.clone())?;
+    let mut events = TerminalEvents::new()?;
+    let mut start = Instant::now();
+    for step in &recording.steps {
+        while let Some(wait) = (start + step.0.div_f64(args.speed)).checked_duration_since(Instant::now()) {
+            // A tick at a time, so signals are seen promptly
+            if events.next(wait.min(SIGNAL_CHECK))?.is_some_and(|event| stops(&event)) {
+                return Ok(());
+            }
+            let now = Instant::now();
+            match handle_interrupt(&mut events, &mut terminal, None)? {
+                Some(Interrupt::Quit) => return Ok(()),
+                // Carries on from where it was rather than jumping ahead
+                Some(Interrupt::Suspend) => start += now.elapsed(),
+                None => {},
+            }
+        }
+        apply(&mut app, &mut terminal, &clock, step)?;
+    }
+    loop {
+        if events.next(SIGNAL_CHECK)?.is_some_and(|event| matches!(event, Event::Key(key) if key.kind == KeyEventKind::Press)) {
+            return Ok(());
+        }
+        if handle_interrupt(&mut events, &mut terminal, Some(&mut app))? == Some(Interrupt::Quit) {
+            return Ok(());
+        }
+    }
+}
+
+// Suspends if a signal asked to, passing on any signal that came in.
+//
+// On resuming, the screen is drawn again in full by the next recorded draw,
+// as drawing here would move the app on ahead of the recording. Once the
+// recording is over, `finished` is drawn straight away instead.
+fn handle_interrupt<B: ratatui::backend::Backend>(
+    events: &mut impl EventSource,
+    terminal: &mut Terminal<B>,
+    finished: Option<&mut App>,
+) -> Result<Option<Interrupt>> {
+    let interrupt = events.interrupt();
+    if interrupt == Some(Interrupt::Suspend) {
+        events.suspend()?;
+        terminal.clear()?;
+        if let Some(app) = finished {
+            app.draw(terminal)?;
+        }
+    }
+    Ok(interrupt)
+}
+
+fn stops(event: &Event) -> bool {
+    let Event::Key(key) = event else {
+        return false;
+    };
+    key.kind == KeyEventKind::Press
+        && match key.code {
+            KeyCode::Esc | KeyCode::Char('q') => true,
+            KeyCode::Char('c') => key.modifiers.contains(KeyModifiers::CONTROL),
+            _ => false,
+        }
+}
@@ -0,0 +1,198 @@
+use std::{collections::VecDeque, str::FromStr, time::Duration};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[allow(clippy::upper_case_acronyms)]
+pub enum State {
+    TALKING,
+    IDLE,
+    DYING,
+    BLINKING,
+    THINKING,
+    LISTENING,
+    ERROR,
+}
+
+impl FromStr for State {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_ascii_lowercase().as_str() {
+            "talking" => Ok(TALKING),
+            "idle" => Ok(IDLE),
+            "dying" => Ok(DYING),
+            "blinking" => Ok(BLINKING),
+            "thinking" => Ok(THINKING),
+            "listening" => Ok(LISTENING),
+            "error" => Ok(ERROR),
+            _ => Err(format!("unknown avatar state '{name}'")),
+        }
+    }
+}
+
+/// Something that happened in the app which may move the avatar to another state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trigger {
+    Blink,
+    BlinkDone,
+    Typing,
+    StoppedTyping,
+    RequestSent,
+    ReplyStarted,
+    ReplyFinished,
+    BackendFailed,
+    Panic,
+    Revive,
+}
+
+/// Emitted every time the avatar actually changes state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Transition {
+    pub from: State,
+    pub to: State,
+    pub trigger: Trigger,
+}
+
+struct Rule {
+    from: &'static [State],
+    trigger: Trigger,
+    to: State,
+}
+
+use State::*;
+
+const ALIVE: &[State] = &[TALKING, IDLE, BLINKING, THINKING, LISTENING, ERROR];
+
+// Any (state, trigger) pair not listed here is ignored.
+const RULES: &[Rule] = &[
+    Rule { from: &[IDLE], trigger: Trigger::Blink, to: BLINKING },
+    Rule { from: &[BLINKING], trigger: Trigger::BlinkDone, to: IDLE },
+    Rule { from: &[IDLE, BLINKING, ERROR], trigger: Trigger::Typing, to: LISTENING },
+    Rule { from: &[LISTENING], trigger: Trigger::StoppedTyping, to: IDLE },
+    Rule { from: &[IDLE, BLINKING, LISTENING, ERROR], trigger: Trigger::RequestSent, to: THINKING },
+    Rule { from: &[IDLE, BLINKING, LISTENING, THINKING], trigger: Trigger::ReplyStarted, to: TALKING },
+    Rule { from: &[TALKING], trigger: Trigger::ReplyFinished, to: IDLE },
+    Rule { from: &[IDLE, BLINKING, LISTENING, THINKING, TALKING], trigger: Trigger::BackendFailed, to: ERROR },
+    Rule { from: ALIVE, trigger: Trigger::Panic, to: DYING },
+    Rule { from: &[DYING], trigger: Trigger::Revive, to: IDLE },
+];
+
+pub fn next_state(from: State, trigger: Trigger) -> Option<State> {
+    RULES.iter()
+        .find(|rule| rule.trigger == trigger && rule.from.contains(&from))
+        .map(|rule| rule.to)
+}
+
+pub struct StateMachine {
+    state: State,
+    since: Duration,
+    transitions: VecDeque<Transition>,
+}
+
+impl StateMachine {
+    pub fn new(now: Duration) -> Self {
+        Self {
+            state: IDLE,
+            since: now,
+            transitions: VecDeque::new(),
+        }
+    }
+
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    /// When the current state was entered.
+    pub fn since(&self) -> Duration {
+        self.since
+    }
+
+    /// Applies `trigger`, returning whether the state changed.
+    pub fn fire(&mut self, trigger: Trigger, now: Duration) -> bool {
+        match next_state(self.state, trigger) {
+            Some(to) if to != self.state => {
+                self.transitions.push_back(Transition { from: self.state, to, trigger });
+                self.state = to;
+                self.since = now;
+                true
+            },
+            _ => false,
+        }
+    }
+
+    /// Moves to `state` unconditionally and without recording a transition.
+    pub fn reset_to(&mut self, state: State, now: Duration) {
+        self.state = state;
+        self.since = now;
+    }
+
+    /// Hands out the transitions recorded since the last call.
+    pub fn drain_transitions(&mut self) -> impl Iterator<Item = Transition> + '_ {
+        self.transitions.drain(..)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL: &[State] = &[TALKING, IDLE, DYING, BLINKING, THINKING, LISTENING, ERROR];
+    const TRIGGERS: &[Trigger] = &[
+        Trigger::Blink, Trigger::BlinkDone, Trigger::Typing, Trigger::StoppedTyping, Trigger::RequestSent,
+        Trigger::ReplyStarted, Trigger::ReplyFinished, Trigger::BackendFailed, Trigger::Panic, Trigger::Revive,
+    ];
+
+    #[test]
+    fn allowed_transitions() {
+        let cases = [
+            (IDLE, Trigger::Blink, BLINKING),
+            (BLINKING, Trigger::BlinkDone, IDLE),
+            (IDLE, Trigger::Typing, LISTENING),
+            (ERROR, Trigger::Typing, LISTENING),
+            (LISTENING, Trigger::StoppedTyping, IDLE),
+            (LISTENING, Trigger::RequestSent, THINKING),
+            (ERROR, Trigger::RequestSent, THINKING),
+            (THINKING, Trigger::ReplyStarted, TALKING),
+            (IDLE, Trigger::ReplyStarted, TALKING),
+            (TALKING, Trigger::ReplyFinished, IDLE),
+            (THINKING, Trigger::BackendFailed, ERROR),
+            (TALKING, Trigger::BackendFailed, ERROR),
+            (LISTENING, Trigger::BackendFailed, ERROR),
+            (IDLE, Trigger::BackendFailed, ERROR),
+            (ERROR, Trigger::Panic, DYING),
+            (THINKING, Trigger::Panic, DYING),
+            (DYING, Trigger::Revive, IDLE),
+        ];
+        for (from, trigger, to) in cases {
+            let mut machine = StateMachine::new(Duration::ZERO);
+            machine.reset_to(from, Duration::ZERO);
+            assert!(machine.fire(trigger, Duration::from_secs(1)), "{from:?} + {trigger:?}");
+            assert_eq!(machine.state(), to, "{from:?} + {trigger:?}");
+            assert_eq!(machine.since(), Duration::from_secs(1));
+            let transitions: Vec<_> = machine.drain_transitions().collect();
+            assert_eq!(transitions, [Transition { from, to, trigger }]);
+        }
+    }
+
+    #[test]
+    fn rejected_triggers_leave_the_state_alone() {
+        for &from in ALL {
+            for &trigger in TRIGGERS {
+                if next_state(from, trigger).is_some_and(|to| to != from) {
+                    continue;
+                }
+                let mut machine = StateMachine::new(Duration::ZERO);
+                machine.reset_to(from, Duration::ZERO);
+                assert!(!machine.fire(trigger, Duration::from_secs(1)), "{from:?} + {trigger:?}");
+                assert_eq!(machine.state(), from);
+                assert_eq!(machine.since(), Duration::ZERO);
+                assert_eq!(machine.drain_transitions().count(), 0);
+            }
+        }
+        // The dead only come back when revived
+        for &trigger in TRIGGERS.iter().filter(|&&trigger| trigger != Trigger::Revive) {
+            assert_eq!(next_state(DYING, trigger), None, "{trigger:?}");
+        }
+        assert_eq!(next_state(THINKING, Trigger::Typing), None);
+        assert_eq!(next_state(IDLE, Trigger::ReplyFinished), None);
+    }
+}
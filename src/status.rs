@@ -0,0 +1,172 @@
+use std::time::Duration;
+
+use ratatui::{
+    layout::{Constraint, Layout, Rect},
+    style::Modifier,
+    text::{Line, Span},
+    widgets::Paragraph,
+    Frame,
+};
+
+use crate::{backend::Connection, clock::SharedClock, theme::Theme};
+
+/// How long a notification stays up, the last second of which it spends fading.
+const NOTICE_TIME: Duration = Duration::from_secs(4);
+const FADE_TIME: Duration = Duration::from_secs(1);
+
+/// Roughly how many tokens a model would split `chars` characters of English into.
+pub fn estimate_tokens(chars: usize) -> usize {
+    chars.div_ceil(4)
+}
+
+struct Notice {
+    text: String,
+    error: bool,
+    shown_at: Duration,
+}
+
+// Timings and sizes of the last exchange with the model.
+#[derive(Default)]
+struct Exchange {
+    sent_at: Duration,
+    prompt_tokens: usize,
+    reply_tokens: Option<usize>,
+    /// From sending the prompt until the whole reply came back, leaving out
+    /// the time spent typing it out
+    reply_time: Option<Duration>,
+}
+
+/// The line along the bottom of the screen: what the app is doing, how the
+/// last reply went and short-lived notifications.
+pub struct StatusBar {
+    clock: SharedClock,
+    session: String,
+    notice: Option<Notice>,
+    exchange: Option<Exchange>,
+    /// Whether the last request failed
+    failed: bool,
+}
+
+impl StatusBar {
+    pub fn new(clock: SharedClock, session: String) -> Self {
+        Self { clock, session, notice: None, exchange: None, failed: false }
+    }
+
+    /// Shows `text` for a few seconds, replacing any notification already up.
+    pub fn notify(&mut self, text: impl Into<String>) {
+        let text = text.into();
+        tracing::info!("{text}");
+        self.notice = Some(Notice { text, error: false, shown_at: self.clock.now() });
+    }
+
+    /// Like `notify`, in the error style.
+    pub fn warn(&mut self, text: impl Into<String>) {
+        let text = text.into();
+        tracing::warn!("{text}");
+        self.notice = Some(Notice { text, error: true, shown_at: self.clock.now() });
+    }
+
+    /// A prompt went off with `context_chars` characters of conversation for the model to read.
+    pub fn sent(&mut self, context_chars: usize) {
+        self.failed = false;
+        self.exchange = Some(Exchange {
+            sent_at: self.clock.now(),
+            prompt_tokens: estimate_tokens(context_chars),
+            ..Exchange::default()
+        });
+    }
+
+    /// The reply came back from the model. Later calls are ignored.
+    pub fn replied(&mut self, reply: &str) {
+        let now = self.clock.now();
+        if let Some(exchange) = self.exchange.as_mut().filter(|exchange| exchange.reply_time.is_none()) {
+            exchange.reply_time = Some(now - exchange.sent_at);
+            exchange.reply_tokens = Some(estimate_tokens(reply.chars().count()));
+        }
+    }
+
+    pub fn failed(&mut self) {
+        self.failed = true;
+    }
+
+    /// Draws the bar, with `mode` saying what keys do at the moment.
+    pub fn render(&mut self, frame: &mut Frame, area: Rect, mode: &str, connection: Connection, theme: &Theme) {
+        let now = self.clock.now();
+        if self.notice.as_ref().is_some_and(|notice| now.saturating_sub(notice.shown_at) >= NOTICE_TIME) {
+            self.notice = None;
+        }
+
+        let separator = || Span::styled(" │ ", theme.status.add_modifier(Modifier::DIM));
+        let mut left = vec![Span::styled(format!(" {mode} "), theme.status.add_modifier(Modifier::REVERSED | Modifier::BOLD))];
+        left.push(Span::raw(" "));
+        left.push(match (connection, self.failed) {
+            (Connection::Busy, _) => Span::styled("thinking…", theme.status),
+            (_, true) => Span::styled("last request failed", theme.error),
+            (Connection::Ready, _) => Span::styled("ready", theme.status),
+            (Connection::Offline, _) => Span::styled("model offline", theme.error),
+        });
+        if let Some(exchange) = &self.exchange {
+            let seconds = exchange.reply_time.map_or("…".to_string(), |time| format!("{:.1}s", time.as_secs_f64()));
+            left.push(separator());
+            left.push(Span::styled(format!("reply in {seconds}"), theme.status));
+            let reply_tokens = exchange.reply_tokens.map_or("…".to_string(), |tokens| format!("~{tokens}"));
+            left.push(separator());
+            left.push(Span::styled(
+                format!("~{} prompt / {reply_tokens} reply tokens", exchange.prompt_tokens),
+                theme.status,
+            ));
+        }
+
+        let mut right = Vec::new();
+        if let Some(notice) = &self.notice {
+            let style = if notice.error { theme.error } else { theme.status.add_modifier(Modifier::BOLD) };
+            let fading = NOTICE_TIME.saturating_sub(now.saturating_sub(notice.shown_at)) <= FADE_TIME;
+            let style = if fading { style.add_modifier(Modifier::DIM).remove_modifier(Modifier::BOLD) } else { style };
+            right.push(Span::styled(notice.text.clone(), style));
+            right.push(separator());
+        }
+        right.push(Span::styled(format!("{} ", self.session), theme.status.add_modifier(Modifier::ITALIC)));
+
+        // The notification and session keep their place, squeezing the rest if need be
+        let right = Line::from(right);
+        let [left_area, right_area] = Layout::horizontal([Constraint::Min(0), Constraint::Length(right.width() as u16)]).areas(area);
+        frame.render_widget(Paragraph::new(Line::from(left)).style(theme.status), left_area);
+        frame.render_widget(Paragraph::new(right).style(theme.status), right_area);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+
+    #[test]
+    fn times_the_last_reply_and_lets_notices_fade() {
+        let clock = ManualClock::new();
+        let mut status = StatusBar::new(clock.clone(), "test".to_string());
+        status.sent(400);
+        clock.advance(Duration::from_millis(1500));
+        status.replied("four score");
+        clock.advance(Duration::from_secs(2));
+        status.replied("and seven years ago");
+
+        let exchange = status.exchange.as_ref().unwrap();
+        assert_eq!(exchange.prompt_tokens, 100);
+        assert_eq!(exchange.reply_tokens, Some(3));
+        assert_eq!(exchange.reply_time, Some(Duration::from_millis(1500)));
+
+        let backend = ratatui::backend::TestBackend::new(80, 1);
+        let mut terminal = ratatui::Terminal::new(backend).unwrap();
+        status.notify("Copied");
+        let mut draw = |status: &mut StatusBar| {
+            terminal.draw(|frame| status.render(frame, frame.size(), "NORMAL", Connection::Ready, &Theme::default())).unwrap();
+            let buffer = terminal.backend().buffer();
+            (0..80).map(|x| buffer.get(x, 0).symbol()).collect::<String>()
+        };
+        let line = draw(&mut status);
+        assert!(line.starts_with(" NORMAL  ready │ reply in 1.5s │ ~100 prompt / ~3 reply tokens"), "{line}");
+        assert!(line.trim_end().ends_with("Copied │ test"), "{line}");
+        clock.advance(NOTICE_TIME);
+        assert!(!draw(&mut status).contains("Copied"));
+    }
+}
@@ -0,0 +1,70 @@
+//! Taking over the terminal for the full-screen app and handing it back,
+//! which has to happen however the app stops: returning, panicking, being
+//! killed or being suspended with Ctrl+Z.
+
+use std::{
+    io::{self, stdout},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use crossterm::{
+    event::{
+        DisableBracketedPaste, DisableMouseCapture, EnableBracketedPaste, EnableMouseCapture,
+        KeyboardEnhancementFlags, PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+    },
+    execute,
+    terminal::{self, EnterAlternateScreen, LeaveAlternateScreen},
+};
+
+/// Whether the terminal is in raw mode on the alternate screen.
+static ENTERED: AtomicBool = AtomicBool::new(false);
+/// Whether keyboard enhancement flags were pushed and need popping.
+static ENHANCED_KEYS: AtomicBool = AtomicBool::new(false);
+
+/// Puts the terminal in raw mode on the alternate screen.
+pub fn enter() -> io::Result<()> {
+    terminal::enable_raw_mode()?;
+    ENTERED.store(true, Ordering::SeqCst);
+    execute!(
+        stdout(),
+        EnterAlternateScreen,
+        // Pasted text arrives as a single event instead of a stream of key presses
+        EnableBracketedPaste,
+        // Clicks, the wheel and dragging over panes and their borders
+        EnableMouseCapture
+    )?;
+    // Lets terminals that support it report Shift+Enter separately from Enter
+    if terminal::supports_keyboard_enhancement().unwrap_or(false) {
+        execute!(stdout(), PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES))?;
+        ENHANCED_KEYS.store(true, Ordering::SeqCst);
+    }
+    Ok(())
+}
+
+/// Puts the terminal back as `enter` found it. Does nothing if it already is,
+/// so it is safe to call from a panic hook as well as on the way out.
+pub fn restore() {
+    if !ENTERED.swap(false, Ordering::SeqCst) {
+        return;
+    }
+    if ENHANCED_KEYS.swap(false, Ordering::SeqCst) {
+        let _ = execute!(stdout(), PopKeyboardEnhancementFlags);
+    }
+    let _ = execute!(stdout(), DisableMouseCapture, DisableBracketedPaste, LeaveAlternateScreen);
+    let _ = terminal::disable_raw_mode();
+}
+
+/// Hands the terminal back to the shell and stops the process, as Ctrl+Z
+/// would outside raw mode, then takes the terminal over again once the
+/// shell brings the app back with `fg`.
+#[cfg(unix)]
+pub fn suspend() -> io::Result<()> {
+    restore();
+    signal_hook::low_level::emulate_default_handler(signal_hook::consts::SIGTSTP)?;
+    enter()
+}
+
+#[cfg(not(unix))]
+pub fn suspend() -> io::Result<()> {
+    Ok(())
+}
@@ -0,0 +1,341 @@
+use std::{fs, io, path::PathBuf};
+
+use ratatui::style::{Color, Modifier, Style};
+
+use crate::{paths, typewriter::Role};
+
+/// Themes that are always available, the first being the default.
+pub const BUILT_IN: &[&str] = &["classic", "dark", "light", "high-contrast"];
+
+// Stops a theme file that inherits from itself going round forever.
+const MAX_INHERITANCE: usize = 8;
+
+/// Where theme files live: `$XDG_CONFIG_HOME/stemmgpt/themes/<name>.toml`.
+pub fn themes_dir() -> Option<PathBuf> {
+    paths::config_dir().map(|dir| dir.join("themes"))
+}
+
+/// Named styles for every part of the screen.
+#[derive(Debug, Clone)]
+pub struct Theme {
+    pub name: String,
+    /// The conversation and the background behind it
+    pub text: Style,
+    /// Panes that do not have focus
+    pub border: Style,
+    /// The pane being typed in
+    pub accent: Style,
+    /// Border around the welcome banner
+    pub header: Style,
+    pub title: Style,
+    pub subtitle: Style,
+    pub hint: Style,
+    pub user: Style,
+    pub assistant: Style,
+    pub system: Style,
+    pub error: Style,
+    pub avatar: Style,
+    /// Command popup and palette
+    pub popup: Style,
+    /// Highlighted popup entries and selected text
+    pub selection: Style,
+    /// Key bindings listed in the palette
+    pub key: Style,
+    /// The status bar along the bottom
+    pub status: Style,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::classic()
+    }
+}
+
+fn fg(colour: Color) -> Style {
+    Style::default().fg(colour)
+}
+
+impl Theme {
+    /// The original look: blue on white conversation and a green avatar.
+    fn classic() -> Self {
+        Self {
+            name: "classic".to_string(),
+            text: Style::default().fg(Color::Rgb(0, 0, 255)).bg(Color::White),
+            border: Style::default(),
+            accent: fg(Color::Yellow),
+            header: fg(Color::Magenta),
+            title: fg(Color::Green).add_modifier(Modifier::BOLD),
+            subtitle: fg(Color::Blue),
+            hint: fg(Color::Yellow).add_modifier(Modifier::ITALIC),
+            user: fg(Color::Rgb(120, 0, 160)).add_modifier(Modifier::BOLD),
+            assistant: fg(Color::Rgb(0, 0, 255)).add_modifier(Modifier::BOLD),
+            system: fg(Color::Rgb(160, 80, 0)).add_modifier(Modifier::BOLD),
+            error: fg(Color::Rgb(255, 64, 64)),
+            avatar: fg(Color::Rgb(0, 255, 0)),
+            popup: Style::default().fg(Color::Yellow).bg(Color::Black),
+            selection: Style::default().add_modifier(Modifier::REVERSED),
+            key: fg(Color::Cyan),
+            status: Style::default().fg(Color::White).bg(Color::Blue),
+        }
+    }
+
+    fn dark() -> Self {
+        Self {
+            name: "dark".to_string(),
+            text: Style::default().fg(Color::Gray).bg(Color::Rgb(24, 24, 32)),
+            border: fg(Color::DarkGray),
+            accent: fg(Color::LightCyan),
+            header: fg(Color::DarkGray),
+            title: fg(Color::LightCyan).add_modifier(Modifier::BOLD),
+            subtitle: fg(Color::Gray),
+            hint: fg(Color::DarkGray).add_modifier(Modifier::ITALIC),
+            user: fg(Color::LightMagenta).add_modifier(Modifier::BOLD),
+            assistant: fg(Color::LightBlue).add_modifier(Modifier::BOLD),
+            system: fg(Color::Yellow).add_modifier(Modifier::BOLD),
+            error: fg(Color::LightRed),
+            avatar: fg(Color::LightGreen),
+            popup: Style::default().fg(Color::Gray).bg(Color::Rgb(40, 40, 52)),
+            selection: Style::default().fg(Color::Black).bg(Color::LightCyan),
+            key: fg(Color::LightCyan),
+            status: Style::default().fg(Color::Gray).bg(Color::Rgb(40, 40, 52)),
+        }
+    }
+
+    fn light() -> Self {
+        Self {
+            name: "light".to_string(),
+            text: Style::default().fg(Color::Black).bg(Color::Rgb(250, 250, 245)),
+            border: fg(Color::Gray),
+            accent: fg(Color::Blue),
+            header: fg(Color::Gray),
+            title: fg(Color::Blue).add_modifier(Modifier::BOLD),
+            subtitle: fg(Color::DarkGray),
+            hint: fg(Color::DarkGray).add_modifier(Modifier::ITALIC),
+            user: fg(Color::Magenta).add_modifier(Modifier::BOLD),
+            assistant: fg(Color::Blue).add_modifier(Modifier::BOLD),
+            system: fg(Color::Rgb(150, 80, 0)).add_modifier(Modifier::BOLD),
+            error: fg(Color::Red),
+            avatar: fg(Color::Rgb(0, 140, 0)),
+            popup: Style::default().fg(Color::Black).bg(Color::Rgb(230, 230, 220)),
+            selection: Style::default().fg(Color::White).bg(Color::Blue),
+            key: fg(Color::Blue),
+            status: Style::default().fg(Color::Black).bg(Color::Rgb(220, 220, 210)),
+        }
+    }
+
+    /// Only black, white and bright colours, with bold where it helps.
+    fn high_contrast() -> Self {
+        let bold = |colour| fg(colour).add_modifier(Modifier::BOLD);
+        Self {
+            name: "high-contrast".to_string(),
+            text: Style::default().fg(Color::White).bg(Color::Black),
+            border: fg(Color::White),
+            accent: bold(Color::Yellow),
+            header: fg(Color::White),
+            title: bold(Color::White),
+            subtitle: fg(Color::White),
+            hint: bold(Color::Yellow),
+            user: bold(Color::LightCyan),
+            assistant: bold(Color::White),
+            system: bold(Color::Yellow),
+            error: bold(Color::LightRed),
+            avatar: bold(Color::LightGreen),
+            popup: Style::default().fg(Color::White).bg(Color::Black),
+            selection: Style::default().fg(Color::Black).bg(Color::Yellow).add_modifier(Modifier::BOLD),
+            key: bold(Color::LightCyan),
+            status: Style::default().fg(Color::Black).bg(Color::White),
+        }
+    }
+
+    fn built_in(name: &str) -> Option<Self> {
+        match name {
+            "classic" => Some(Self::classic()),
+            "dark" => Some(Self::dark()),
+            "light" => Some(Self::light()),
+            "high-contrast" => Some(Self::high_contrast()),
+            _ => None,
+        }
+    }
+
+    /// A built-in theme, or one read from the themes directory.
+    ///
+    /// Files are read afresh each time so edits show up when the theme is picked again.
+    pub fn named(name: &str) -> Result<Self, String> {
+        Self::resolve(name, 0)
+    }
+
+    fn resolve(name: &str, depth: usize) -> Result<Self, String> {
+        if let Some(theme) = Self::built_in(name) {
+            return Ok(theme);
+        }
+        // A theme is a file in the themes directory and nowhere else
+        if name.is_empty() || name.contains("..") || name.chars().any(std::path::is_separator) {
+            return Err(format!("'{name}' is not a theme name"));
+        }
+        if depth >= MAX_INHERITANCE {
+            return Err(format!("theme '{name}' inherits from too many others, is there a loop?"));
+        }
+        let Some(path) = themes_dir().map(|dir| dir.join(format!("{name}.toml"))) else {
+            return Err(format!("unknown theme '{name}'"));
+        };
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                return Err(format!("unknown theme '{name}', try one of {}", available().join(", ")));
+            },
+            Err(err) => return Err(format!("could not read {}: {err}", path.display())),
+        };
+        Self::from_toml(name, &text, depth)
+            .map_err(|errors| format!("invalid theme {}:\n  {}", path.display(), errors.join("\n  ")))
+    }
+
+    /// Reads a theme file: an optional `inherits = "<theme>"` (classic by default)
+    /// and any of the styles to change, either as a colour or as a table such as
+    /// `user = { fg = "#ff88ff", bold = true }`.
+    fn from_toml(name: &str, text: &str, depth: usize) -> Result<Self, Vec<String>> {
+        let table: toml::Table = text.parse().map_err(|err: toml::de::Error| vec![err.to_string().trim_end().to_string()])?;
+        let base = match table.get("inherits") {
+            None => "classic",
+            Some(toml::Value::String(base)) => base,
+            Some(_) => return Err(vec!["inherits should name a theme".to_string()]),
+        };
+        let mut theme = Self::resolve(base, depth + 1).map_err(|err| vec![err])?;
+        theme.name = name.to_string();
+
+        let mut errors = Vec::new();
+        for (key, value) in table.iter().filter(|(key, _)| *key != "inherits") {
+            let Some(style) = theme.style_mut(key) else {
+                errors.push(format!("unknown style '{key}', expected one of {}", STYLES.join(", ")));
+                continue;
+            };
+            match parse_style(value) {
+                Ok(parsed) => *style = parsed,
+                Err(err) => errors.push(format!("{key}: {err}")),
+            }
+        }
+        match errors.is_empty() {
+            true => Ok(theme),
+            false => Err(errors),
+        }
+    }
+
+    fn style_mut(&mut self, name: &str) -> Option<&mut Style> {
+        Some(match name {
+            "text" => &mut self.text,
+            "border" => &mut self.border,
+            "accent" => &mut self.accent,
+            "header" => &mut self.header,
+            "title" => &mut self.title,
+            "subtitle" => &mut self.subtitle,
+            "hint" => &mut self.hint,
+            "user" => &mut self.user,
+            "assistant" => &mut self.assistant,
+            "system" => &mut self.system,
+            "error" => &mut self.error,
+            "avatar" => &mut self.avatar,
+            "popup" => &mut self.popup,
+            "selection" => &mut self.selection,
+            "key" => &mut self.key,
+            "status" => &mut self.status,
+            _ => return None,
+        })
+    }
+
+    /// How to label messages from `role`.
+    pub fn role(&self, role: Role) -> Style {
+        match role {
+            Role::User => self.user,
+            Role::Assistant => self.assistant,
+            Role::System => self.system,
+            Role::Error => self.error,
+        }
+    }
+}
+
+const STYLES: &[&str] = &[
+    "text", "border", "accent", "header", "title", "subtitle", "hint",
+    "user", "assistant", "system", "error", "avatar", "popup", "selection", "key", "status",
+];
+
+const MODIFIERS: &[(&str, Modifier)] = &[
+    ("bold", Modifier::BOLD),
+    ("dim", Modifier::DIM),
+    ("italic", Modifier::ITALIC),
+    ("underlined", Modifier::UNDERLINED),
+    ("reversed", Modifier::REVERSED),
+];
+
+fn parse_style(value: &toml::Value) -> Result<Style, String> {
+    let parse_colour = |value: &toml::Value| -> Result<Color, String> {
+        let name = value.as_str().ok_or("colours are strings such as \"red\" or \"#ff0000\"")?;
+        name.parse().map_err(|_| format!("unknown colour '{name}'"))
+    };
+    let table = match value {
+        toml::Value::Table(table) => table,
+        colour => return Ok(Style::default().fg(parse_colour(colour)?)),
+    };
+    let mut style = Style::default();
+    for (key, value) in table {
+        match key.as_str() {
+            "fg" => style = style.fg(parse_colour(value)?),
+            "bg" => style = style.bg(parse_colour(value)?),
+            key => {
+                let &(_, modifier) = MODIFIERS.iter().find(|(name, _)| *name == key)
+                    .ok_or_else(|| format!("unknown setting '{key}', expected fg, bg or a modifier such as bold"))?;
+                match value.as_bool() {
+                    Some(true) => style = style.add_modifier(modifier),
+                    Some(false) => style = style.remove_modifier(modifier),
+                    None => return Err(format!("{key} should be true or false")),
+                }
+            },
+        }
+    }
+    Ok(style)
+}
+
+/// Names of the built-in themes followed by any in the themes directory.
+pub fn available() -> Vec<String> {
+    let mut names: Vec<String> = BUILT_IN.iter().map(|name| name.to_string()).collect();
+    let mut files: Vec<String> = themes_dir()
+        .and_then(|dir| fs::read_dir(dir).ok())
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            let stem = path.file_stem()?.to_str()?;
+            (path.extension()? == "toml" && !BUILT_IN.contains(&stem)).then(|| stem.to_string())
+        })
+        .collect();
+    files.sort();
+    names.extend(files);
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn files_change_styles_on_top_of_their_base() {
+        let theme = Theme::from_toml("mine", "inherits = \"dark\"\nuser = { fg = \"#ff0000\", bold = true }\nerror = \"yellow\"", 0).unwrap();
+        assert_eq!(theme.name, "mine");
+        assert_eq!(theme.user, Style::default().fg(Color::Rgb(255, 0, 0)).add_modifier(Modifier::BOLD));
+        assert_eq!(theme.error, Style::default().fg(Color::Yellow));
+        assert_eq!(theme.text, Theme::dark().text);
+    }
+
+    #[test]
+    fn reports_every_mistake() {
+        let errors = Theme::from_toml("mine", "usr = \"red\"\naccent = \"reddish\"\nkey = { fg = \"red\", blink = true }", 0).unwrap_err();
+        assert_eq!(errors.len(), 3, "{errors:?}");
+    }
+
+    #[test]
+    fn names_stay_inside_the_themes_directory() {
+        for name in ["../config", "a/b", "..", ""] {
+            assert_eq!(Theme::named(name).err(), Some(format!("'{name}' is not a theme name")));
+        }
+        let errors = Theme::from_toml("mine", "inherits = \"../../secret\"", 0).unwrap_err();
+        assert!(errors[0].contains("is not a theme name"), "{errors:?}");
+    }
+}
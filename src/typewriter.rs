@@ -1,83 +1,388 @@
-use chrono::Utc;
-use ratatui::{layout::Alignment, style::{Color, Style}, widgets::{Block, Paragraph, Widget, Wrap}};
+use std::time::Duration;
 
-use crate::animation::State;
+use ratatui::{
+    layout::{Alignment, Rect},
+    text::{Line, Span},
+    widgets::{Block, Paragraph, Wrap},
+    Frame,
+};
 
+use crate::{clipboard, clock::SharedClock, config::TypewriterSettings, state::Trigger, theme::Theme};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    User,
+    Assistant,
+    /// Notices from the app itself, e.g. command output.
+    System,
+    /// Failures to get a reply from the model.
+    Error,
+}
+
+impl Role {
+    pub fn label(self) -> &'static str {
+        match self {
+            Role::User => "You",
+            Role::Assistant => "STEMM GPT",
+            Role::System => "System",
+            Role::Error => "Error",
+        }
+    }
+}
+
+pub struct Message {
+    pub role: Role,
+    pub text: String,
+}
+
+/// Fastest and slowest the typewriter can be set to go, a character at a time.
+pub const MIN_CHAR_DELAY: Duration = Duration::from_millis(1);
+pub const MAX_CHAR_DELAY: Duration = Duration::from_millis(1000);
+
+/// The conversation so far, with the newest message typed out a character at a time.
 pub struct Typewriter {
+    clock: SharedClock,
     current_message_index: usize,
     visible_chars: usize,
-    last_char_time: i64,
-    char_delay_ms: i64,
-    messages: Vec<String>,
+    last_char_time: Duration,
+    /// The delay the user asked for
+    char_delay: Duration,
+    /// The delay for the message being typed, shorter than `char_delay` if
+    /// it would otherwise take longer than `max_message_time`
+    message_char_delay: Duration,
+    max_message_time: Option<Duration>,
+    natural_pacing: bool,
+    messages: Vec<Message>,
+    /// Lines scrolled back from the end of the conversation
+    scroll_back: usize,
+    /// Height of the output last time it was drawn, for paging
+    page_height: usize,
 }
 
 impl Typewriter {
-    pub fn new() -> Self {
+    pub fn new(clock: SharedClock, settings: &TypewriterSettings) -> Self {
+        let now = clock.now();
         Self {
+            clock,
             current_message_index: 0,
             visible_chars: 0,
-            last_char_time: Utc::now().timestamp_millis(),
-            char_delay_ms: 75,
-            messages: Vec::new(), // 50ms between chars (adjust for speed)
+            last_char_time: now,
+            char_delay: Duration::from_millis(settings.char_delay_ms),
+            message_char_delay: Duration::from_millis(settings.char_delay_ms),
+            max_message_time: (settings.max_message_secs > 0).then(|| Duration::from_secs(settings.max_message_secs)),
+            natural_pacing: settings.natural_pacing,
+            messages: Vec::new(),
+            scroll_back: 0,
+            page_height: 0,
         }
     }
 
     // Call this method in your main loop to update the typewriter effect
-    pub fn update_typewriter(&mut self) -> Option<State> {
+    pub fn update_typewriter(&mut self) -> Option<Trigger> {
         if self.messages.is_empty() {
-            return Some(State::IDLE)
+            return Some(Trigger::ReplyFinished)
         }
 
-        let current_time = Utc::now().timestamp_millis();
+        let current_time = self.clock.now();
+        let current_message = &self.messages[self.current_message_index].text;
+        // The last character shown, if any, then the ones still to come
+        let mut chars = current_message.chars().skip(self.visible_chars.saturating_sub(1)).peekable();
+        let mut previous = if self.visible_chars > 0 { chars.next() } else { None };
+        let mut shown = 0;
 
-        // Check if enough time has passed to show next character
-        if current_time - self.last_char_time >= self.char_delay_ms {
-            let current_message = &self.messages[self.current_message_index];
-            //println!("Typewriter: visible_chars={}, total_chars={}",
-              //  self.visible_chars,
-                //current_message.chars().count();
-
-            if self.visible_chars < current_message.chars().count() {
-                // Show next character
-                self.visible_chars += 1;
-                self.last_char_time = current_time;
-                return Some(State::TALKING)
-            } else {
-                return Some(State::IDLE)
+        // Several characters a frame when the delay is shorter than a frame
+        while let Some(&next) = chars.peek() {
+            let wait = self.message_char_delay * pause(self.natural_pacing, previous, Some(next));
+            if current_time.saturating_sub(self.last_char_time) < wait {
+                break;
             }
+            self.last_char_time += wait;
+            previous = chars.next();
+            shown += 1;
         }
 
-        None
+        let finished = chars.peek().is_none();
+        self.visible_chars += shown;
+        match (shown > 0, finished) {
+            (true, _) => Some(Trigger::ReplyStarted),
+            (false, true) => Some(Trigger::ReplyFinished),
+            (false, false) => None,
+        }
+    }
+
+    // Speeds up the character delay for the current message if typing out
+    // what is left of it would take longer than allowed.
+    fn pace_message(&mut self) {
+        self.message_char_delay = self.char_delay;
+        let (Some(max_time), Some(message)) = (self.max_message_time, self.messages.get(self.current_message_index)) else {
+            return;
+        };
+        let mut chars = message.text.chars().skip(self.visible_chars.saturating_sub(1));
+        let mut previous = if self.visible_chars > 0 { chars.next() } else { None };
+        let mut delays: u32 = 0;
+        for c in chars {
+            delays = delays.saturating_add(pause(self.natural_pacing, previous, Some(c)));
+            previous = Some(c);
+        }
+        if delays > 0 && self.char_delay * delays > max_time {
+            self.message_char_delay = max_time / delays;
+        }
+    }
+
+    /// Shows the rest of the message being typed at once.
+    pub fn finish_message(&mut self) {
+        if let Some(message) = self.messages.get(self.current_message_index) {
+            self.visible_chars = message.text.chars().count();
+        }
+    }
+
+    /// The character revealed most recently, which is what the avatar is "saying".
+    pub fn current_char(&self) -> Option<char> {
+        let message = self.messages.get(self.current_message_index)?;
+        message.text.chars().nth(self.visible_chars.checked_sub(1)?)
     }
 
     fn start_new_message(&mut self) {
         if !self.messages.is_empty() {
             self.current_message_index = self.messages.len() - 1;
             self.visible_chars = 0;
-            self.last_char_time = Utc::now().timestamp_millis();
+            self.last_char_time = self.clock.now();
+            self.pace_message();
         }
     }
 
-    pub fn output_canvas(&mut self) -> impl Widget + '_ {
+    /// Draws the whole conversation, scrolled so the newest text is in view.
+    pub fn render_output(&mut self, area: Rect, frame: &mut Frame, theme: &Theme, focused: bool) {
         let block = Block::bordered()
-            .title(" Output ")
+            .title(if self.scroll_back > 0 { " Output (scrolled back) " } else { " Output " })
             .title_alignment(Alignment::Left)
-            .style(Style::default().fg(Color::Rgb(0, 0, 255)).bg(Color::White));
+            .style(theme.text)
+            .border_style(if focused { theme.accent } else { theme.border });
+        let inner = block.inner(area);
 
-        let display_text = if self.messages.is_empty() {
-            String::new()
-        } else {
-            let current_message = &self.messages[self.current_message_index];
-            current_message.chars().take(self.visible_chars).collect::<String>()
-        };
+        let mut lines = Vec::new();
+        // Code blocks in replies are numbered through the conversation for `/copy code`
+        let mut code_blocks = 0;
+        for (index, message) in self.messages.iter().enumerate().take(self.current_message_index + 1) {
+            let label = theme.role(message.role);
+            lines.push(Line::from(Span::styled(format!("{}:", message.role.label()), label)));
+            let text: String = if index == self.current_message_index {
+                message.text.chars().take(self.visible_chars).collect()
+            } else {
+                message.text.clone()
+            };
+            let starts: Vec<usize> = match message.role {
+                Role::Assistant => clipboard::code_blocks(&text).into_iter().map(|block| block.line).collect(),
+                _ => Vec::new(),
+            };
+            for (number, line) in text.lines().enumerate() {
+                let mut line = Line::from(line.to_string());
+                if starts.contains(&number) {
+                    code_blocks += 1;
+                    line.spans.push(Span::styled(code_label(code_blocks), theme.key));
+                }
+                lines.push(line);
+            }
+            lines.push(Line::default());
+        }
 
-        Paragraph::new(display_text)
-            .block(block)
-            .wrap(Wrap { trim: false })
+        let paragraph = Paragraph::new(lines).wrap(Wrap { trim: false });
+        let bottom = paragraph.line_count(inner.width).saturating_sub(inner.height as usize);
+        self.page_height = inner.height as usize;
+        self.scroll_back = self.scroll_back.min(bottom);
+        let scroll = bottom - self.scroll_back;
+        frame.render_widget(paragraph.block(block).scroll((scroll.min(u16::MAX as usize) as u16, 0)), area);
     }
 
-    pub fn add_message(&mut self, message: String) {
-        self.messages.push(message);
+    /// Adds a message that is typed out a character at a time.
+    pub fn add_message(&mut self, role: Role, text: String) {
+        self.messages.push(Message { role, text });
+        self.scroll_back = 0;
         self.start_new_message();
     }
-}
\ No newline at end of file
+
+    /// Adds a message that appears all at once, finishing any message still being typed.
+    pub fn show_message(&mut self, role: Role, text: String) {
+        self.visible_chars = text.chars().count();
+        self.messages.push(Message { role, text });
+        self.current_message_index = self.messages.len() - 1;
+        self.scroll_back = 0;
+    }
+
+    /// Pages back through the conversation, keeping a line of overlap.
+    pub fn scroll_up(&mut self) {
+        self.scroll_back += self.page_height.saturating_sub(1).max(1);
+    }
+
+    /// Scrolls back by `lines`, or forward for a negative number.
+    pub fn scroll_by(&mut self, lines: isize) {
+        self.scroll_back = self.scroll_back.saturating_add_signed(lines);
+    }
+
+    /// Pages forward again, back to following new text at the end.
+    pub fn scroll_down(&mut self) {
+        self.scroll_back = self.scroll_back.saturating_sub(self.page_height.saturating_sub(1).max(1));
+    }
+
+    pub fn messages(&self) -> &[Message] {
+        &self.messages
+    }
+
+    pub fn clear(&mut self) {
+        self.messages.clear();
+        self.current_message_index = 0;
+        self.visible_chars = 0;
+        self.scroll_back = 0;
+    }
+
+    pub fn char_delay(&self) -> Duration {
+        self.char_delay
+    }
+
+    /// The delay the message being typed actually goes at, which is shorter
+    /// than `char_delay` for a long one.
+    pub fn typing_delay(&self) -> Duration {
+        let typing = self.messages.get(self.current_message_index)
+            .is_some_and(|message| self.visible_chars < message.text.chars().count());
+        if typing { self.message_char_delay } else { self.char_delay }
+    }
+
+    pub fn set_char_delay(&mut self, delay: Duration) {
+        self.char_delay = delay.clamp(MIN_CHAR_DELAY, MAX_CHAR_DELAY);
+        self.pace_message();
+    }
+}
+
+// Shown after the opening fence of the nth code block in the conversation.
+fn code_label(number: usize) -> String {
+    format!("  [code {number}]")
+}
+
+/// `row`, a row of the conversation as drawn, without the code block label it may end with.
+pub fn without_code_label(row: &str) -> &str {
+    let Some(start) = row.rfind("[code ") else {
+        return row;
+    };
+    let number = row[start + "[code ".len()..].strip_suffix(']').unwrap_or_default();
+    if number.is_empty() || !number.chars().all(|c| c.is_ascii_digit()) {
+        return row;
+    }
+    match row[..start].strip_suffix("  ") {
+        Some(before) => before,
+        // Wrapped onto a row of its own
+        None if row[..start].trim().is_empty() => "",
+        None => row,
+    }
+}
+
+// How many character delays to wait between `previous` and `next`: longer
+// after the end of a sentence, a clause or a line when pacing naturally.
+// Full stops only count before a space, so `3.14` and `e.g.` run on.
+fn pause(natural: bool, previous: Option<char>, next: Option<char>) -> u32 {
+    if !natural {
+        return 1;
+    }
+    let spaced = next.is_none_or(char::is_whitespace);
+    match previous {
+        Some('\n') => 4,
+        Some('.' | '!' | '?') if spaced => 6,
+        Some(',' | ';' | ':') if spaced => 3,
+        _ => 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::clock::ManualClock;
+
+    fn typewriter(clock: &Rc<ManualClock>, max_message_secs: u64, natural_pacing: bool) -> Typewriter {
+        let settings = TypewriterSettings { char_delay_ms: 100, max_message_secs, natural_pacing };
+        Typewriter::new(clock.clone(), &settings)
+    }
+
+    // A message of `count` different characters, so the one shown last says how many are
+    fn numbered(count: u32) -> String {
+        (0..count).map(|number| char::from_u32(0x4e00 + number).unwrap()).collect()
+    }
+
+    fn shown_after(typewriter: &mut Typewriter, clock: &ManualClock, time: Duration) -> u32 {
+        clock.advance(time);
+        typewriter.update_typewriter();
+        typewriter.current_char().map_or(0, |c| c as u32 - 0x4e00 + 1)
+    }
+
+    #[test]
+    fn code_labels_are_left_out_of_copied_rows() {
+        assert_eq!(without_code_label(&format!("```python{}", code_label(12))), "```python");
+        assert_eq!(without_code_label(code_label(3).trim_start()), "");
+        for row in ["print(xs[code 1])", "see [code x]", "plain text", "```"] {
+            assert_eq!(without_code_label(row), row);
+        }
+    }
+
+    #[test]
+    fn types_as_many_characters_as_the_clock_has_moved_on() {
+        let clock = ManualClock::new();
+        let mut typewriter = typewriter(&clock, 0, false);
+        typewriter.add_message(Role::Assistant, "abcdef".to_string());
+        assert_eq!(typewriter.update_typewriter(), None);
+        assert_eq!(typewriter.current_char(), None);
+
+        // However the time is split into frames, the same text is shown
+        clock.advance(Duration::from_millis(300));
+        assert_eq!(typewriter.update_typewriter(), Some(Trigger::ReplyStarted));
+        assert_eq!(typewriter.current_char(), Some('c'));
+        clock.advance(Duration::from_millis(150));
+        typewriter.update_typewriter();
+        clock.advance(Duration::from_millis(50));
+        typewriter.update_typewriter();
+        assert_eq!(typewriter.current_char(), Some('e'));
+    }
+
+    #[test]
+    fn pauses_after_sentences_when_pacing_naturally() {
+        let clock = ManualClock::new();
+        let mut natural = typewriter(&clock, 0, true);
+        natural.add_message(Role::Assistant, "Hi. Pi is 3.14".to_string());
+        // Six delays after the full stop, but none inside 3.14
+        clock.advance(Duration::from_millis(800));
+        natural.update_typewriter();
+        assert_eq!(natural.current_char(), Some('.'));
+        clock.advance(Duration::from_millis(100));
+        natural.update_typewriter();
+        assert_eq!(natural.current_char(), Some(' '));
+        clock.advance(Duration::from_millis(1000));
+        natural.update_typewriter();
+        assert_eq!(natural.current_char(), Some('4'));
+        assert_eq!(natural.update_typewriter(), Some(Trigger::ReplyFinished));
+    }
+
+    #[test]
+    fn caps_how_long_a_message_takes() {
+        // 1000 characters at 100ms would take 100 seconds
+        let clock = ManualClock::new();
+        let mut capped = typewriter(&clock, 10, true);
+        capped.add_message(Role::Assistant, numbered(1000));
+        assert_eq!(shown_after(&mut capped, &clock, Duration::from_secs(5)), 500);
+        assert_eq!(capped.typing_delay(), Duration::from_millis(10));
+        capped.finish_message();
+        assert_eq!(shown_after(&mut capped, &clock, Duration::ZERO), 1000);
+        assert_eq!(capped.typing_delay(), Duration::from_millis(100));
+    }
+
+    #[test]
+    fn a_new_speed_paces_what_is_left_of_the_message() {
+        let clock = ManualClock::new();
+        let mut capped = typewriter(&clock, 10, false);
+        capped.add_message(Role::Assistant, numbered(1000));
+        assert_eq!(shown_after(&mut capped, &clock, Duration::from_secs(5)), 500);
+
+        // The other 500 characters get the full 10 seconds to themselves
+        capped.set_char_delay(Duration::from_millis(100));
+        assert_eq!(capped.typing_delay(), Duration::from_millis(20));
+        assert_eq!(shown_after(&mut capped, &clock, Duration::from_secs(5)), 750);
+    }
+}
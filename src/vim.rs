@@ -0,0 +1,779 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use serde::{Deserialize, Serialize};
+
+use crate::editor::Editor;
+
+/// Counts stop growing here, which is more than any prompt needs.
+const MAX_COUNT: usize = 10_000;
+
+/// A put with a count inserts at most this many characters.
+const MAX_PUT: usize = 100_000;
+
+/// Which keys edit the input box.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum KeyMode {
+    #[default]
+    Standard,
+    Vim,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VimMode {
+    Normal,
+    Insert,
+    Visual,
+}
+
+impl VimMode {
+    pub fn label(self) -> &'static str {
+        match self {
+            VimMode::Normal => "NORMAL",
+            VimMode::Insert => "INSERT",
+            VimMode::Visual => "VISUAL",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operator {
+    Delete,
+    Change,
+    Yank,
+}
+
+// f, F, t and T: which way to look and whether to stop just short of the character.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Find {
+    forward: bool,
+    till: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Pending {
+    Nothing,
+    Find(Find),
+    Object { inner: bool },
+}
+
+/// Where a motion lands and how much of the text an operator on it covers.
+struct Motion {
+    target: usize,
+    inclusive: bool,
+    linewise: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CharClass {
+    Blank,
+    Word,
+    Punctuation,
+}
+
+fn class(c: char) -> CharClass {
+    if c.is_whitespace() {
+        CharClass::Blank
+    } else if c.is_alphanumeric() || c == '_' {
+        CharClass::Word
+    } else {
+        CharClass::Punctuation
+    }
+}
+
+/// Modal Vim-style editing on top of `Editor`, enabled with `ui.keymode = "vim"`.
+///
+/// Motions are worked out on the text as a flat list of characters, with line
+/// breaks included, and then handed to the editor as offsets.
+pub struct Vim {
+    mode: VimMode,
+    count: Option<usize>,
+    operator: Option<(Operator, usize)>,
+    pending: Pending,
+    last_find: Option<(Find, char)>,
+    /// Where the selection started in visual mode.
+    anchor: usize,
+    /// The unnamed register, and whether it holds whole lines.
+    register: String,
+    linewise: bool,
+}
+
+impl Vim {
+    pub fn new() -> Self {
+        Self {
+            mode: VimMode::Insert,
+            count: None,
+            operator: None,
+            pending: Pending::Nothing,
+            last_find: None,
+            anchor: 0,
+            register: String::new(),
+            linewise: false,
+        }
+    }
+
+    pub fn mode(&self) -> VimMode {
+        self.mode
+    }
+
+    /// Starts over in insert mode, e.g. when the input box gains focus.
+    pub fn reset(&mut self) {
+        self.mode = VimMode::Insert;
+        self.clear_pending();
+    }
+
+    /// The selected characters in visual mode, as a range of offsets.
+    pub fn selection(&self, editor: &Editor) -> Option<(usize, usize)> {
+        if self.mode != VimMode::Visual {
+            return None;
+        }
+        let cursor = editor.cursor_offset();
+        Some((self.anchor.min(cursor), self.anchor.max(cursor) + 1))
+    }
+
+    /// Handles `key`, returning false if it should get the ordinary editing behaviour instead.
+    pub fn handle_key(&mut self, key: KeyEvent, editor: &mut Editor) -> bool {
+        if self.mode == VimMode::Insert {
+            if key.code != KeyCode::Esc {
+                return false;
+            }
+            self.mode = VimMode::Normal;
+            let chars = chars(editor);
+            let cursor = editor.cursor_offset();
+            if cursor > line_start(&chars, cursor) {
+                editor.set_cursor_offset(cursor - 1);
+            }
+            return true;
+        }
+
+        if key.modifiers.contains(KeyModifiers::CONTROL) {
+            if key.code == KeyCode::Char('r') && self.mode == VimMode::Normal {
+                editor.redo();
+                self.clamp(editor);
+                return true;
+            }
+            return false;
+        }
+
+        let c = match key.code {
+            KeyCode::Char(c) => c,
+            KeyCode::Backspace => 'h',
+            KeyCode::Delete => 'x',
+            KeyCode::Esc if self.is_pending() => {
+                self.clear_pending();
+                return true;
+            },
+            KeyCode::Esc if self.mode == VimMode::Visual => {
+                self.mode = VimMode::Normal;
+                self.clamp(editor);
+                return true;
+            },
+            _ => {
+                self.clear_pending();
+                return false;
+            },
+        };
+        self.handle_char(c, editor);
+        true
+    }
+
+    fn is_pending(&self) -> bool {
+        self.count.is_some() || self.operator.is_some() || self.pending != Pending::Nothing
+    }
+
+    fn clear_pending(&mut self) {
+        self.count = None;
+        self.operator = None;
+        self.pending = Pending::Nothing;
+    }
+
+    // The count typed before the command, multiplied by any count typed before the operator.
+    fn take_count(&mut self) -> usize {
+        let count = self.count.take().unwrap_or(1);
+        self.operator.map_or(count, |(_, op_count)| (count * op_count).min(MAX_COUNT))
+    }
+
+    fn handle_char(&mut self, c: char, editor: &mut Editor) {
+        match self.pending {
+            Pending::Find(find) => {
+                self.pending = Pending::Nothing;
+                self.last_find = Some((find, c));
+                let count = self.take_count();
+                let motion = find_char(&chars(editor), editor.cursor_offset(), find, c, count);
+                return self.apply_motion(motion, editor);
+            },
+            Pending::Object { inner } => {
+                self.pending = Pending::Nothing;
+                let chars = chars(editor);
+                let range = text_object(&chars, editor.cursor_offset(), c, inner);
+                return self.apply_range(range, editor);
+            },
+            Pending::Nothing => {},
+        }
+
+        match c {
+            '1'..='9' => return self.push_digit(c),
+            '0' if self.count.is_some() => return self.push_digit(c),
+            _ => {},
+        }
+
+        let chars = chars(editor);
+        let cursor = editor.cursor_offset();
+        let waiting = self.operator.is_some() || self.mode == VimMode::Visual;
+
+        let operator = match c {
+            'd' | 'x' if self.mode == VimMode::Visual => Some(Operator::Delete),
+            'd' => Some(Operator::Delete),
+            'c' => Some(Operator::Change),
+            'y' => Some(Operator::Yank),
+            _ => None,
+        };
+        if let Some(operator) = operator {
+            return self.start_operator(operator, editor);
+        }
+
+        match c {
+            'i' | 'a' if waiting => self.pending = Pending::Object { inner: c == 'i' },
+            'f' | 'F' | 't' | 'T' => {
+                self.pending = Pending::Find(Find { forward: c.is_lowercase(), till: c.eq_ignore_ascii_case(&'t') });
+            },
+            ';' | ',' => {
+                let count = self.take_count();
+                let motion = self.last_find.and_then(|(find, target)| {
+                    let find = Find { forward: find.forward == (c == ';'), ..find };
+                    find_char(&chars, cursor, find, target, count)
+                });
+                self.apply_motion(motion, editor);
+            },
+            'v' if self.mode == VimMode::Visual => {
+                self.mode = VimMode::Normal;
+                self.clamp(editor);
+            },
+            'v' => {
+                self.mode = VimMode::Visual;
+                self.anchor = cursor;
+            },
+            'o' if self.mode == VimMode::Visual => {
+                editor.set_cursor_offset(self.anchor);
+                self.anchor = cursor;
+            },
+            _ if self.mode == VimMode::Visual || self.operator.is_some() => {
+                let operating = self.operator.is_some();
+                let count = self.take_count();
+                let motion = motion(&chars, cursor, c, count, operating);
+                self.apply_motion(motion, editor);
+            },
+            _ => self.command(c, &chars, cursor, editor),
+        }
+    }
+
+    fn push_digit(&mut self, digit: char) {
+        let value = digit.to_digit(10).unwrap_or(0) as usize;
+        self.count = Some((self.count.unwrap_or(0) * 10 + value).min(MAX_COUNT));
+    }
+
+    fn start_operator(&mut self, operator: Operator, editor: &mut Editor) {
+        if self.mode == VimMode::Visual {
+            let range = self.selection(editor);
+            self.mode = VimMode::Normal;
+            return self.operate(operator, range.map(|(start, end)| (start, end, false)), editor);
+        }
+        match self.operator {
+            // dd, cc and yy work on whole lines.
+            Some((pending, _)) if pending == operator => {
+                let count = self.take_count();
+                let chars = chars(editor);
+                let cursor = editor.cursor_offset();
+                let last = (1..count).fold(cursor, |at, _| next_line_start(&chars, at).unwrap_or(at));
+                self.operate(operator, Some((cursor, last, true)), editor);
+            },
+            Some(_) => self.clear_pending(),
+            None => self.operator = Some((operator, self.count.take().unwrap_or(1))),
+        }
+    }
+
+    // Moves the cursor, or hands the covered text to the pending operator.
+    fn apply_motion(&mut self, motion: Option<Motion>, editor: &mut Editor) {
+        let Some(motion) = motion else {
+            return self.clear_pending();
+        };
+        let Some((operator, _)) = self.operator else {
+            editor.set_cursor_offset(motion.target);
+            return self.clamp(editor);
+        };
+        let cursor = editor.cursor_offset();
+        let range = if motion.linewise {
+            (cursor.min(motion.target), cursor.max(motion.target), true)
+        } else if motion.target < cursor {
+            (motion.target, cursor, false)
+        } else if motion.inclusive {
+            (cursor, motion.target + 1, false)
+        } else {
+            (cursor, motion.target, false)
+        };
+        self.operate(operator, Some(range), editor);
+    }
+
+    fn apply_range(&mut self, range: Option<(usize, usize)>, editor: &mut Editor) {
+        let Some((start, end)) = range else {
+            return self.clear_pending();
+        };
+        if self.mode == VimMode::Visual {
+            self.anchor = start;
+            editor.set_cursor_offset(end.max(start + 1) - 1);
+            return;
+        }
+        if let Some((operator, _)) = self.operator {
+            self.operate(operator, Some((start, end, false)), editor);
+        }
+    }
+
+    // `range` is a pair of offsets, or for linewise ranges any offset in the first and last lines.
+    fn operate(&mut self, operator: Operator, range: Option<(usize, usize, bool)>, editor: &mut Editor) {
+        self.clear_pending();
+        let Some((start, end, linewise)) = range else {
+            return;
+        };
+        let chars = chars(editor);
+        let (mut start, mut end) = (start.min(chars.len()), end.min(chars.len()));
+        if linewise {
+            start = line_start(&chars, start);
+            end = line_end(&chars, end);
+        }
+        self.register = chars[start..end].iter().collect();
+        self.linewise = linewise;
+
+        match operator {
+            Operator::Yank => editor.set_cursor_offset(start),
+            Operator::Delete => {
+                // Deleting whole lines takes one of the line breaks around them too.
+                if linewise && end < chars.len() {
+                    end += 1;
+                } else if linewise && start > 0 {
+                    start -= 1;
+                }
+                editor.delete_range(start, end);
+                if linewise {
+                    let chars = self::chars(editor);
+                    editor.set_cursor_offset(first_non_blank(&chars, editor.cursor_offset()));
+                }
+            },
+            Operator::Change => {
+                editor.delete_range(start, end);
+                self.mode = VimMode::Insert;
+                return;
+            },
+        }
+        self.mode = VimMode::Normal;
+        self.clamp(editor);
+    }
+
+    // Normal mode commands that are not operators or motions.
+    fn command(&mut self, c: char, chars: &[char], cursor: usize, editor: &mut Editor) {
+        let count = self.take_count();
+        let start = line_start(chars, cursor);
+        let end = line_end(chars, cursor);
+        match c {
+            'x' => self.operate(Operator::Delete, Some((cursor, (cursor + count).min(end), false)), editor),
+            'X' => self.operate(Operator::Delete, Some((cursor.saturating_sub(count).max(start), cursor, false)), editor),
+            'D' => self.operate(Operator::Delete, Some((cursor, end, false)), editor),
+            'C' => self.operate(Operator::Change, Some((cursor, end, false)), editor),
+            's' => self.operate(Operator::Change, Some((cursor, (cursor + count).min(end), false)), editor),
+            'S' => self.operate(Operator::Change, Some((start, end, false)), editor),
+            'Y' => self.operate(Operator::Yank, Some((cursor, cursor, true)), editor),
+            'p' | 'P' => self.put(c == 'p', count, chars, cursor, editor),
+            'u' => {
+                for _ in 0..count {
+                    if !editor.undo() {
+                        break;
+                    }
+                }
+                self.clamp(editor);
+            },
+            'i' => self.mode = VimMode::Insert,
+            'a' => {
+                editor.set_cursor_offset((cursor + 1).min(end));
+                self.mode = VimMode::Insert;
+            },
+            'I' => {
+                editor.set_cursor_offset(first_non_blank(chars, cursor));
+                self.mode = VimMode::Insert;
+            },
+            'A' => {
+                editor.set_cursor_offset(end);
+                self.mode = VimMode::Insert;
+            },
+            'o' => {
+                editor.set_cursor_offset(end);
+                editor.insert_newline();
+                self.mode = VimMode::Insert;
+            },
+            'O' => {
+                editor.set_cursor_offset(start);
+                editor.insert_newline();
+                editor.move_up();
+                self.mode = VimMode::Insert;
+            },
+            _ => {
+                if let Some(motion) = motion(chars, cursor, c, count, false) {
+                    editor.set_cursor_offset(motion.target);
+                    self.clamp(editor);
+                }
+            },
+        }
+    }
+
+    fn put(&mut self, after: bool, count: usize, chars: &[char], cursor: usize, editor: &mut Editor) {
+        if self.register.is_empty() {
+            return;
+        }
+        let count = count.min(MAX_PUT / (self.register.chars().count() + 1)).max(1);
+        if self.linewise {
+            let line = self.register.clone() + "\n";
+            let text = line.repeat(count);
+            if after {
+                editor.set_cursor_offset(line_end(chars, cursor));
+                editor.insert_str(&("\n".to_string() + text.trim_end_matches('\n')));
+            } else {
+                editor.set_cursor_offset(line_start(chars, cursor));
+                editor.insert_str(&text);
+            }
+            let chars = self::chars(editor);
+            let at = if after { next_line_start(chars.as_slice(), cursor).unwrap_or(cursor) } else { line_start(&chars, cursor) };
+            editor.set_cursor_offset(first_non_blank(&chars, at));
+            return;
+        }
+        let text = self.register.repeat(count);
+        let at = if after && cursor < line_end(chars, cursor) { cursor + 1 } else { cursor };
+        editor.set_cursor_offset(at);
+        editor.insert_str(&text);
+        editor.set_cursor_offset(at + text.chars().count() - 1);
+    }
+
+    // In normal mode the cursor sits on a character, never after the last one on a line.
+    fn clamp(&self, editor: &mut Editor) {
+        if self.mode == VimMode::Insert {
+            return;
+        }
+        let chars = chars(editor);
+        let cursor = editor.cursor_offset();
+        if cursor == line_end(&chars, cursor) && cursor > line_start(&chars, cursor) {
+            editor.set_cursor_offset(cursor - 1);
+        }
+    }
+}
+
+fn chars(editor: &Editor) -> Vec<char> {
+    editor.text().chars().collect()
+}
+
+fn line_start(chars: &[char], at: usize) -> usize {
+    chars[..at.min(chars.len())].iter().rposition(|&c| c == '\n').map_or(0, |i| i + 1)
+}
+
+// Offset of the line break ending the line containing `at`, or the end of the text.
+fn line_end(chars: &[char], at: usize) -> usize {
+    chars[at.min(chars.len())..].iter().position(|&c| c == '\n').map_or(chars.len(), |i| at + i)
+}
+
+fn next_line_start(chars: &[char], at: usize) -> Option<usize> {
+    let end = line_end(chars, at);
+    (end < chars.len()).then_some(end + 1)
+}
+
+fn first_non_blank(chars: &[char], at: usize) -> usize {
+    let start = line_start(chars, at);
+    let end = line_end(chars, at);
+    (start..end).find(|&i| !chars[i].is_whitespace()).unwrap_or(start)
+}
+
+// `operating` is set when the motion is the target of an operator rather than a cursor move.
+fn motion(chars: &[char], cursor: usize, c: char, count: usize, operating: bool) -> Option<Motion> {
+    let charwise = |target, inclusive| Some(Motion { target, inclusive, linewise: false });
+    let start = line_start(chars, cursor);
+    let end = line_end(chars, cursor);
+    match c {
+        'h' => charwise(cursor.saturating_sub(count).max(start), false),
+        'l' | ' ' => charwise((cursor + count).min(end), false),
+        '0' => charwise(start, false),
+        '^' => charwise(first_non_blank(chars, cursor), false),
+        '$' => {
+            let last = (1..count).fold(cursor, |at, _| next_line_start(chars, at).unwrap_or(at));
+            charwise(line_end(chars, last).saturating_sub(1).max(line_start(chars, last)), true)
+        },
+        'w' => {
+            let target = (0..count).fold(cursor, |at, _| word_forward(chars, at));
+            // An operator on the last word of a line stops at the line break.
+            charwise(if operating && target > end && cursor < end { end } else { target }, false)
+        },
+        'b' => charwise((0..count).fold(cursor, |at, _| word_backward(chars, at)), false),
+        'e' => charwise((0..count).fold(cursor, |at, _| word_end(chars, at)), true),
+        'j' | 'k' => {
+            let column = cursor - start;
+            let mut line = start;
+            for _ in 0..count {
+                line = if c == 'j' {
+                    next_line_start(chars, line)?
+                } else if line > 0 {
+                    line_start(chars, line - 1)
+                } else {
+                    return None;
+                };
+            }
+            let target = (line + column).min(line_end(chars, line));
+            Some(Motion { target, inclusive: false, linewise: true })
+        },
+        _ => None,
+    }
+}
+
+fn word_forward(chars: &[char], mut at: usize) -> usize {
+    if at >= chars.len() {
+        return chars.len();
+    }
+    let start_class = class(chars[at]);
+    if start_class != CharClass::Blank {
+        while at < chars.len() && class(chars[at]) == start_class {
+            at += 1;
+        }
+    }
+    while at < chars.len() && class(chars[at]) == CharClass::Blank {
+        at += 1;
+    }
+    at
+}
+
+fn word_backward(chars: &[char], mut at: usize) -> usize {
+    while at > 0 && class(chars[at - 1]) == CharClass::Blank {
+        at -= 1;
+    }
+    if at == 0 {
+        return 0;
+    }
+    let word_class = class(chars[at - 1]);
+    while at > 0 && class(chars[at - 1]) == word_class {
+        at -= 1;
+    }
+    at
+}
+
+fn word_end(chars: &[char], mut at: usize) -> usize {
+    at += 1;
+    while at < chars.len() && class(chars[at]) == CharClass::Blank {
+        at += 1;
+    }
+    if at >= chars.len() {
+        return chars.len().saturating_sub(1);
+    }
+    let word_class = class(chars[at]);
+    while at + 1 < chars.len() && class(chars[at + 1]) == word_class {
+        at += 1;
+    }
+    at
+}
+
+fn find_char(chars: &[char], cursor: usize, find: Find, target: char, count: usize) -> Option<Motion> {
+    let start = line_start(chars, cursor);
+    let end = line_end(chars, cursor);
+    let mut at = cursor;
+    for _ in 0..count {
+        at = if find.forward {
+            (at + 1..end).find(|&i| chars[i] == target)?
+        } else {
+            (start..at).rev().find(|&i| chars[i] == target)?
+        };
+    }
+    let target = match (find.till, find.forward) {
+        (true, true) => at - 1,
+        (true, false) => at + 1,
+        (false, _) => at,
+    };
+    Some(Motion { target, inclusive: find.forward, linewise: false })
+}
+
+// The range covered by `iw`, `aw`, `i"`, `a'` and friends.
+fn text_object(chars: &[char], cursor: usize, c: char, inner: bool) -> Option<(usize, usize)> {
+    let start = line_start(chars, cursor);
+    let end = line_end(chars, cursor);
+    if cursor >= end {
+        return None;
+    }
+    match c {
+        'w' | 'W' => {
+            let same = |i: usize| match c {
+                'W' => chars[i].is_whitespace() == chars[cursor].is_whitespace(),
+                _ => class(chars[i]) == class(chars[cursor]),
+            };
+            let mut first = cursor;
+            while first > start && same(first - 1) {
+                first -= 1;
+            }
+            let mut last = cursor + 1;
+            while last < end && same(last) {
+                last += 1;
+            }
+            if !inner {
+                // Take the blanks after the word, or before it if there are none.
+                let trailing = (last..end).take_while(|&i| chars[i].is_whitespace()).count();
+                if trailing > 0 {
+                    last += trailing;
+                } else {
+                    first -= (start..first).rev().take_while(|&i| chars[i].is_whitespace()).count();
+                }
+            }
+            Some((first, last))
+        },
+        '"' | '\'' | '`' => {
+            let quotes: Vec<usize> = (start..end).filter(|&i| chars[i] == c).collect();
+            let pair = quotes.chunks_exact(2)
+                .find(|pair| cursor <= pair[1])
+                .map(|pair| (pair[0], pair[1]))?;
+            Some(if inner { (pair.0 + 1, pair.1) } else { (pair.0, pair.1 + 1) })
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buffer(text: &str, cursor: usize) -> Editor {
+        let mut editor = Editor::new();
+        editor.set_text(text);
+        editor.set_cursor_offset(cursor);
+        editor
+    }
+
+    fn normal() -> Vim {
+        Vim { mode: VimMode::Normal, ..Vim::new() }
+    }
+
+    fn keys(vim: &mut Vim, editor: &mut Editor, keys: &str) {
+        for c in keys.chars() {
+            vim.handle_key(KeyEvent::from(KeyCode::Char(c)), editor);
+        }
+    }
+
+    #[test]
+    fn word_motions() {
+        let mut vim = normal();
+        let mut editor = buffer("foo.bar baz\nqux", 0);
+        keys(&mut vim, &mut editor, "w");
+        assert_eq!(editor.cursor_offset(), 3);
+        keys(&mut vim, &mut editor, "2w");
+        assert_eq!(editor.cursor_offset(), 8);
+        keys(&mut vim, &mut editor, "w");
+        assert_eq!(editor.cursor_offset(), 12);
+        keys(&mut vim, &mut editor, "b");
+        assert_eq!(editor.cursor_offset(), 8);
+        keys(&mut vim, &mut editor, "0e");
+        assert_eq!(editor.cursor_offset(), 2);
+        keys(&mut vim, &mut editor, "$");
+        assert_eq!(editor.cursor_offset(), 10);
+    }
+
+    #[test]
+    fn find_and_till() {
+        let mut vim = normal();
+        let mut editor = buffer("a,b,c,d", 0);
+        keys(&mut vim, &mut editor, "f,");
+        assert_eq!(editor.cursor_offset(), 1);
+        keys(&mut vim, &mut editor, ";");
+        assert_eq!(editor.cursor_offset(), 3);
+        keys(&mut vim, &mut editor, "0dt,");
+        assert_eq!(editor.text(), ",b,c,d");
+        keys(&mut vim, &mut editor, "2df,");
+        assert_eq!(editor.text(), "d");
+    }
+
+    #[test]
+    fn operators_take_counts() {
+        let mut vim = normal();
+        let mut editor = buffer("one two three four five", 0);
+        keys(&mut vim, &mut editor, "d2w");
+        assert_eq!(editor.text(), "three four five");
+        keys(&mut vim, &mut editor, "2dw");
+        assert_eq!(editor.text(), "five");
+        keys(&mut vim, &mut editor, "u");
+        assert_eq!(editor.text(), "three four five");
+    }
+
+    #[test]
+    fn huge_counts_are_capped() {
+        let mut vim = normal();
+        let mut editor = buffer("ab", 0);
+        keys(&mut vim, &mut editor, "yl99999999999999999999999p");
+        assert_eq!(editor.text().chars().count(), 2 + MAX_COUNT);
+        keys(&mut vim, &mut editor, "u");
+        assert_eq!(editor.text(), "ab");
+        keys(&mut vim, &mut editor, "99999d99999d");
+        assert_eq!(editor.text(), "");
+        keys(&mut vim, &mut editor, "99999999999u");
+
+        let mut editor = buffer(&"x".repeat(99), 0);
+        keys(&mut vim, &mut editor, "yy9999p");
+        assert!(editor.text().chars().count() <= 99 + MAX_PUT);
+    }
+
+    #[test]
+    fn dw_on_the_last_word_keeps_the_line_break() {
+        let mut vim = normal();
+        let mut editor = buffer("first\nsecond", 0);
+        keys(&mut vim, &mut editor, "dw");
+        assert_eq!(editor.text(), "\nsecond");
+    }
+
+    #[test]
+    fn change_word_enters_insert_mode() {
+        let mut vim = normal();
+        let mut editor = buffer("hello world", 0);
+        keys(&mut vim, &mut editor, "ce");
+        assert_eq!(editor.text(), " world");
+        assert_eq!(vim.mode(), VimMode::Insert);
+        assert!(!vim.handle_key(KeyEvent::from(KeyCode::Char('x')), &mut editor));
+        assert!(vim.handle_key(KeyEvent::from(KeyCode::Esc), &mut editor));
+        assert_eq!(vim.mode(), VimMode::Normal);
+    }
+
+    #[test]
+    fn yank_and_put_lines() {
+        let mut vim = normal();
+        let mut editor = buffer("a\nb\nc", 0);
+        keys(&mut vim, &mut editor, "yyjp");
+        assert_eq!(editor.text(), "a\nb\na\nc");
+        assert_eq!(editor.cursor_offset(), 4);
+        keys(&mut vim, &mut editor, "2dd");
+        assert_eq!(editor.text(), "a\nb");
+        keys(&mut vim, &mut editor, "kP");
+        assert_eq!(editor.text(), "a\nc\na\nb");
+    }
+
+    #[test]
+    fn text_objects() {
+        let mut vim = normal();
+        let mut editor = buffer("say \"hi there\" now", 7);
+        keys(&mut vim, &mut editor, "ci\"");
+        assert_eq!(editor.text(), "say \"\" now");
+
+        let mut vim = normal();
+        let mut editor = buffer("one two three", 5);
+        keys(&mut vim, &mut editor, "daw");
+        assert_eq!(editor.text(), "one three");
+        keys(&mut vim, &mut editor, "yiw$p");
+        assert_eq!(editor.text(), "one threethree");
+    }
+
+    #[test]
+    fn visual_selection() {
+        let mut vim = normal();
+        let mut editor = buffer("abcdef", 1);
+        keys(&mut vim, &mut editor, "vll");
+        assert_eq!(vim.selection(&editor), Some((1, 4)));
+        keys(&mut vim, &mut editor, "d");
+        assert_eq!(editor.text(), "aef");
+        assert_eq!(vim.mode(), VimMode::Normal);
+
+        keys(&mut vim, &mut editor, "0viwy");
+        assert_eq!(vim.register, "aef");
+    }
+}